@@ -5,7 +5,7 @@ use std::io::{Write, SeekFrom, Seek, Read};
 use std::path::PathBuf;
 
 use bencher::Bencher;
-use file_objects_rs::{FileSystem, FakeFileSystem};
+use file_objects_rs::{CachingFileSystem, DirEntry, FileSystem, FakeFileSystem};
 
 fn create_file_absolute(bench: &mut Bencher) {
     let fs = FakeFileSystem::new();
@@ -27,7 +27,7 @@ fn create_file_relative(bench: &mut Bencher) {
 
 fn create_file_deep_relative_path(bench: &mut Bencher) {
     let fs = FakeFileSystem::new();
-    let deep: PathBuf = std::iter::repeat("test").take(20).collect();
+    let deep: PathBuf = std::iter::repeat_n("test", 20).collect();
     fs.create_dir_all(&deep).unwrap();
     let path = deep.join("test.txt");
     bench.iter( || {
@@ -38,7 +38,7 @@ fn create_file_deep_relative_path(bench: &mut Bencher) {
 
 fn create_file_deep_absolute_path(bench: &mut Bencher) {
     let fs = FakeFileSystem::new();
-    let deep: PathBuf = std::iter::repeat("test").take(20).collect();
+    let deep: PathBuf = std::iter::repeat_n("test", 20).collect();
     let deep = fs.current_dir().unwrap().join(deep);
     fs.create_dir_all(&deep).unwrap();
     let path = deep.join("test.txt");
@@ -67,6 +67,33 @@ fn write_file(bench: &mut Bencher) {
     });
 }
 
+const LARGE_FILE_SIZE: usize = 10 * 1024 * 1024;
+const CHUNK_SIZE: usize = 4 * 1024;
+
+fn write_large_file(bench: &mut Bencher) {
+    let fs = FakeFileSystem::new();
+    let path = fs.current_dir().unwrap().join("hello.txt");
+    let chunk = [0x55; CHUNK_SIZE];
+    bench.iter( || {
+        let mut f = fs.create(&path).unwrap();
+        for _ in 0..LARGE_FILE_SIZE / CHUNK_SIZE {
+            f.write_all(&chunk).unwrap();
+        }
+    });
+}
+
+fn write_large_file_with_capacity(bench: &mut Bencher) {
+    let fs = FakeFileSystem::new();
+    let path = fs.current_dir().unwrap().join("hello.txt");
+    let chunk = [0x55; CHUNK_SIZE];
+    bench.iter( || {
+        let mut f = fs.create_with_capacity(&path, LARGE_FILE_SIZE).unwrap();
+        for _ in 0..LARGE_FILE_SIZE / CHUNK_SIZE {
+            f.write_all(&chunk).unwrap();
+        }
+    });
+}
+
 fn read_file(bench: &mut Bencher) {
     let fs = FakeFileSystem::new();
     let path = fs.current_dir().unwrap().join("hello.txt");
@@ -134,6 +161,33 @@ fn read_dir(bench: &mut Bencher) {
     });
 }
 
+const LARGE_DIR_ENTRIES: usize = 50_000;
+
+fn large_dir(fs: &FakeFileSystem) -> PathBuf {
+    let root = fs.current_dir().unwrap();
+    for id in 0..LARGE_DIR_ENTRIES {
+        fs.create(root.join(id.to_string())).unwrap();
+    }
+    root
+}
+
+fn read_dir_large(bench: &mut Bencher) {
+    let fs = FakeFileSystem::new();
+    let root = large_dir(&fs);
+    bench.iter( || {
+        let dir = fs.read_dir(&root).unwrap();
+        dir.map(|e| e.unwrap().file_name()).collect::<Vec<_>>()
+    });
+}
+
+fn read_dir_names_large(bench: &mut Bencher) {
+    let fs = FakeFileSystem::new();
+    let root = large_dir(&fs);
+    bench.iter( || {
+        fs.read_dir_names(&root).unwrap().len()
+    });
+}
+
 fn is_dir(bench: &mut Bencher) {
     let fs = FakeFileSystem::new();
     let root = fs.current_dir().unwrap();
@@ -144,6 +198,31 @@ fn is_dir(bench: &mut Bencher) {
     });
 }
 
+const CANONICALIZE_PATHS: usize = 1000;
+
+fn canonicalize_deep_paths<T: FileSystem>(fs: T, bench: &mut Bencher) {
+    let deep: PathBuf = std::iter::repeat_n("test", 20).collect();
+    let deep = fs.current_dir().unwrap().join(deep);
+    fs.create_dir_all(&deep).unwrap();
+    let paths: Vec<PathBuf> = (0..CANONICALIZE_PATHS).map(|id| deep.join(id.to_string())).collect();
+    for path in &paths {
+        fs.create(path).unwrap();
+    }
+    bench.iter( || {
+        for path in &paths {
+            fs.canonicalize(path).unwrap();
+        }
+    });
+}
+
+fn canonicalize_deep(bench: &mut Bencher) {
+    canonicalize_deep_paths(FakeFileSystem::new(), bench);
+}
+
+fn canonicalize_deep_caching(bench: &mut Bencher) {
+    canonicalize_deep_paths(CachingFileSystem::new(FakeFileSystem::new()), bench);
+}
+
 fn copy_file(bench: &mut Bencher) {
     let fs = FakeFileSystem::new();
     let root = fs.current_dir().unwrap();
@@ -167,6 +246,19 @@ fn rename_file(bench: &mut Bencher) {
     });
 }
 
+fn rename_deep_dir(bench: &mut Bencher) {
+    let fs = FakeFileSystem::new();
+    let root = fs.current_dir().unwrap();
+    let deep: PathBuf = std::iter::repeat_n("test", 20).collect();
+    fs.create_dir_all(root.join("dir1").join(&deep)).unwrap();
+    let path1 = root.join("dir1");
+    let path2 = root.join("dir2");
+    bench.iter( || {
+        fs.rename(&path1, &path2).unwrap();
+        fs.rename(&path2, &path1).unwrap();
+    });
+}
+
 benchmark_group!(benches,
     create_file_absolute,
     create_file_relative,
@@ -174,14 +266,21 @@ benchmark_group!(benches,
     create_file_deep_absolute_path,
     create_file_long_filename,
     write_file,
+    write_large_file,
+    write_large_file_with_capacity,
     read_file,
     seek_in_reader,
     create_dir_relative,
     create_dir_absolute,
     open_file_with_large_fs,
     read_dir,
+    read_dir_large,
+    read_dir_names_large,
     is_dir,
+    canonicalize_deep,
+    canonicalize_deep_caching,
     copy_file,
     rename_file,
+    rename_deep_dir,
 );
 benchmark_main!(benches);
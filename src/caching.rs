@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::Result;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{FileSystem, Metadata, NodeKind, OpenOptions};
+#[cfg(feature = "temp")]
+use super::TempFileSystem;
+
+/// A file system which remembers the results of [`canonicalize`].
+///
+/// A path is canonicalized by canonicalizing its parent first, so paths which share
+/// ancestors only look up what they do not have in common.
+///
+/// Removals, renames and permission changes made through this file system forget the
+/// results they may affect. Changes made any other way, for example through another
+/// process, are not noticed, and may leave stale results behind.
+///
+/// [`canonicalize`]: trait.FileSystem.html#tymethod.canonicalize
+#[derive(Clone, Debug, Default)]
+pub struct CachingFileSystem<T> {
+    inner: T,
+    canonical: Arc<Mutex<HashMap<PathBuf, PathBuf>>>,
+}
+
+impl<T: FileSystem> CachingFileSystem<T> {
+    pub fn new(inner: T) -> Self {
+        CachingFileSystem {
+            inner,
+            canonical: Arc::default(),
+        }
+    }
+
+    /// Returns the file system which does the actual work.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn absolute(&self, path: &Path) -> Result<PathBuf> {
+        if path.is_relative() {
+            Ok(self.inner.current_dir()?.join(path))
+        } else {
+            Ok(path.to_path_buf())
+        }
+    }
+
+    fn cached_canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        if let Some(canonical) = self.canonical.lock().unwrap().get(path) {
+            return Ok(canonical.clone());
+        }
+        let canonical = self
+            .canonicalize_component(path)
+            .or_else(|_| self.inner.canonicalize(path))?;
+        self.canonical.lock().unwrap().insert(path.to_path_buf(), canonical.clone());
+        Ok(canonical)
+    }
+
+    // Canonicalizes the last component of path on top of its canonicalized parent.
+    // Symbolic links and errors are left to the inner file system, so that
+    // they are handled exactly as it would.
+    fn canonicalize_component(&self, path: &Path) -> Result<PathBuf> {
+        let (parent, last) = match (path.parent(), path.components().next_back()) {
+            (Some(parent), Some(last)) => (parent, last),
+            _ => return self.inner.canonicalize(path),
+        };
+        let parent = self.cached_canonicalize(parent)?;
+        match last {
+            Component::Normal(name) => {
+                let candidate = parent.join(name);
+                if self.inner.symlink_metadata(&candidate)?.is_symlink() {
+                    self.inner.canonicalize(path)
+                } else {
+                    Ok(candidate)
+                }
+            }
+            Component::ParentDir if self.inner.symlink_metadata(&parent)?.is_dir() => {
+                Ok(parent.parent().map(Path::to_path_buf).unwrap_or(parent))
+            }
+            _ => self.inner.canonicalize(path),
+        }
+    }
+
+    // Forgets the canonical paths which a change to the node at path may affect:
+    // those of the node and its descendants, and all those which were not
+    // already canonical, as they may have been resolved through the node.
+    // Call this once the change is made, whether it succeeded or not, so that
+    // a concurrent canonicalize cannot cache what the change is about to undo.
+    fn invalidate(&self, path: &Path) {
+        let location = self.absolute(path).ok().and_then(|path| {
+            let name = path.file_name()?;
+            let parent = self.canonicalize(path.parent()?).ok()?;
+            Some(parent.join(name))
+        });
+        let mut canonical = self.canonical.lock().unwrap();
+        match location {
+            Some(location) => canonical.retain(|p, c| p == c && !c.starts_with(&location)),
+            None => canonical.clear(),
+        }
+    }
+
+    fn invalidate_all(&self) {
+        self.canonical.lock().unwrap().clear();
+    }
+}
+
+impl<T: FileSystem> FileSystem for CachingFileSystem<T> {
+    type DirEntry = T::DirEntry;
+    type ReadDir = T::ReadDir;
+    type WalkDir = T::WalkDir;
+    type File = T::File;
+    type Permissions = T::Permissions;
+    type Metadata = T::Metadata;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
+        self.inner.open(path)
+    }
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
+        self.inner.create(path)
+    }
+
+    fn create_with_capacity<P: AsRef<Path>>(&self, path: P, capacity: usize) -> Result<Self::File> {
+        self.inner.create_with_capacity(path, capacity)
+    }
+
+    fn open_with_options<P: AsRef<Path>>(&self, path: P, options: &OpenOptions) -> Result<Self::File> {
+        self.inner.open_with_options(path, options)
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Self::Permissions) -> Result<()> {
+        // the permissions of the target of a symbolic link change
+        let target = self.inner.canonicalize(&path);
+        let result = self.inner.set_permissions(path, perm);
+        match target {
+            Ok(target) => self.invalidate(&target),
+            Err(_) => self.invalidate_all(),
+        }
+        result
+    }
+
+    #[cfg(unix)]
+    fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.inner.chown(path, uid, gid)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<NodeKind> {
+        self.inner.file_type(path)
+    }
+
+    fn change_token<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        self.inner.change_token(path)
+    }
+
+    fn write_if_unchanged<P, C>(&self, path: P, expected_token: u64, contents: C) -> Result<bool>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        self.inner.write_if_unchanged(path, expected_token, contents)
+    }
+
+    fn write_batch(&self, writes: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+        self.inner.write_batch(writes)
+    }
+
+    fn set_len<P: AsRef<Path>>(&self, path: P, size: u64) -> Result<()> {
+        self.inner.set_len(path, size)
+    }
+
+    fn current_dir(&self) -> Result<PathBuf> {
+        self.inner.current_dir()
+    }
+
+    fn set_current_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.set_current_dir(path)
+    }
+
+    fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn try_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        self.inner.try_exists(path)
+    }
+
+    fn is_dir<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_dir(path)
+    }
+
+    fn is_file<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_file(path)
+    }
+
+    fn is_hidden<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        self.inner.is_hidden(path)
+    }
+
+    fn writable<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        self.inner.writable(path)
+    }
+
+    fn sync_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.sync_dir(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.create_dir(path)
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.create_dir_all(path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let result = self.inner.remove_dir(path.as_ref());
+        self.invalidate(path.as_ref());
+        result
+    }
+
+    fn remove_dir_and_empty_parents<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        // which parents are removed is not known up front
+        let result = self.inner.remove_dir_and_empty_parents(path);
+        self.invalidate_all();
+        result
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let result = self.inner.remove_dir_all(path.as_ref());
+        self.invalidate(path.as_ref());
+        result
+    }
+
+    fn remove_dir_all_report<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        let result = self.inner.remove_dir_all_report(path.as_ref());
+        self.invalidate(path.as_ref());
+        result
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadDir> {
+        self.inner.read_dir(path)
+    }
+
+    fn walk_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::WalkDir> {
+        self.inner.walk_dir(path)
+    }
+
+    fn find_duplicates<P: AsRef<Path>>(&self, root: P) -> Result<Vec<Vec<PathBuf>>> {
+        self.inner.find_duplicates(root)
+    }
+
+    fn read_dir_names<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(OsString, NodeKind)>> {
+        self.inner.read_dir_names(path)
+    }
+
+    fn dir_is_empty<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        self.inner.dir_is_empty(path)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let result = self.inner.remove_file(path.as_ref());
+        self.invalidate(path.as_ref());
+        result
+    }
+
+    fn copy_file<P, Q>(&self, from: P, to: Q) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.inner.copy_file(from, to)
+    }
+
+    fn rename<P, Q>(&self, from: P, to: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let result = self.inner.rename(from.as_ref(), to.as_ref());
+        self.invalidate(from.as_ref());
+        self.invalidate(to.as_ref());
+        result
+    }
+
+    fn rename_noreplace<P, Q>(&self, from: P, to: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let result = self.inner.rename_noreplace(from.as_ref(), to.as_ref());
+        self.invalidate(from.as_ref());
+        self.invalidate(to.as_ref());
+        result
+    }
+
+    fn hard_link<P, Q>(&self, src: P, dst: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.inner.hard_link(src, dst)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        let path = path.as_ref();
+        if path.as_os_str().is_empty() {
+            return self.inner.canonicalize(path);
+        }
+        self.cached_canonicalize(&self.absolute(path)?)
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.inner.read_link(path)
+    }
+
+    #[cfg(unix)]
+    fn symlink<P, Q>(&self, original: P, link: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.inner.symlink(original, link)
+    }
+}
+
+#[cfg(feature = "temp")]
+impl<T: FileSystem + TempFileSystem> TempFileSystem for CachingFileSystem<T> {
+    type TempDir = T::TempDir;
+
+    fn temp_dir<S: AsRef<str>>(&self, prefix: S) -> Result<Self::TempDir> {
+        self.inner.temp_dir(prefix)
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{ErrorKind, Result};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::registry::create_error;
+
+/// An operation for which [`FakeFileSystem::inject_error`] can make a path fail.
+///
+/// [`FakeFileSystem::inject_error`]: struct.FakeFileSystem.html#method.inject_error
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FsOp {
+    /// Opening an existing file, without creating it.
+    Open,
+    /// Opening a file with `create` or `create_new`, creating a symbolic or hard link.
+    Create,
+    /// Reading from an open file.
+    Read,
+    /// Writing to a file, or changing its length, through an open file or by path.
+    Write,
+    /// Getting the metadata or change token of a node.
+    Metadata,
+    /// Creating a directory.
+    CreateDir,
+    /// Listing or walking a directory.
+    ReadDir,
+    /// Removing a file or directory.
+    Remove,
+    /// Renaming a node, from or to the path.
+    Rename,
+    /// Copying a file, from or to the path.
+    Copy,
+    /// Changing the permissions or the owner of a node.
+    SetPermissions,
+}
+
+impl FsOp {
+    // Whether the operation changes the file system.
+    fn changes(self) -> bool {
+        match self {
+            FsOp::Open | FsOp::Read | FsOp::Metadata | FsOp::ReadDir => false,
+            FsOp::Create
+            | FsOp::Write
+            | FsOp::CreateDir
+            | FsOp::Remove
+            | FsOp::Rename
+            | FsOp::Copy
+            | FsOp::SetPermissions => true,
+        }
+    }
+}
+
+// The errors injected into a file system, shared by its clones and open files.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+    errors: Arc<Mutex<HashMap<(PathBuf, FsOp), ErrorKind>>>,
+    // the paths whose copies come out corrupted, without an error
+    corrupted: Arc<Mutex<HashSet<PathBuf>>>,
+    // whether every change fails, as on a read-only mount
+    readonly: Arc<AtomicBool>,
+    // the most normal components a path may have, if limited
+    max_path_depth: Arc<Mutex<Option<usize>>>,
+}
+
+impl Faults {
+    pub fn inject(&self, path: PathBuf, op: FsOp, kind: ErrorKind) {
+        self.errors.lock().unwrap().insert((path, op), kind);
+    }
+
+    pub fn corrupt(&self, path: PathBuf) {
+        self.corrupted.lock().unwrap().insert(path);
+    }
+
+    pub fn clear(&self) {
+        self.errors.lock().unwrap().clear();
+        self.corrupted.lock().unwrap().clear();
+    }
+
+    // Whether copies to path should come out corrupted.
+    pub fn corrupts(&self, path: &Path) -> bool {
+        self.corrupted.lock().unwrap().contains(path)
+    }
+
+    pub fn set_readonly(&self, readonly: bool) {
+        self.readonly.store(readonly, Ordering::Relaxed);
+    }
+
+    pub fn set_max_path_depth(&self, depth: Option<usize>) {
+        *self.max_path_depth.lock().unwrap() = depth;
+    }
+
+    // Fails if the file system is read-only.
+    pub fn check_writable(&self) -> Result<()> {
+        if self.readonly.load(Ordering::Relaxed) {
+            Err(create_error(ErrorKind::PermissionDenied))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Fails if path is too deep.
+    pub fn check_depth(&self, path: &Path) -> Result<()> {
+        if let Some(max_depth) = *self.max_path_depth.lock().unwrap() {
+            let depth = path.components().filter(|c| matches!(c, Component::Normal(_))).count();
+            if depth > max_depth {
+                return Err(create_error(ErrorKind::InvalidInput));
+            }
+        }
+        Ok(())
+    }
+
+    // Fails with the error injected for op on path, if any, if path is
+    // too deep, or if op changes a read-only file system.
+    pub fn check(&self, path: &Path, op: FsOp) -> Result<()> {
+        self.check_depth(path)?;
+        if op.changes() {
+            self.check_writable()?;
+        }
+        let errors = self.errors.lock().unwrap();
+        if errors.is_empty() {
+            return Ok(());
+        }
+        match errors.get(&(path.to_path_buf(), op)) {
+            Some(&kind) => Err(create_error(kind)),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,27 +1,35 @@
-use std::ffi::{OsStr, OsString};
+use std::ffi::OsString;
+use std::fs;
 use std::io::{self, Result, SeekFrom};
-use std::iter::Iterator;
+use std::iter::{FromIterator, Iterator};
 use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::vec::IntoIter;
-use std::cmp::min;
 use std::io::ErrorKind;
 use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
 use node::{SharedMode};
 use registry::create_error;
-use crate::OpenOptions;
+use crate::{has_hidden_name, OpenOptions};
 
-use super::{FileSystem, FileExt, Metadata, Permissions};
+use super::{FileSystem, FileExt, FileTimes, Metadata, NodeKind, Permissions};
 #[cfg(feature = "temp")]
 use super::{TempDir, TempFileSystem};
 
 #[cfg(feature = "temp")]
 pub use self::tempdir::FakeTempDir;
 
+use self::faults::Faults;
 use self::registry::Registry;
 
+pub use self::faults::FsOp;
+
+mod faults;
 mod node;
 mod registry;
+#[cfg(feature = "serde")]
+mod serialize;
 #[cfg(feature = "temp")]
 mod tempdir;
 
@@ -29,6 +37,18 @@ mod tempdir;
 #[derive(Clone, Debug, Default)]
 pub struct FakeFileSystem {
     registry: Arc<Mutex<Registry>>,
+    faults: Faults,
+    syncs: Arc<Syncs>,
+    // the current directory of this handle and its clones,
+    // if not the one shared by all handles, in the registry
+    cwd: Option<Arc<Mutex<PathBuf>>>,
+}
+
+// How many times files and directories were synced.
+#[derive(Debug, Default)]
+struct Syncs {
+    files: AtomicU64,
+    dirs: AtomicU64,
 }
 
 fn to_absolute_path<F>(mut path: Cow<'_, Path>, get_current_dir: F) -> Cow<'_, Path>
@@ -42,210 +62,780 @@ where F: FnOnce() -> Result<PathBuf> {
     path
 }
 
+// Path drops a trailing separator from its components,
+// so look at the raw path to tell whether there is one.
+fn has_trailing_separator(path: &Path) -> bool {
+    path.as_os_str()
+        .as_encoded_bytes()
+        .last()
+        .is_some_and(|&b| std::path::is_separator(char::from(b)))
+}
+
+// The mode of a node copied from disk, given the mode it would get by default.
+fn os_mode(metadata: &fs::Metadata, default_mode: u32) -> u32 {
+    #[cfg(unix)]
+    {
+        let _ = default_mode;
+        metadata.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        if metadata.permissions().readonly() {
+            default_mode & !0o222
+        } else {
+            default_mode
+        }
+    }
+}
+
+// Copies the children of the directory at os_dir on disk into fake_dir.
+fn load_dir(registry: &mut Registry, os_dir: &Path, fake_dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(os_dir)? {
+        let entry = entry?;
+        let os_path = entry.path();
+        let fake_path = fake_dir.join(entry.file_name());
+
+        if cfg!(unix) && entry.file_type()?.is_symlink() {
+            registry.create_symlink(&fs::read_link(&os_path)?, &fake_path)?;
+            continue;
+        }
+        let metadata = fs::metadata(&os_path)?;
+        if metadata.is_dir() {
+            registry.create_dir(&fake_path)?;
+            load_dir(registry, &os_path, &fake_path)?;
+            // only now, as the mode may not allow adding the children
+            registry.set_mode(&fake_path, os_mode(&metadata, node::Dir::DEFAULT_MODE))?;
+        } else if metadata.is_file() {
+            let mode = os_mode(&metadata, node::File::DEFAULT_MODE);
+            registry.create_file(&fake_path, &fs::read(&os_path)?, mode)?;
+        }
+        // other kinds of nodes, such as named pipes, have no counterpart here
+    }
+    Ok(())
+}
+
+// Gives the node at os_path on disk the permissions of a fake node with this mode.
+fn set_os_mode(os_path: &Path, mode: u32) -> Result<()> {
+    let mut permissions = fs::symlink_metadata(os_path)?.permissions();
+    #[cfg(unix)]
+    permissions.set_mode(mode);
+    #[cfg(not(unix))]
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(os_path, permissions)
+}
+
+// Copies a single node of the fake to os_path on disk.
+// The mode of a directory is left to the caller.
+fn dump_node(node: &node::Node, os_path: &Path, overwrite: bool) -> Result<()> {
+    if overwrite && !node.is_dir() && fs::symlink_metadata(os_path).is_ok_and(|m| !m.is_dir()) {
+        fs::remove_file(os_path)?;
+    }
+    match *node {
+        node::Node::Dir(_) => match fs::create_dir(os_path) {
+            Err(_) if overwrite && os_path.is_dir() => Ok(()),
+            result => result,
+        },
+        node::Node::File(ref file) => {
+            fs::write(os_path, &*file.contents.borrow())?;
+            set_os_mode(os_path, file.mode.get())
+        }
+        #[cfg(unix)]
+        node::Node::Symlink(ref link) => std::os::unix::fs::symlink(&link.target, os_path),
+        // there is no telling whether to create a file or a directory link
+        #[cfg(not(unix))]
+        node::Node::Symlink(_) => Ok(()),
+    }
+}
+
 impl FakeFileSystem {
     pub fn new() -> Self {
         let registry = Registry::new();
 
         FakeFileSystem {
             registry: Arc::new(Mutex::new(registry)),
+            faults: Faults::default(),
+            syncs: Arc::default(),
+            cwd: None,
+        }
+    }
+
+    /// Copies the directory tree at `root` on disk into a new file system,
+    /// with `root` as its root.
+    ///
+    /// Files keep their contents and modes, and directories their modes.
+    /// On unix, symbolic links are copied as links to the very same target,
+    /// elsewhere whatever they point to is copied instead.
+    pub fn load_from_os<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref();
+        let mut registry = Registry::new();
+        let fake_root = PathBuf::from(MAIN_SEPARATOR.to_string());
+
+        load_dir(&mut registry, root, &fake_root)?;
+        registry.set_mode(&fake_root, os_mode(&fs::metadata(root)?, node::Dir::DEFAULT_MODE))?;
+
+        Ok(FakeFileSystem {
+            registry: Arc::new(Mutex::new(registry)),
+            faults: Faults::default(),
+            syncs: Arc::default(),
+            cwd: None,
+        })
+    }
+
+    /// Returns the total length of the contents of all files, in bytes.
+    /// Hard links share their contents, which count only once.
+    ///
+    /// The contents of removed files which are still open are not counted.
+    pub fn total_bytes(&self) -> u64 {
+        self.registry.lock().unwrap().total_bytes()
+    }
+
+    /// Returns the number of nodes, including the root directory.
+    pub fn node_count(&self) -> usize {
+        self.registry.lock().unwrap().node_count()
+    }
+
+    /// Copies the whole file system to the directory `root` on disk, the inverse of
+    /// [`load_from_os`]. `root` is created if it does not exist yet.
+    ///
+    /// Files keep their contents and modes, and directories their modes. As the
+    /// fake never checks the search (x) bits of a directory, a directory gets them
+    /// wherever its mode has the read bits, so that its contents can be reached.
+    /// On unix, symbolic links are copied as links to the very same target,
+    /// elsewhere they are left out.
+    ///
+    /// This fails with [`io::ErrorKind::AlreadyExists`] if `root` is not empty,
+    /// unless `overwrite` is true. Nodes in the way are then replaced by the
+    /// copies, except for directories, which are merged.
+    ///
+    /// [`load_from_os`]: #method.load_from_os
+    /// [`io::ErrorKind::AlreadyExists`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.AlreadyExists
+    pub fn dump_to_os<P: AsRef<Path>>(&self, root: P, overwrite: bool) -> Result<()> {
+        let root = root.as_ref();
+        if !overwrite && fs::read_dir(root).is_ok_and(|mut entries| entries.next().is_some()) {
+            return Err(create_error(ErrorKind::AlreadyExists));
         }
+        fs::create_dir_all(root)?;
+
+        let registry = self.registry.lock().unwrap();
+        let fake_root = PathBuf::from(MAIN_SEPARATOR.to_string());
+        let fake_paths = registry.walk_dir(&fake_root)?;
+        let os_path = |fake_path: &Path| root.join(fake_path.strip_prefix(&fake_root).unwrap_or(fake_path));
+
+        // every directory comes before its descendants
+        for fake_path in &fake_paths {
+            dump_node(registry.get(fake_path)?, &os_path(fake_path), overwrite)?;
+        }
+        // only now, as the modes may not allow adding the children
+        for fake_path in fake_paths.iter().rev().chain(Some(&fake_root)) {
+            if let node::Node::Dir(ref dir) = *registry.get(fake_path)? {
+                let mode = dir.mode.get();
+                set_os_mode(&os_path(fake_path), mode | (mode & 0o444) >> 2)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the file system round every timestamp it records from now on down
+    /// to a multiple of `granularity`, like file systems with coarse timestamps do.
+    /// Two changes within the same interval then get the same timestamp.
+    ///
+    /// The default granularity, zero, keeps timestamps exact.
+    pub fn set_time_granularity(&self, granularity: Duration) {
+        self.registry.lock().unwrap().clock().set_granularity(granularity);
+    }
+
+    /// Returns how many times files were synced, with [`sync_all`] or [`sync_data`].
+    ///
+    /// [`sync_all`]: trait.FileExt.html#tymethod.sync_all
+    /// [`sync_data`]: trait.FileExt.html#tymethod.sync_data
+    pub fn file_syncs(&self) -> u64 {
+        self.syncs.files.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many times directories were synced, with [`sync_dir`].
+    ///
+    /// [`sync_dir`]: trait.FileSystem.html#tymethod.sync_dir
+    pub fn dir_syncs(&self) -> u64 {
+        self.syncs.dirs.load(Ordering::Relaxed)
+    }
+
+    /// Limits the total length of the contents of all files to `bytes`, as
+    /// counted by [`total_bytes`]. Writes, and changes of length, which would
+    /// go beyond it fail with `ErrorKind::StorageFull`, until removing or
+    /// truncating files frees enough space.
+    ///
+    /// By default, there is no limit. Files already beyond it are left alone.
+    ///
+    /// [`total_bytes`]: #method.total_bytes
+    pub fn set_capacity(&self, bytes: u64) {
+        self.registry.lock().unwrap().set_capacity(Some(bytes));
+    }
+
+    /// Makes names which differ only in case name the same node, or not, like
+    /// the default file systems of macOS and Windows. Nodes keep the case of
+    /// the name they were created with, which a [`rename`] to a name that only
+    /// differs in case changes.
+    ///
+    /// By default, case matters. Only names which are valid Unicode are compared
+    /// without case, and every lookup then has to look at all entries of each directory.
+    ///
+    /// [`rename`]: trait.FileSystem.html#tymethod.rename
+    pub fn set_case_insensitive(&self, case_insensitive: bool) {
+        self.registry.lock().unwrap().set_case_insensitive(case_insensitive);
+    }
+
+    /// Marks the node at `path` hidden or not, like the hidden attribute on Windows.
+    /// [`is_hidden`] then reports it as hidden on any platform.
+    /// A symbolic link is not followed.
+    ///
+    /// [`is_hidden`]: trait.FileSystem.html#tymethod.is_hidden
+    pub fn set_hidden<P: AsRef<Path>>(&self, path: P, hidden: bool) -> Result<()> {
+        self.apply_nofollow(path.as_ref(), |r, p| r.set_hidden(p, hidden))
+    }
+
+    /// Takes a copy of the whole file system: its nodes, their contents,
+    /// permissions and timestamps, and the current directory.
+    /// Changes made after taking the snapshot do not affect it.
+    pub fn snapshot(&self) -> FakeSnapshot {
+        FakeSnapshot(self.registry.lock().unwrap().deep_clone())
+    }
+
+    /// Puts the file system back in the state it was in when `snapshot` was taken.
+    /// This affects all clones of this file system.
+    ///
+    /// Files which are open keep referring to the files they were opened on,
+    /// which are no longer part of the file system.
+    pub fn restore(&self, snapshot: FakeSnapshot) {
+        *self.registry.lock().unwrap() = snapshot.0;
+    }
+
+    /// Makes `op` on the node at `path` fail with an error of the given `kind`
+    /// from now on, in this file system, its clones and the files they open.
+    /// The node does not need to exist yet.
+    ///
+    /// Symbolic links in `path` are resolved when the error is injected,
+    /// so the error is for the node which `path` leads to at that time.
+    pub fn inject_error<P: AsRef<Path>>(&self, path: P, op: FsOp, kind: ErrorKind) {
+        // a path caught in a loop of links fails before any injected error could
+        if let Ok(path) = self.apply(path.as_ref(), |_, p| Ok(p.to_path_buf())) {
+            self.faults.inject(path, op, kind);
+        }
+    }
+
+    /// Makes every copy to the file at `path` come out with its bytes
+    /// inverted from now on, in this file system and its clones, while the
+    /// copy itself still succeeds, as with a failing disk. This lets tests
+    /// check that corruption is caught, for example by [`copy_file_verified`].
+    ///
+    /// Symbolic links in `path` are resolved as for [`inject_error`].
+    ///
+    /// [`copy_file_verified`]: ../trait.FileSystem.html#method.copy_file_verified
+    /// [`inject_error`]: #method.inject_error
+    pub fn inject_corruption<P: AsRef<Path>>(&self, path: P) {
+        if let Ok(path) = self.apply(path.as_ref(), |_, p| Ok(p.to_path_buf())) {
+            self.faults.corrupt(path);
+        }
+    }
+
+    /// Removes all errors injected with [`inject_error`], and all corruption
+    /// injected with [`inject_corruption`].
+    ///
+    /// [`inject_error`]: #method.inject_error
+    /// [`inject_corruption`]: #method.inject_corruption
+    pub fn clear_errors(&self) {
+        self.faults.clear();
+    }
+
+    /// Makes every change to the file system fail with `PermissionDenied`
+    /// while `readonly` is true, as if it were mounted read-only.
+    /// This includes opening files for writing, and writing to files which
+    /// are already open. Reading files and their metadata keeps working.
+    ///
+    /// Unlike a read-only mode, this applies to all nodes, whatever their
+    /// permissions, in this file system, its clones and the files they open.
+    pub fn set_readonly_fs(&self, readonly: bool) {
+        self.faults.set_readonly(readonly);
+    }
+
+    /// Makes every operation on a path with more than `depth` components,
+    /// after resolving symbolic links, fail with `ErrorKind::InvalidInput`,
+    /// in this file system, its clones and the files they open.
+    /// [`exists`], [`is_dir`] and [`is_file`] return false for such a path.
+    /// This guards tests against pathological inputs, such as a path with
+    /// thousands of components.
+    ///
+    /// By default, and after passing `None`, there is no limit.
+    /// Nodes already beyond it stay, but cannot be reached while it holds.
+    ///
+    /// [`exists`]: ../trait.FileSystem.html#tymethod.exists
+    /// [`is_dir`]: ../trait.FileSystem.html#tymethod.is_dir
+    /// [`is_file`]: ../trait.FileSystem.html#tymethod.is_file
+    pub fn set_max_path_depth(&self, depth: Option<usize>) {
+        self.faults.set_max_path_depth(depth);
+    }
+
+    /// Returns a handle on the same file system, with a current directory of
+    /// its own, which starts out as the current directory of this handle.
+    /// [`set_current_dir`] on either handle then leaves the other one alone.
+    /// Clones of the new handle share its current directory.
+    ///
+    /// Otherwise, all clones of a `FakeFileSystem` share one current directory,
+    /// which snapshots record and restore. The `OsFileSystem` has no such option,
+    /// as its current directory is that of the whole process.
+    ///
+    /// [`set_current_dir`]: trait.FileSystem.html#tymethod.set_current_dir
+    pub fn with_independent_cwd(&self) -> Self {
+        let cwd = self
+            .current_dir()
+            .unwrap_or_else(|_| PathBuf::from(MAIN_SEPARATOR.to_string()));
+        FakeFileSystem {
+            cwd: Some(Arc::new(Mutex::new(cwd))),
+            ..self.clone()
+        }
+    }
+
+    fn current_dir_in(&self, registry: &Registry) -> Result<PathBuf> {
+        match self.cwd {
+            Some(ref cwd) => {
+                let cwd = cwd.lock().unwrap().clone();
+                registry.get_dir(&cwd).map(|_| cwd)
+            }
+            None => registry.current_dir(),
+        }
+    }
+
+    // Makes path absolute, and resolves any symbolic links in it,
+    // except for the final component if follow is false.
+    fn resolve_path(&self, registry: &Registry, path: &Path, follow: bool) -> Result<PathBuf> {
+        let path = to_absolute_path(Cow::from(path), || self.current_dir_in(registry));
+        registry.resolve(&path, follow)
+    }
+
+    fn apply<F, T>(&self, path: &Path, f: F) -> Result<T>
+    where
+        F: FnOnce(&MutexGuard<Registry>, &Path) -> Result<T>,
+    {
+        let registry = self.registry.lock().unwrap();
+        let path = self.resolve_path(&registry, path, true)?;
+
+        f(&registry, &path)
     }
 
-    fn apply<F, T>(&self, path: &Path, f: F) -> T
+    // Like apply(), but does not follow a symbolic link in the final component.
+    fn apply_nofollow<F, T>(&self, path: &Path, f: F) -> Result<T>
     where
-        F: FnOnce(&MutexGuard<Registry>, &Path) -> T,
+        F: FnOnce(&MutexGuard<Registry>, &Path) -> Result<T>,
     {
         let registry = self.registry.lock().unwrap();
-        let path = to_absolute_path(Cow::from(path), || registry.current_dir());
+        let path = self.resolve_path(&registry, path, false)?;
 
         f(&registry, &path)
     }
 
-    fn apply_mut<F, T>(&self, path: &Path, mut f: F) -> T
+    fn apply_mut<F, T>(&self, path: &Path, mut f: F) -> Result<T>
+    where
+        F: FnMut(&mut MutexGuard<Registry>, &Path) -> Result<T>,
+    {
+        let mut registry = self.registry.lock().unwrap();
+        let path = self.resolve_path(&registry, path, true)?;
+
+        f(&mut registry, &path)
+    }
+
+    // Like apply_mut(), but does not follow a symbolic link in the final component.
+    fn apply_mut_nofollow<F, T>(&self, path: &Path, mut f: F) -> Result<T>
     where
-        F: FnMut(&mut MutexGuard<Registry>, &Path) -> T,
+        F: FnMut(&mut MutexGuard<Registry>, &Path) -> Result<T>,
     {
         let mut registry = self.registry.lock().unwrap();
-        let path = to_absolute_path(Cow::from(path), || registry.current_dir());
+        let path = self.resolve_path(&registry, path, false)?;
 
         f(&mut registry, &path)
     }
 
-    fn apply_mut_from_to<F, T>(&self, from: &Path, to: &Path, mut f: F) -> T
+    fn apply_mut_from_to<F, T>(&self, from: &Path, to: &Path, follow: bool, mut f: F) -> Result<T>
     where
-        F: FnMut(&mut MutexGuard<Registry>, &Path, &Path) -> T,
+        F: FnMut(&mut MutexGuard<Registry>, &Path, &Path) -> Result<T>,
     {
         let mut registry = self.registry.lock().unwrap();
-        let from = to_absolute_path(Cow::from(from), || registry.current_dir());
-        let to   = to_absolute_path(Cow::from(to  ), || registry.current_dir());
+        let from = self.resolve_path(&registry, from, follow)?;
+        let to   = self.resolve_path(&registry, to,   follow)?;
 
         f(&mut registry, &from, &to)
     }
+}
 
-    // Opens an existing file as write-only.
-    // Does not modify the file on open.
-    fn open_writable<P: AsRef<Path>>(&self, path: P) -> Result<FakeOpenFile> {
-        self.apply(path.as_ref(), |r, p| {
-            r.get_file_if_writable(p)
-                .map(|f| FakeOpenFile::new(f, AccessMode::Write))
-        })
+/// The state of a [`FakeFileSystem`] at some point in time,
+/// see [`FakeFileSystem::snapshot`].
+///
+/// With the `serde` feature, a snapshot can be serialized, for example to keep
+/// it as a fixture. Only the nodes, their contents, modes and inode numbers,
+/// and the current directory are kept. Timestamps are not. Files which share
+/// an inode number come back as hard links to one file.
+///
+/// [`FakeFileSystem`]: struct.FakeFileSystem.html
+/// [`FakeFileSystem::snapshot`]: struct.FakeFileSystem.html#method.snapshot
+#[derive(Debug)]
+pub struct FakeSnapshot(Registry);
+
+impl Clone for FakeSnapshot {
+    // a copy must not share anything with the original either, or restoring
+    // one of them and changing the file system would change the other
+    fn clone(&self) -> Self {
+        FakeSnapshot(self.0.deep_clone())
     }
+}
 
-    // Creates a new file as write-only.
-    // Fails if the file already exists.
-    fn create_new<P: AsRef<Path>>(&self, path: P) -> Result<FakeOpenFile> {
-        self.apply_mut(path.as_ref(), |r, p| {
-            // make sure file does not exist
-            // careful, check presence in a way that works even if
-            // we have no access to the file.
-            if r.readonly(p).is_ok() {
-                return Err(io::Error::new(ErrorKind::AlreadyExists, "Already Exists"));
+/// Builds a file system holding the given files, creating their parent
+/// directories as needed. Relative paths are relative to the root.
+///
+/// # Panics
+///
+/// Panics if a file cannot be created, for example because one of its
+/// ancestors is also given as a file.
+impl<P, C> FromIterator<(P, C)> for FakeFileSystem
+where
+    P: AsRef<Path>,
+    C: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = (P, C)>>(files: I) -> Self {
+        let mut registry = Registry::new();
+        let root = PathBuf::from(MAIN_SEPARATOR.to_string());
+
+        for (path, contents) in files {
+            let path = root.join(path);
+            if let Some(parent) = path.parent() {
+                registry.create_dir_all(parent)
+                    .unwrap_or_else(|e| panic!("cannot create {}: {}", parent.display(), e));
             }
-            // create it
-            r.write_file(p, &[])?;
-            r.get_file_if_writable(p)
-                .map(|f| FakeOpenFile::new(f, AccessMode::Write))
-        })
-    }
+            registry.write_file(&path, contents.as_ref())
+                .unwrap_or_else(|e| panic!("cannot create {}: {}", path.display(), e));
+        }
 
-    // Opens an existing file as write-only.
-    // Truncates on open.
-    // Fails if the file does not exist.
-    fn overwrite<P: AsRef<Path>>(&self, path: P) -> Result<FakeOpenFile> {
-        self.apply(path.as_ref(), |r, p| {
-            // overwite file
-            // this ensure the file exists and we have
-            // write access.
-            r.overwrite_file(p, &[])?;
-            let f = r.get_file_if_writable(p)?;
-            Ok(FakeOpenFile::new(f, AccessMode::Write))
-        })
+        FakeFileSystem {
+            registry: Arc::new(Mutex::new(registry)),
+            faults: Faults::default(),
+            syncs: Arc::default(),
+            cwd: None,
+        }
     }
 }
 
 impl FileSystem for FakeFileSystem {
     type DirEntry = DirEntry;
     type ReadDir = ReadDir;
+    type WalkDir = WalkDir;
     type File = FakeOpenFile;
     type Permissions = FakePermissions;
     type Metadata = FakeMetadata;
 
     fn open<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
-        self.apply(path.as_ref(), |r, p|
-            r.get_file_if_readable(p)
-                .map(|f| FakeOpenFile::new(f, AccessMode::Read)))
+        self.open_with_options(path, &OpenOptions::new().read(true))
     }
 
     fn create<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
-        self.apply_mut(path.as_ref(), |r, p| {
-            r.write_file(p, &[])?;
-            let f = r.get_file_if_writable(p)?;
-            Ok(FakeOpenFile::new(f, AccessMode::Write))
-        })
+        let options = OpenOptions::new().create(true).truncate(true).write(true);
+        self.open_with_options(path, &options)
     }
 
-    fn open_with_options<P: AsRef<Path>>(&self, path: P, o: &OpenOptions) -> Result<Self::File> {
-
-        let o_create = OpenOptions::new().create(true).truncate(true).write(true);
-        let o_open = OpenOptions::new().read(true);
-        let o_open_writable = OpenOptions::new().write(true);
-        let o_create_new = OpenOptions::new().create_new(true).write(true);
-        let o_overwrite = OpenOptions::new().truncate(true).write(true);
+    fn create_with_capacity<P: AsRef<Path>>(&self, path: P, capacity: usize) -> Result<Self::File> {
+        let file = self.create(path)?;
+        file.f.contents.borrow_mut().reserve(capacity);
+        Ok(file)
+    }
 
-        match o {
-            o if *o == o_create         => self.create(path),
-            o if *o == o_open           => self.open(path),
-            o if *o == o_open_writable  => self.open_writable(path),
-            o if *o == o_create_new     => self.create_new(path),
-            o if *o == o_overwrite      => self.overwrite(path),
-             _ => Err(io::Error::new(ErrorKind::InvalidInput,
-                        format!("FakeFileSystem: Unsupported {:?}", o))),
+    fn open_with_options<P: AsRef<Path>>(&self, path: P, o: &OpenOptions) -> Result<Self::File> {
+        let access_mode = AccessMode::from_options(o)?;
+        // as on the OsFileSystem, no file stays open across an exec
+        if o.cloexec == Some(false) {
+            return Err(create_error(ErrorKind::Unsupported));
         }
+        let must_be_dir = has_trailing_separator(path.as_ref());
+        self.apply_mut(path.as_ref(), |r, p| {
+            // a path ending in a separator names a directory, which opening does not create
+            if must_be_dir {
+                match r.get(p) {
+                    Ok(node) if node.is_dir() => {}
+                    _ if o.create || o.create_new => return Err(create_error(ErrorKind::IsADirectory)),
+                    Ok(_) => return Err(create_error(ErrorKind::NotADirectory)),
+                    Err(_) => {}
+                }
+            }
+            // like O_EXCL, create_new does not follow a symbolic link, not even a dangling one
+            if o.create_new && r.get(&self.resolve_path(r, path.as_ref(), false)?).is_ok() {
+                return Err(create_error(ErrorKind::AlreadyExists));
+            }
+            let op = if o.create || o.create_new { FsOp::Create } else { FsOp::Open };
+            self.faults.check(p, op)?;
+            if access_mode.write {
+                self.faults.check_writable()?;
+            }
+            if o.create_parents && (o.create || o.create_new) {
+                if let Some(parent) = p.parent() {
+                    self.faults.check(parent, FsOp::CreateDir)?;
+                    r.create_dir_all(parent)?;
+                }
+            }
+            r.open_file(p, o)
+                .map(|f| FakeOpenFile::new(&f, access_mode, self, p))
+        })
     }
 
     #[cfg(unix)]
     fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Self::Permissions) -> Result<()>
     {
-        self.apply(path.as_ref(), |r, p| r.set_mode(p, perm.mode()))
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::SetPermissions)?;
+            r.set_mode(p, perm.mode())
+        })
     }
 
     #[cfg(not(unix))]
     fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Self::Permissions) -> Result<()>
     {
-        self.apply(path.as_ref(), |r, p| r.set_readonly(p, perm.readonly()))
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::SetPermissions)?;
+            r.set_readonly(p, perm.readonly())
+        })
     }
 
     fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
-        self.apply(path.as_ref(), |r, p|
-            if r.is_file(p) {
-                r.get_file(p).map(FakeMetadata::from)
-            } else {
-                r.get_dir(p).map(FakeMetadata::from)
-            }
-        )
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Metadata)?;
+            r.get(p).map(FakeMetadata::from)
+        })
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        self.apply_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Metadata)?;
+            r.get(p).map(FakeMetadata::from)
+        })
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<NodeKind> {
+        self.apply_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Metadata)?;
+            r.get(p).map(node::Node::kind)
+        })
     }
 
     fn current_dir(&self) -> Result<PathBuf> {
         let registry = self.registry.lock().unwrap();
-        registry.current_dir()
+        self.current_dir_in(&registry)
     }
 
     fn set_current_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        self.apply_mut(path.as_ref(), |r, p| r.set_current_dir(p.to_path_buf()))
+        match self.cwd {
+            Some(ref cwd) => self.apply(path.as_ref(), |r, p| {
+                self.faults.check_depth(p)?;
+                r.get_dir(p)?;
+                *cwd.lock().unwrap() = p.to_path_buf();
+                Ok(())
+            }),
+            None => self.apply_mut(path.as_ref(), |r, p| {
+                self.faults.check_depth(p)?;
+                r.set_current_dir(p.to_path_buf())
+            }),
+        }
+    }
+
+    fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.apply(path.as_ref(), |r, p| Ok(self.faults.check_depth(p).is_ok() && r.exists(p)))
+            .unwrap_or(false)
+    }
+
+    fn try_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check_depth(p)?;
+            r.try_exists(p)
+        })
     }
 
     fn is_dir<P: AsRef<Path>>(&self, path: P) -> bool {
-        self.apply(path.as_ref(), |r, p| r.is_dir(p))
+        self.apply(path.as_ref(), |r, p| Ok(self.faults.check_depth(p).is_ok() && r.is_dir(p)))
+            .unwrap_or(false)
     }
 
     fn is_file<P: AsRef<Path>>(&self, path: P) -> bool {
-        self.apply(path.as_ref(), |r, p| r.is_file(p))
+        self.apply(path.as_ref(), |r, p| Ok(self.faults.check_depth(p).is_ok() && r.is_file(p)))
+            .unwrap_or(false)
+    }
+
+    fn writable<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check_depth(p)?;
+            let writable = match r.get(p) {
+                Ok(node::Node::File(_)) => r.get_file_if_writable(p).is_ok(),
+                Ok(_) => false,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                    let parent = p.parent().unwrap_or(p);
+                    r.get(parent)?.is_dir() && r.get_dir_writable(parent).is_ok()
+                }
+                Err(e) => return Err(e),
+            };
+            // nothing can be written while the whole file system is read-only
+            Ok(writable && self.faults.check_writable().is_ok())
+        })
+    }
+
+    fn is_hidden<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref();
+        self.apply_nofollow(path, |r, p| {
+            self.faults.check_depth(p)?;
+            let hidden = r.get(p)?.hidden().get();
+            Ok(hidden || (cfg!(not(windows)) && has_hidden_name(path)))
+        })
+    }
+
+    #[cfg(unix)]
+    fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::SetPermissions)?;
+            r.chown(p, uid, gid)
+        })
+    }
+
+    fn sync_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check_depth(p)?;
+            match r.get(p)? {
+                node if node.is_dir() => Ok(()),
+                _ => Err(create_error(ErrorKind::NotADirectory)),
+            }
+        })?;
+        self.syncs.dirs.fetch_add(1, Ordering::Relaxed);
+        Ok(())
     }
 
     fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        self.apply_mut(path.as_ref(), |r, p| r.create_dir(p))
+        self.apply_mut_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::CreateDir)?;
+            r.create_dir(p)
+        })
     }
 
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        self.apply_mut(path.as_ref(), |r, p| r.create_dir_all(p))
+        self.apply_mut_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::CreateDir)?;
+            r.create_dir_all(p)
+        })
     }
 
     fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        self.apply_mut(path.as_ref(), |r, p| r.remove_dir(p))
+        self.apply_mut_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Remove)?;
+            r.remove_dir(p)
+        })
+    }
+
+    fn remove_dir_and_empty_parents<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.apply_mut_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Remove)?;
+            r.remove_dir_and_empty_parents(p)
+        })
     }
 
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        self.apply_mut(path.as_ref(), |r, p| r.remove_dir_all(p))
+        self.apply_mut_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Remove)?;
+            r.remove_dir_all(p)
+        })
+    }
+
+    fn remove_dir_all_report<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        self.apply_mut_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Remove)?;
+            r.remove_dir_all_report(p)
+        })
     }
 
     fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadDir> {
         let path = path.as_ref();
 
-        self.apply(path, |r, p| r.read_dir(p)).map(|entries| {
-            let entries = entries
-                .iter()
-                .map(|e| {
-                    let file_name = e.file_name().unwrap_or_else(|| e.as_os_str());
+        self.apply(path, |r, p| {
+            self.faults.check(p, FsOp::ReadDir)?;
+            r.read_dir(p)
+        }).map(|node_paths| ReadDir::new(path, node_paths, &self.registry))
+    }
+
+    fn walk_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::WalkDir> {
+        let path = path.as_ref();
+
+        self.apply(path, |r, p| {
+            self.faults.check(p, FsOp::ReadDir)?;
+            r.walk_dir(p).map(|nodes| (p.to_path_buf(), nodes))
+        })
+            .map(|(root, nodes)| {
+                let entries = nodes
+                    .into_iter()
+                    .map(|n| {
+                        // keep the path the caller passed in, as read_dir does
+                        let entry_path = path.join(n.strip_prefix(&root).unwrap_or(&n));
+
+                        Ok(DirEntry::new(entry_path, n.clone(), &self.registry))
+                    })
+                    .collect();
+
+                WalkDir::new(entries)
+            })
+    }
 
-                    Ok(DirEntry::new(path, &file_name))
+    fn find_duplicates<P: AsRef<Path>>(&self, root: P) -> Result<Vec<Vec<PathBuf>>> {
+        let root = root.as_ref();
+
+        self.apply(root, |r, p| {
+            self.faults.check_depth(p)?;
+            let groups = r.find_duplicates(p)?;
+            // keep the path the caller passed in, as walk_dir does
+            Ok(groups
+                .into_iter()
+                .map(|group| {
+                    group
+                        .iter()
+                        .map(|n| root.join(n.strip_prefix(p).unwrap_or(n)))
+                        .collect()
                 })
-                .collect();
+                .collect())
+        })
+    }
 
-            ReadDir::new(entries)
+    fn read_dir_names<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(OsString, NodeKind)>> {
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::ReadDir)?;
+            r.read_dir_names(p)
+        })
+    }
+
+    fn dir_is_empty<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::ReadDir)?;
+            r.dir_is_empty(p)
         })
     }
 
     fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        self.apply_mut(path.as_ref(), |r, p| r.remove_file(p))
+        self.apply_mut_nofollow(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Remove)?;
+            r.remove_file(p)
+        })
     }
 
-    fn copy_file<P, Q>(&self, from: P, to: Q) -> Result<()>
+    fn copy_file<P, Q>(&self, from: P, to: Q) -> Result<u64>
     where
         P: AsRef<Path>,
         Q: AsRef<Path>,
     {
-        self.apply_mut_from_to(from.as_ref(), to.as_ref(), |r, from, to| {
-            r.copy_file(from, to)
+        self.apply_mut_from_to(from.as_ref(), to.as_ref(), true, |r, from, to| {
+            self.faults.check(from, FsOp::Copy)?;
+            self.faults.check(to, FsOp::Copy)?;
+            let len = r.copy_file(from, to)?;
+            if self.faults.corrupts(to) {
+                for byte in r.get_file(to)?.contents.borrow_mut().iter_mut() {
+                    *byte = !*byte;
+                }
+            }
+            Ok(len)
         })
     }
 
@@ -254,7 +844,90 @@ impl FileSystem for FakeFileSystem {
         P: AsRef<Path>,
         Q: AsRef<Path>,
     {
-        self.apply_mut_from_to(from.as_ref(), to.as_ref(), |r, from, to| r.rename(from, to))
+        self.apply_mut_from_to(from.as_ref(), to.as_ref(), false, |r, from, to| {
+            self.faults.check(from, FsOp::Rename)?;
+            self.faults.check(to, FsOp::Rename)?;
+            r.rename(from, to)
+        })
+    }
+
+    fn rename_noreplace<P, Q>(&self, from: P, to: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.apply_mut_from_to(from.as_ref(), to.as_ref(), false, |r, from, to| {
+            self.faults.check(from, FsOp::Rename)?;
+            self.faults.check(to, FsOp::Rename)?;
+            r.rename_noreplace(from, to)
+        })
+    }
+
+    fn change_token<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        self.apply(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Metadata)?;
+            r.change_token(p)
+        })
+    }
+
+    fn write_if_unchanged<P, C>(&self, path: P, expected_token: u64, contents: C) -> Result<bool>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        self.apply_mut(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Write)?;
+            r.write_if_unchanged(p, expected_token, contents.as_ref())
+        })
+    }
+
+    fn write_batch(&self, writes: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+        let mut registry = self.registry.lock().unwrap();
+        let mut resolved = Vec::with_capacity(writes.len());
+        for (path, contents) in writes {
+            let path = self.resolve_path(&registry, path, true)?;
+            self.faults.check(&path, FsOp::Write)?;
+            resolved.push((path, contents.as_slice()));
+        }
+        registry.write_batch(&resolved)
+    }
+
+    fn set_len<P: AsRef<Path>>(&self, path: P, size: u64) -> Result<()> {
+        self.apply_mut(path.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Write)?;
+            r.set_len(p, size as usize)
+        })
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.apply_nofollow(path.as_ref(), |r, p| {
+            self.faults.check_depth(p)?;
+            r.read_link(p)
+        })
+    }
+
+    #[cfg(unix)]
+    fn symlink<P, Q>(&self, original: P, link: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let original = original.as_ref();
+        self.apply_mut_nofollow(link.as_ref(), |r, p| {
+            self.faults.check(p, FsOp::Create)?;
+            r.create_symlink(original, p)
+        })
+    }
+
+    fn hard_link<P, Q>(&self, src: P, dst: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.apply_mut_from_to(src.as_ref(), dst.as_ref(), false, |r, src, dst| {
+            self.faults.check(dst, FsOp::Create)?;
+            r.hard_link(src, dst)
+        })
     }
 
     fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
@@ -263,16 +936,49 @@ impl FileSystem for FakeFileSystem {
         if path.as_os_str().is_empty() {
             return Err(create_error(ErrorKind::NotFound));
         }
-        self.apply(path, |r, p| r.canonicalize_path(p))
+        self.apply(path, |r, p| {
+            self.faults.check_depth(p)?;
+            r.canonicalize_path(p)
+        })
     }
 }
 
 /// How a `fs::File` is accessed.
 ///
-#[derive(Debug, PartialEq)]
-enum AccessMode {
-    Read,
-    Write,
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AccessMode {
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+impl AccessMode {
+    // Validates the options the same way std does on unix, see
+    // std::sys::fs::unix::OpenOptions::get_access_mode() and
+    // get_creation_mode().
+    fn from_options(o: &OpenOptions) -> Result<Self> {
+        if !o.read && !o.write && !o.append {
+            return Err(create_error(ErrorKind::InvalidInput));
+        }
+        match (o.write, o.append) {
+            (true, false) => {}
+            (false, false) => {
+                if o.truncate || o.create || o.create_new {
+                    return Err(create_error(ErrorKind::InvalidInput));
+                }
+            }
+            (_, true) => {
+                if o.truncate && !o.create_new {
+                    return Err(create_error(ErrorKind::InvalidInput));
+                }
+            }
+        }
+        Ok(AccessMode {
+            read: o.read,
+            write: o.write || o.append,
+            append: o.append,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -281,41 +987,70 @@ pub struct FakeOpenFile {
     f: node::File,
     pos: usize,
     access_mode: AccessMode,
+    /// Version of the contents when the file was opened
+    opened_version: u64,
+    /// The file system the file was opened in, which limits how much it can grow,
+    /// counts syncs, and injects errors, the latter by the path it was opened at
+    fs: FakeFileSystem,
+    path: PathBuf,
 }
 
 impl FakeOpenFile {
-    fn new(file: &node::File, access_mode: AccessMode) -> Self {
+    fn new(file: &node::File, access_mode: AccessMode, fs: &FakeFileSystem, path: &Path) -> Self {
+        if access_mode.write {
+            file.writers.acquire();
+        }
         FakeOpenFile {
             f: file.clone(),
             pos: 0,
             access_mode,
+            opened_version: file.contents.version(),
+            fs: fs.clone(),
+            path: path.to_path_buf(),
         }
     }
-    fn verify_access(&self, access_mode: AccessMode) -> Result<()> {
-        if access_mode != self.access_mode {
-            Err(create_error(ErrorKind::Other))
+    fn verify_readable(&self) -> Result<()> {
+        self.fs.faults.check(&self.path, FsOp::Read)?;
+        if self.access_mode.read {
+            Ok(())
         } else {
+            Err(create_error(ErrorKind::PermissionDenied))
+        }
+    }
+    // Changes the contents with change, unless they cannot become as long
+    // as len says, given their length under the same lock.
+    fn resize<L, F, T>(&self, len: L, change: F) -> Result<T>
+    where
+        L: FnOnce(usize) -> usize,
+        F: FnOnce(&node::File) -> T,
+    {
+        let mut registry = self.fs.registry.lock().unwrap();
+        let len = len(self.f.contents.borrow().len());
+        registry.resize(&self.f, len, change)
+    }
+    fn verify_writable(&self) -> Result<()> {
+        self.fs.faults.check(&self.path, FsOp::Write)?;
+        if self.access_mode.write {
             Ok(())
+        } else {
+            Err(create_error(ErrorKind::PermissionDenied))
+        }
+    }
+}
+
+impl Drop for FakeOpenFile {
+    fn drop(&mut self) {
+        if self.access_mode.write {
+            self.f.writers.release();
         }
     }
 }
 
 impl io::Read for FakeOpenFile {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.verify_access(AccessMode::Read)?;
-        let contents = self.f.contents.borrow();
-        let pos = self.pos;
-        // If the underlying file has shrunk, the offset could
-        // point to beyond eof.
-        let len = if pos < contents.len() {
-            min(contents.len() - pos, buf.len())
-        } else {
-            0
-        };
-        if len > 0 {
-            buf[..len].copy_from_slice(&contents[pos..pos+len]);
-            self.pos += len;
-        }
+        self.verify_readable()?;
+        let len = self.f.read_at(buf, self.pos);
+        self.pos += len;
         Ok(len)
     }
 }
@@ -339,17 +1074,16 @@ impl io::Seek for FakeOpenFile {
 
 impl io::Write for FakeOpenFile {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.verify_access(AccessMode::Write)?;
-        let mut contents = self.f.contents.borrow_mut();
-        let pos = self.pos;
-        // if pos points beyond eof, resize contents to pos and pad with zeros
-        if pos > contents.len() {
-            contents.resize(pos, 0);
-        }
-        let copy_len = min(buf.len(), contents.len() - pos);
-        contents[pos..pos+copy_len].copy_from_slice(&buf[..copy_len]);
-        contents.extend_from_slice(&buf[copy_len..]);
-        self.pos += buf.len();
+        self.verify_writable()?;
+        if self.access_mode.append {
+            // like O_APPEND, finding the end and writing there is one step,
+            // so writers appending at the same time keep each other's writes
+            self.pos = self.resize(|len| len + buf.len(), |f| f.append(buf))?;
+        } else {
+            let pos = self.pos;
+            self.resize(|_| pos + buf.len(), |f| f.write_at(buf, pos))?;
+            self.pos += buf.len();
+        }
         Ok(buf.len())
     }
     fn flush(&mut self) -> Result<()> {
@@ -364,24 +1098,67 @@ impl FileExt for FakeOpenFile {
         Ok(FakeMetadata::from(&self.f))
     }
     fn set_len(&self, size: u64) -> Result<()> {
-        self.verify_access(AccessMode::Write)?;
-        let mut contents = self.f.contents.borrow_mut();
-        contents.resize(size as usize, 0);
-        Ok(())
+        self.verify_writable()?;
+        self.resize(|_| size as usize, |f| f.set_len(size as usize))
     }
     fn sync_all(&self) -> Result<()> {
+        self.fs.syncs.files.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
     fn sync_data(&self) -> Result<()> {
+        self.fs.syncs.files.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
+    fn set_times(&self, times: FileTimes) -> Result<()> {
+        self.fs.faults.check_writable()?;
+        self.f.times.set(times.accessed, times.modified);
+        Ok(())
+    }
+    fn has_changed_since_open(&self) -> Result<bool> {
+        Ok(self.f.contents.version() != self.opened_version)
+    }
+    fn try_clone(&self) -> Result<Self> {
+        if self.access_mode.write {
+            self.f.writers.acquire();
+        }
+        Ok(FakeOpenFile {
+            f: self.f.clone(),
+            pos: self.pos,
+            access_mode: self.access_mode,
+            opened_version: self.opened_version,
+            fs: self.fs.clone(),
+            path: self.path.clone(),
+        })
+    }
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.verify_readable()?;
+        Ok(self.f.read_at(buf, offset as usize))
+    }
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        self.verify_writable()?;
+        // as on Linux, a file opened for appending ignores the offset
+        if self.access_mode.append {
+            self.resize(|len| len + buf.len(), |f| f.append(buf))?;
+        } else {
+            let pos = offset as usize;
+            self.resize(|_| pos + buf.len(), |f| f.write_at(buf, pos))?;
+        }
+        Ok(buf.len())
+    }
 }
 
 #[derive(Debug)]
 pub struct FakeMetadata {
     len: u64,
     permissions: FakePermissions,
-    is_dir: bool,
+    kind: NodeKind,
+    is_sparse: bool,
+    times: node::Times,
+    uid: u32,
+    gid: u32,
+    ino: u64,
 }
 
 impl From<&node::File> for FakeMetadata {
@@ -389,7 +1166,12 @@ impl From<&node::File> for FakeMetadata {
         FakeMetadata {
             len: f.contents.borrow().len() as u64,
             permissions: FakePermissions::from(&f.mode),
-            is_dir: false,
+            kind: NodeKind::File,
+            is_sparse: !f.holes.is_empty(),
+            times: f.times.get(),
+            uid: f.owner.uid(),
+            gid: f.owner.gid(),
+            ino: f.ino,
         }
     }
 }
@@ -399,7 +1181,39 @@ impl From<&node::Dir> for FakeMetadata {
         FakeMetadata {
             len: 4096,
             permissions: FakePermissions::from(&d.mode),
-            is_dir: true,
+            kind: NodeKind::Dir,
+            is_sparse: false,
+            times: d.times.get(),
+            uid: d.owner.uid(),
+            gid: d.owner.gid(),
+            ino: d.ino,
+        }
+    }
+}
+
+impl From<&node::Symlink> for FakeMetadata {
+    fn from(l: &node::Symlink) -> Self {
+        // like on unix, the length of a link is the length of its target
+        FakeMetadata {
+            len: l.target.as_os_str().len() as u64,
+            permissions: FakePermissions(node::Symlink::MODE),
+            kind: NodeKind::Symlink,
+            is_sparse: false,
+            times: l.times.get(),
+            // links have no owner of their own here
+            uid: 0,
+            gid: 0,
+            ino: l.ino,
+        }
+    }
+}
+
+impl From<&node::Node> for FakeMetadata {
+    fn from(n: &node::Node) -> Self {
+        match n {
+            node::Node::File(ref file) => FakeMetadata::from(file),
+            node::Node::Dir(ref dir) => FakeMetadata::from(dir),
+            node::Node::Symlink(ref link) => FakeMetadata::from(link),
         }
     }
 }
@@ -408,11 +1222,19 @@ impl Metadata for FakeMetadata {
     type Permissions = FakePermissions;
 
     fn is_dir(&self) -> bool {
-        self.is_dir
+        self.kind == NodeKind::Dir
     }
 
     fn is_file(&self) -> bool {
-        !self.is_dir
+        self.kind == NodeKind::File
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.kind == NodeKind::Symlink
+    }
+
+    fn kind(&self) -> NodeKind {
+        self.kind
     }
 
     fn len(&self) -> u64 {
@@ -422,6 +1244,38 @@ impl Metadata for FakeMetadata {
     fn permissions(&self) -> Self::Permissions {
         self.permissions.clone()
     }
+
+    fn modified(&self) -> Result<SystemTime> {
+        Ok(self.times.modified)
+    }
+
+    fn accessed(&self) -> Result<SystemTime> {
+        Ok(self.times.accessed)
+    }
+
+    fn created(&self) -> Result<SystemTime> {
+        Ok(self.times.created)
+    }
+
+    #[cfg(unix)]
+    fn is_sparse(&self) -> bool {
+        self.is_sparse
+    }
+
+    #[cfg(unix)]
+    fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    #[cfg(unix)]
+    fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    #[cfg(unix)]
+    fn ino(&self) -> u64 {
+        self.ino
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -460,39 +1314,62 @@ impl Permissions for FakePermissions {
 
 #[derive(Debug)]
 pub struct DirEntry {
-    parent: PathBuf,
-    file_name: OsString,
+    // the entry's path as the caller named it, built once, as fs::DirEntry does
+    path: PathBuf,
+    // the entry's path in the registry, with all symbolic links resolved
+    node_path: PathBuf,
+    registry: Arc<Mutex<Registry>>,
 }
 
 impl DirEntry {
-    fn new<P, S>(parent: P, file_name: S) -> Self
-    where
-        P: AsRef<Path>,
-        S: AsRef<OsStr>,
-    {
+    fn new(path: PathBuf, node_path: PathBuf, registry: &Arc<Mutex<Registry>>) -> Self {
         DirEntry {
-            parent: parent.as_ref().to_path_buf(),
-            file_name: file_name.as_ref().to_os_string(),
+            path,
+            node_path,
+            registry: registry.clone(),
         }
     }
 }
 
 impl crate::DirEntry for DirEntry {
+    type Metadata = FakeMetadata;
+    type FileType = NodeKind;
+
     fn file_name(&self) -> OsString {
-        self.file_name.clone()
+        self.path.file_name().unwrap_or_else(|| self.path.as_os_str()).to_os_string()
     }
 
     fn path(&self) -> PathBuf {
-        self.parent.join(&self.file_name)
+        self.path.clone()
+    }
+
+    fn metadata(&self) -> Result<Self::Metadata> {
+        let registry = self.registry.lock().unwrap();
+        registry.get(&self.node_path).map(FakeMetadata::from)
+    }
+
+    fn file_type(&self) -> Result<Self::FileType> {
+        let registry = self.registry.lock().unwrap();
+        registry.get(&self.node_path).map(|node| node.kind())
     }
 }
 
+/// The entries of a directory, as they were when it was read.
+/// Each entry is only built once it is asked for.
 #[derive(Debug)]
-pub struct ReadDir(IntoIter<Result<DirEntry>>);
+pub struct ReadDir {
+    parent: PathBuf,
+    node_paths: IntoIter<PathBuf>,
+    registry: Arc<Mutex<Registry>>,
+}
 
 impl ReadDir {
-    fn new(entries: Vec<Result<DirEntry>>) -> Self {
-        ReadDir(entries.into_iter())
+    fn new(parent: &Path, node_paths: Vec<PathBuf>, registry: &Arc<Mutex<Registry>>) -> Self {
+        ReadDir {
+            parent: parent.to_path_buf(),
+            node_paths: node_paths.into_iter(),
+            registry: registry.clone(),
+        }
     }
 }
 
@@ -500,12 +1377,39 @@ impl Iterator for ReadDir {
     type Item = Result<DirEntry>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next()
+        let node_path = self.node_paths.next()?;
+        let file_name = node_path.file_name().unwrap_or_else(|| node_path.as_os_str());
+        let path = self.parent.join(file_name);
+
+        Some(Ok(DirEntry::new(path, node_path, &self.registry)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.node_paths.size_hint()
     }
 }
 
 impl crate::ReadDir<DirEntry> for ReadDir {}
 
+#[derive(Debug)]
+pub struct WalkDir(IntoIter<Result<DirEntry>>);
+
+impl WalkDir {
+    fn new(entries: Vec<Result<DirEntry>>) -> Self {
+        WalkDir(entries.into_iter())
+    }
+}
+
+impl Iterator for WalkDir {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl crate::WalkDir<DirEntry> for WalkDir {}
+
 #[cfg(feature = "temp")]
 impl TempFileSystem for FakeFileSystem {
     type TempDir = FakeTempDir;
@@ -514,6 +1418,6 @@ impl TempFileSystem for FakeFileSystem {
         let base = std::env::temp_dir();
         let dir = FakeTempDir::new(Arc::downgrade(&self.registry), &base, prefix.as_ref());
 
-        self.create_dir_all(&dir.path()).and(Ok(dir))
+        self.create_dir_all(dir.path()).and(Ok(dir))
     }
 }
@@ -1,25 +1,161 @@
-use std::sync::{Arc, Mutex};
-use std::ops::{Deref, DerefMut};
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::ops::{Deref, DerefMut, Range};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::NodeKind;
+
+/// Source of the versions handed out to file contents.
+/// Being global, no two versions of any two files are ever the same.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Source of the inode numbers handed out to nodes.
+/// Being global, no two nodes of any two file systems share one,
+/// except for copies of a node, which keep its number.
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+fn next_ino() -> u64 {
+    NEXT_INO.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Makes sure `ino`, which a node brought along from elsewhere,
+/// is never handed out to another node.
+#[cfg(feature = "serde")]
+pub fn reserve_ino(ino: u64) {
+    NEXT_INO.fetch_max(ino + 1, Ordering::Relaxed);
+}
 
 /// A reference-counted pointer to the contents of a file.
 ///
 /// `clone` just creates another pointer, it does not Clone
 /// the contents itself.
 ///
+/// The bytes themselves may also be shared with copies of the contents,
+/// made by `deep_clone`, until either is changed. Only then are they copied.
+///
 #[derive(Debug, Clone)]
-pub struct SharedContents(Arc<Mutex<Vec<u8>>>);
+pub struct SharedContents {
+    data: Arc<Mutex<Arc<Vec<u8>>>>,
+    version: Arc<AtomicU64>,
+}
+
+/// Borrows the bytes of a file, copying them on the first
+/// mutable access if they are shared with a copy.
+struct ContentsGuard<'a>(MutexGuard<'a, Arc<Vec<u8>>>);
+
+impl Deref for ContentsGuard<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for ContentsGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        Arc::make_mut(&mut self.0)
+    }
+}
 
 impl SharedContents {
     fn new(contents: Vec<u8>) -> Self {
-        SharedContents(Arc::new(Mutex::new(contents)))
+        SharedContents::from_bytes(Arc::new(contents))
+    }
+    fn from_bytes(bytes: Arc<Vec<u8>>) -> Self {
+        SharedContents {
+            data: Arc::new(Mutex::new(bytes)),
+            version: Arc::new(AtomicU64::new(next_version())),
+        }
     }
     /// Immutably borrow the file contents pointed to.
     pub fn borrow(&self) -> impl Deref<Target=Vec<u8>> + '_ {
-        self.0.lock().unwrap()
+        ContentsGuard(self.data.lock().unwrap())
     }
     /// Mutably borrow the file contents pointed to.
+    /// This counts as a change of the contents.
     pub fn borrow_mut(&self) -> impl DerefMut<Target=Vec<u8>> + '_ {
-        self.0.lock().unwrap()
+        let data = ContentsGuard(self.data.lock().unwrap());
+        self.version.store(next_version(), Ordering::Relaxed);
+        data
+    }
+    /// Makes the contents pointed to the same as those of `other`,
+    /// sharing the bytes until either is changed.
+    /// This counts as a change of the contents.
+    pub fn replace_with(&self, other: &SharedContents) {
+        let bytes = Arc::clone(&other.data.lock().unwrap());
+        *self.data.lock().unwrap() = bytes;
+        self.version.store(next_version(), Ordering::Relaxed);
+    }
+    /// Returns the bytes as they are now, without copying them.
+    pub fn bytes(&self) -> Arc<Vec<u8>> {
+        Arc::clone(&self.data.lock().unwrap())
+    }
+    /// Returns a number which changes whenever the contents do.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Relaxed)
+    }
+    /// Copies the contents themselves, rather than the pointer.
+    /// The copy gets a version of its own, and shares the bytes
+    /// with the original until either is changed.
+    pub fn deep_clone(&self) -> Self {
+        SharedContents::from_bytes(Arc::clone(&self.data.lock().unwrap()))
+    }
+    /// Identifies the contents, which are the same for all pointers to them.
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.data) as usize
+    }
+}
+
+/// A reference-counted list of the byte ranges of a file which
+/// have never been written to.
+///
+/// `clone` just creates another pointer, it does not Clone
+/// the list itself.
+///
+#[derive(Debug, Clone, Default)]
+pub struct SharedHoles(Arc<Mutex<Vec<Range<usize>>>>);
+
+impl SharedHoles {
+    /// Marks `range` as a hole, ie. never written to.
+    pub fn punch(&self, range: Range<usize>) {
+        if !range.is_empty() {
+            self.0.lock().unwrap().push(range);
+        }
+    }
+    /// Marks `range` as written to.
+    pub fn fill(&self, range: Range<usize>) {
+        let mut holes = self.0.lock().unwrap();
+        let mut remaining = Vec::with_capacity(holes.len());
+        for hole in holes.drain(..) {
+            // keep whatever part of the hole lies outside of range
+            let before = hole.start..min(hole.end, range.start);
+            let after = max(hole.start, range.end)..hole.end;
+            remaining.extend([before, after].iter().filter(|r| !r.is_empty()).cloned());
+        }
+        *holes = remaining;
+    }
+    /// Forgets about all holes at or beyond `len`.
+    pub fn truncate(&self, len: usize) {
+        self.fill(len..usize::MAX);
+    }
+    /// Forgets about all holes.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+    /// Copies the list itself, rather than the pointer.
+    pub fn deep_clone(&self) -> Self {
+        SharedHoles(Arc::new(Mutex::new(self.0.lock().unwrap().clone())))
     }
 }
 
@@ -43,6 +179,10 @@ impl SharedMode {
     pub fn can_write(&self) -> bool {
         (*self.0.lock().unwrap() & 0o222) != 0
     }
+    /// Copies the mode itself, rather than the pointer.
+    pub fn deep_clone(&self) -> Self {
+        SharedMode::new(self.get())
+    }
     pub fn make_readonly(&self, readonly: bool) {
         let mut mode = self.0.lock().unwrap();
         if readonly {
@@ -53,29 +193,342 @@ impl SharedMode {
     }
 }
 
+/// The user and group owning a node.
+#[derive(Debug, Clone, Default)]
+pub struct SharedOwner(Arc<Mutex<(u32, u32)>>);
+
+impl SharedOwner {
+    pub fn uid(&self) -> u32 {
+        self.0.lock().unwrap().0
+    }
+    pub fn gid(&self) -> u32 {
+        self.0.lock().unwrap().1
+    }
+    /// Changes the user and group, leaving either alone if it is `None`.
+    pub fn set(&self, uid: Option<u32>, gid: Option<u32>) {
+        let mut owner = self.0.lock().unwrap();
+        if let Some(uid) = uid {
+            owner.0 = uid;
+        }
+        if let Some(gid) = gid {
+            owner.1 = gid;
+        }
+    }
+    /// Copies the owner itself, rather than the pointer.
+    pub fn deep_clone(&self) -> Self {
+        SharedOwner(Arc::new(Mutex::new(*self.0.lock().unwrap())))
+    }
+}
+
+/// Whether a node is marked hidden, like the hidden attribute on Windows.
+#[derive(Debug, Clone, Default)]
+pub struct SharedHidden(Arc<AtomicBool>);
+
+impl SharedHidden {
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+    pub fn set(&self, hidden: bool) {
+        self.0.store(hidden, Ordering::Relaxed);
+    }
+    /// Copies the flag itself, rather than the pointer.
+    pub fn deep_clone(&self) -> Self {
+        SharedHidden(Arc::new(AtomicBool::new(self.get())))
+    }
+}
+
+/// How many open handles may write to a file.
+///
+/// `clone` just creates another pointer, so hard links to a file share the count.
+#[derive(Debug, Clone, Default)]
+pub struct SharedWriters(Arc<AtomicUsize>);
+
+impl SharedWriters {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+    pub fn acquire(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+    pub fn release(&self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The timestamps of a node.
+#[derive(Debug, Clone, Copy)]
+pub struct Times {
+    pub created: SystemTime,
+    pub accessed: SystemTime,
+    pub modified: SystemTime,
+}
+
+/// A reference-counted pointer to the granularity of the timestamps
+/// of a file system, in nanoseconds. Zero means timestamps are exact.
+///
+/// `clone` just creates another pointer, it does not Clone
+/// the granularity itself.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Clock(Arc<AtomicU64>);
+
+impl Clock {
+    pub fn granularity(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+    pub fn set_granularity(&self, granularity: Duration) {
+        self.0.store(granularity.as_nanos() as u64, Ordering::Relaxed);
+    }
+    /// Rounds `time` down to the granularity.
+    pub fn round(&self, time: SystemTime) -> SystemTime {
+        let granularity = self.0.load(Ordering::Relaxed);
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) if granularity > 0 => {
+                let nanos = since_epoch.as_nanos() as u64;
+                UNIX_EPOCH + Duration::from_nanos(nanos - nanos % granularity)
+            }
+            _ => time,
+        }
+    }
+    pub fn now(&self) -> SystemTime {
+        self.round(SystemTime::now())
+    }
+}
+
+/// A reference-counted pointer to the timestamps of a node.
+/// Every timestamp is rounded down by the clock of the file system.
+///
+/// `clone` just creates another pointer, it does not Clone
+/// the timestamps themselves.
+///
+#[derive(Debug, Clone)]
+pub struct SharedTimes(Arc<Mutex<Times>>, Clock);
+
+impl SharedTimes {
+    fn new(clock: &Clock) -> Self {
+        let now = clock.now();
+        SharedTimes(Arc::new(Mutex::new(Times {
+            created: now,
+            accessed: now,
+            modified: now,
+        })), clock.clone())
+    }
+
+    pub fn get(&self) -> Times {
+        *self.0.lock().unwrap()
+    }
+    pub fn touch_accessed(&self) {
+        self.0.lock().unwrap().accessed = self.1.now();
+    }
+    pub fn touch_modified(&self) {
+        self.0.lock().unwrap().modified = self.1.now();
+    }
+    /// Copies the timestamps themselves, rather than the pointer,
+    /// to be rounded by `clock` from then on.
+    pub fn deep_clone(&self, clock: &Clock) -> Self {
+        SharedTimes(Arc::new(Mutex::new(self.get())), clock.clone())
+    }
+    /// Sets the given timestamps, leaving the others unchanged.
+    pub fn set(&self, accessed: Option<SystemTime>, modified: Option<SystemTime>) {
+        let mut times = self.0.lock().unwrap();
+        if let Some(accessed) = accessed {
+            times.accessed = self.1.round(accessed);
+        }
+        if let Some(modified) = modified {
+            times.modified = self.1.round(modified);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct File {
+    /// Shared by all hard links to the file, as clones share it.
+    pub ino: u64,
     pub contents: SharedContents,
+    pub holes: SharedHoles,
     pub mode: SharedMode,
+    pub times: SharedTimes,
+    pub hidden: SharedHidden,
+    pub owner: SharedOwner,
+    pub writers: SharedWriters,
 }
 
 impl File {
-    pub fn new(contents: Vec<u8>) -> Self {
+    /// The mode a file gets if none was specified at creation.
+    pub const DEFAULT_MODE: u32 = 0o644;
+
+    pub fn new(contents: Vec<u8>, mode: u32, clock: &Clock) -> Self {
+        File::with_contents(SharedContents::new(contents), mode, clock)
+    }
+
+    /// Creates a file with the given contents, rather than a copy of them.
+    pub fn with_contents(contents: SharedContents, mode: u32, clock: &Clock) -> Self {
+        File {
+            ino: next_ino(),
+            contents,
+            holes: SharedHoles::default(),
+            mode: SharedMode::new(mode),
+            times: SharedTimes::new(clock),
+            hidden: SharedHidden::default(),
+            owner: SharedOwner::default(),
+            writers: SharedWriters::default(),
+        }
+    }
+
+    /// Copies the file, sharing nothing with the original but its ino.
+    /// The copy is not open anywhere, so has no writers.
+    pub fn deep_clone(&self, clock: &Clock) -> Self {
         File {
-            contents: SharedContents::new(contents),
-            mode: SharedMode::new(0o644),
+            ino: self.ino,
+            contents: self.contents.deep_clone(),
+            holes: self.holes.deep_clone(),
+            mode: self.mode.deep_clone(),
+            times: self.times.deep_clone(clock),
+            hidden: self.hidden.deep_clone(),
+            owner: self.owner.deep_clone(),
+            writers: SharedWriters::default(),
+        }
+    }
+
+    /// Replaces the entire contents of the file, which leaves no holes.
+    pub fn replace_contents(&self, buf: &[u8]) {
+        *self.contents.borrow_mut() = buf.to_vec();
+        self.holes.clear();
+        self.times.touch_modified();
+    }
+
+    /// Replaces the entire contents of the file with those of `other`, which
+    /// leaves no holes. The bytes are only copied once either file changes.
+    pub fn copy_contents(&self, other: &File) {
+        self.contents.replace_with(&other.contents);
+        self.holes.clear();
+        self.times.touch_modified();
+    }
+
+    /// Reads from `pos` into `buf`, returning how many bytes were read.
+    /// Nothing is read if `pos` is at or beyond the end of the file.
+    pub fn read_at(&self, buf: &mut [u8], pos: usize) -> usize {
+        let contents = self.contents.borrow();
+        // If the file has shrunk, pos could point beyond eof.
+        let len = if pos < contents.len() {
+            min(contents.len() - pos, buf.len())
+        } else {
+            0
+        };
+        if len > 0 {
+            buf[..len].copy_from_slice(&contents[pos..pos+len]);
+        }
+        self.times.touch_accessed();
+        len
+    }
+
+    /// Writes all of `buf` at `pos`.
+    /// If `pos` is beyond the end of the file, the gap becomes a hole of zeros.
+    pub fn write_at(&self, buf: &[u8], pos: usize) {
+        let mut contents = self.contents.borrow_mut();
+        if pos > contents.len() {
+            self.holes.punch(contents.len()..pos);
+            contents.resize(pos, 0);
         }
+        self.holes.fill(pos..pos+buf.len());
+        let copy_len = min(buf.len(), contents.len() - pos);
+        contents[pos..pos+copy_len].copy_from_slice(&buf[..copy_len]);
+        contents.extend_from_slice(&buf[copy_len..]);
+        self.times.touch_modified();
+    }
+
+    /// Writes all of `buf` at the end of the file, whatever other writers do
+    /// meanwhile, and returns the new end.
+    pub fn append(&self, buf: &[u8]) -> usize {
+        let mut contents = self.contents.borrow_mut();
+        contents.extend_from_slice(buf);
+        self.times.touch_modified();
+        contents.len()
+    }
+
+    /// Truncates or extends the file, the extension being a hole.
+    pub fn set_len(&self, size: usize) {
+        let mut contents = self.contents.borrow_mut();
+        if size > contents.len() {
+            self.holes.punch(contents.len()..size);
+        } else {
+            self.holes.truncate(size);
+        }
+        contents.resize(size, 0);
+        self.times.touch_modified();
     }
 }
 
 #[derive(Debug)]
 pub struct Dir {
+    pub ino: u64,
     pub mode: SharedMode,
+    pub times: SharedTimes,
+    pub hidden: SharedHidden,
+    pub owner: SharedOwner,
+    /// The nodes in the directory, by name.
+    pub children: BTreeMap<OsString, Node>,
+}
+
+impl Dir {
+    /// The mode a directory gets if none was specified at creation.
+    pub const DEFAULT_MODE: u32 = 0o644;
+
+    pub fn new(mode: u32, clock: &Clock) -> Self {
+        Dir {
+            ino: next_ino(),
+            mode: SharedMode::new(mode),
+            times: SharedTimes::new(clock),
+            hidden: SharedHidden::default(),
+            owner: SharedOwner::default(),
+            children: BTreeMap::new(),
+        }
+    }
+
+    /// Copies the directory, sharing nothing with the original but its ino.
+    /// The copy is empty, as copying the children is up to the caller.
+    pub fn deep_clone(&self, clock: &Clock) -> Self {
+        Dir {
+            ino: self.ino,
+            mode: self.mode.deep_clone(),
+            times: self.times.deep_clone(clock),
+            hidden: self.hidden.deep_clone(),
+            owner: self.owner.deep_clone(),
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Symlink {
+    /// The path the link points to, exactly as it was created.
+    pub target: PathBuf,
+    pub ino: u64,
+    pub times: SharedTimes,
+    pub hidden: SharedHidden,
 }
 
-impl Default for Dir {
-    fn default() -> Self {
-        Dir { mode: SharedMode::new(0o644) }
+impl Symlink {
+    /// Symbolic links always have all permission bits set.
+    pub const MODE: u32 = 0o777;
+
+    pub fn new(target: PathBuf, clock: &Clock) -> Self {
+        Symlink {
+            target,
+            ino: next_ino(),
+            times: SharedTimes::new(clock),
+            hidden: SharedHidden::default(),
+        }
+    }
+
+    /// Copies the link, sharing nothing with the original but its ino.
+    pub fn deep_clone(&self, clock: &Clock) -> Self {
+        Symlink {
+            target: self.target.clone(),
+            ino: self.ino,
+            times: self.times.deep_clone(clock),
+            hidden: self.hidden.deep_clone(),
+        }
     }
 }
 
@@ -83,20 +536,43 @@ impl Default for Dir {
 pub enum Node {
     File(File),
     Dir(Dir),
+    Symlink(Symlink),
 }
 
 impl Node {
     pub fn is_file(&self) -> bool {
+        matches!(*self, Self::File(_))
+    }
+
+    pub fn is_dir(&self) -> bool {
+        matches!(*self, Self::Dir(_))
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        matches!(*self, Self::Symlink(_))
+    }
+
+    pub fn kind(&self) -> NodeKind {
         match *self {
-            Self::File(_) => true,
-            _ => false,
+            Self::File(_) => NodeKind::File,
+            Self::Dir(_) => NodeKind::Dir,
+            Self::Symlink(_) => NodeKind::Symlink,
         }
     }
 
-    pub fn is_dir(&self) -> bool {
+    pub fn mode(&self) -> u32 {
+        match *self {
+            Self::File(ref file) => file.mode.get(),
+            Self::Dir(ref dir) => dir.mode.get(),
+            Self::Symlink(_) => Symlink::MODE,
+        }
+    }
+
+    pub fn hidden(&self) -> &SharedHidden {
         match *self {
-            Self::Dir(_) => true,
-            _ => false,
+            Self::File(ref file) => &file.hidden,
+            Self::Dir(ref dir) => &dir.hidden,
+            Self::Symlink(ref link) => &link.hidden,
         }
     }
 }
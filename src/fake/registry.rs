@@ -1,23 +1,167 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::io::{Error, ErrorKind, Result};
-use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
 
-use super::node::{Dir, File, Node};
+use super::node::{Clock, Dir, File, Node, Symlink};
+use crate::{group_duplicates, hash_contents, NodeKind, OpenOptions};
 
-#[derive(Debug, Default)]
+/// The maximum number of symbolic links followed while resolving a path,
+/// matching Linux' MAXSYMLINKS.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+#[derive(Debug)]
 pub struct Registry {
     cwd: PathBuf,
-    files: HashMap<PathBuf, Node>,
+    // the root directory, which holds all other nodes; paths are looked up
+    // by component, so redundant separators (`/a//b` vs `/a/b`) do not matter
+    root: Node,
+    clock: Clock,
+    // the most bytes the contents of all files may take up, if limited
+    capacity: Option<u64>,
+    // the bytes the contents of all files take up now
+    usage: Usage,
+    // whether names which differ only in case name the same node
+    case_insensitive: bool,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
 }
 
 impl Registry {
     pub fn new() -> Self {
         let cwd = PathBuf::from(MAIN_SEPARATOR.to_string());
-        let mut files = HashMap::new();
+        let clock = Clock::default();
+        let root = Node::Dir(Dir::new(Dir::DEFAULT_MODE, &clock));
+
+        Registry { cwd, root, clock, capacity: None, usage: Usage::default(), case_insensitive: false }
+    }
+
+    /// Copies the registry, sharing nothing with the original, except that
+    /// hard links in the copy share their file just like they do in the original.
+    pub fn deep_clone(&self) -> Self {
+        let clock = Clock::default();
+        clock.set_granularity(self.clock.granularity());
+        let root = deep_clone_node(&self.root, &clock, &mut HashMap::new());
+
+        let mut registry = Registry {
+            cwd: self.cwd.clone(),
+            root,
+            clock,
+            capacity: self.capacity,
+            usage: Usage::default(),
+            case_insensitive: self.case_insensitive,
+        };
+        registry.usage.link(&registry.root);
+        registry
+    }
+
+    /// Builds a registry out of its parts, whose timestamps are rounded by `clock`.
+    /// Fails with `InvalidData` unless the parts form a tree, hanging off a root directory.
+    #[cfg(feature = "serde")]
+    pub fn from_parts(cwd: PathBuf, files: HashMap<PathBuf, Node>, clock: Clock) -> Result<Self> {
+        let root = Node::Dir(Dir::new(Dir::DEFAULT_MODE, &clock));
+        let mut registry = Registry {
+            cwd,
+            root,
+            clock,
+            capacity: None,
+            usage: Usage::default(),
+            case_insensitive: false,
+        };
+
+        // sorting by path puts every directory before what is in it
+        let mut files: Vec<_> = files.into_iter().collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let invalid = |what: &str, path: &Path| {
+            Error::new(ErrorKind::InvalidData, format!("{} {}", path.display(), what))
+        };
+        for (path, node) in files {
+            match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => match registry.get_dir_mut(parent) {
+                    Ok(dir) => {
+                        dir.children.insert(name.to_os_string(), node);
+                    }
+                    Err(_) => return Err(invalid("is not a directory", parent)),
+                },
+                (None, _) if node.is_dir() => registry.root = node,
+                (None, _) => return Err(invalid("is not a directory", &path)),
+                // a path ending in `..`, which cannot name a node of its own
+                (Some(_), None) => return Err(invalid("has no name", &path)),
+            }
+        }
+        registry.usage.link(&registry.root);
+        Ok(registry)
+    }
+
+    /// All nodes, the root first, and every directory right before its descendants.
+    #[cfg(feature = "serde")]
+    pub fn nodes(&self) -> Vec<(PathBuf, &Node)> {
+        let root = PathBuf::from(MAIN_SEPARATOR.to_string());
+        let mut nodes = vec![(root.clone(), &self.root)];
+        collect_descendants(&root, &self.root, &mut nodes);
+        nodes
+    }
+
+    /// The current directory as it was set, even if it has since been removed.
+    #[cfg(feature = "serde")]
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// The total length of the contents of all files.
+    /// Hard links share their contents, which count only once.
+    pub fn total_bytes(&self) -> u64 {
+        self.usage.bytes
+    }
+
+    pub fn node_count(&self) -> usize {
+        count_nodes(&self.root)
+    }
+
+    pub fn set_capacity(&mut self, capacity: Option<u64>) {
+        self.capacity = capacity;
+    }
+
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
+    /// Fails with `StorageFull` if the contents of all files cannot grow
+    /// by `growth` bytes without exceeding the capacity.
+    fn check_capacity(&self, growth: usize) -> Result<()> {
+        match self.capacity {
+            Some(capacity) if growth > 0 && self.usage.bytes + growth as u64 > capacity => {
+                Err(create_error(ErrorKind::StorageFull))
+            }
+            _ => Ok(()),
+        }
+    }
 
-        files.insert(cwd.clone(), Node::Dir(Dir::default()));
+    /// Changes the contents of `file` with `change`, unless they cannot become
+    /// `len` bytes long without exceeding the capacity, which fails with `StorageFull`.
+    ///
+    /// Every change of the length of a file goes through here, under the lock
+    /// of the registry, so that no other change can slip in between the check
+    /// and the change, and so that the bytes used are kept up to date.
+    pub fn resize<T, F>(&mut self, file: &File, len: usize, change: F) -> Result<T>
+    where
+        F: FnOnce(&File) -> T,
+    {
+        let before = file.contents.borrow().len();
+        self.check_capacity(len.saturating_sub(before))?;
+        let result = change(file);
+        let after = file.contents.borrow().len();
+        self.usage.resized(file, before, after);
+        Ok(result)
+    }
 
-        Registry { cwd, files }
+    /// The clock which rounds the timestamps of all nodes.
+    pub fn clock(&self) -> &Clock {
+        &self.clock
     }
 
     pub fn current_dir(&self) -> Result<PathBuf> {
@@ -34,6 +178,30 @@ impl Registry {
         }
     }
 
+    pub fn exists(&self, path: &Path) -> bool {
+        self.get(path).is_ok()
+    }
+
+    pub fn try_exists(&self, path: &Path) -> Result<bool> {
+        // a directory which cannot be read hides what is in it,
+        // starting from the root as the lookup does
+        let ancestors: Vec<&Path> = path.ancestors().skip(1).collect();
+        for ancestor in ancestors.into_iter().rev() {
+            match self.get(ancestor) {
+                Ok(Node::Dir(ref dir)) if !dir.mode.can_read() => {
+                    return Err(create_error(ErrorKind::PermissionDenied))
+                }
+                Ok(Node::File(_)) => return Err(create_error(ErrorKind::NotADirectory)),
+                _ => {}
+            }
+        }
+        match self.get(path) {
+            Ok(_) => Ok(true),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn is_dir(&self, path: &Path) -> bool {
         self.get(path).map(Node::is_dir).unwrap_or(false)
     }
@@ -43,89 +211,207 @@ impl Registry {
     }
 
     pub fn create_dir(&mut self, path: &Path) -> Result<()> {
-        self.insert(path.to_path_buf(), Node::Dir(Dir::default()))
+        self.insert(path.to_path_buf(), Node::Dir(Dir::new(Dir::DEFAULT_MODE, &self.clock)))
     }
 
     pub fn create_dir_all(&mut self, path: &Path) -> Result<()> {
-        // Based on std::fs::DirBuilder::create_dir_all
-        if path == Path::new("") {
-            return Ok(());
-        }
-
-        match self.create_dir(path) {
-            Ok(_) => return Ok(()),
-            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
-            Err(_) if self.is_dir(path) => return Ok(()),
-            Err(e) => return Err(e),
+        // Based on std::fs::DirBuilder::create_dir_all, but iterative,
+        // so that a very deep path cannot exhaust the stack.
+        let mut missing = vec![];
+        let mut current = path;
+        while current != Path::new("") {
+            match self.create_dir(current) {
+                Ok(_) => break,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+                // like std, accept a symbolic link to a directory
+                Err(_) if self.resolve(current, true).is_ok_and(|p| self.is_dir(&p)) => break,
+                // an ancestor is in the way, rather than missing
+                Err(_) if current.parent().is_some_and(|p| self.get(p).is_ok_and(|n| !n.is_dir())) => {
+                    let ancestor = current.parent().unwrap_or(current);
+                    return Err(Error::new(
+                        ErrorKind::NotADirectory,
+                        format!("{} is not a directory", ancestor.display()),
+                    ));
+                }
+                Err(e) => return Err(e),
+            }
+            missing.push(current);
+            current = current.parent().ok_or_else(|| create_error(ErrorKind::Other))?;
         }
 
-        match path.parent() {
-            Some(p) => self.create_dir_all(p)?,
-            None => return Err(create_error(ErrorKind::Other)),
+        // now that the first ancestor exists, create the rest below it
+        for dir in missing.into_iter().rev() {
+            match self.create_dir(dir) {
+                Ok(_) => {}
+                Err(_) if self.is_dir(dir) => {}
+                Err(e) => return Err(e),
+            }
         }
-
-        self.create_dir_all(path)
+        Ok(())
     }
 
     pub fn remove_dir(&mut self, path: &Path) -> Result<()> {
         match self.get_dir(path) {
-            Ok(_) if self.descendants(path).is_empty() => {}
-            Ok(_) => return Err(create_error(ErrorKind::Other)),
+            Ok(dir) if dir.children.is_empty() => {}
+            Ok(_) => return Err(create_error(ErrorKind::DirectoryNotEmpty)),
             Err(e) => return Err(e),
         };
 
         self.remove(path).and(Ok(()))
     }
 
+    pub fn remove_dir_and_empty_parents(&mut self, path: &Path) -> Result<()> {
+        self.remove_dir(path)?;
+        // never remove the root
+        for ancestor in path.ancestors().skip(1).filter(|a| a.parent().is_some()) {
+            if self.remove_dir(ancestor).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     pub fn remove_dir_all(&mut self, path: &Path) -> Result<()> {
+        // like std, only remove the link, not what it points to
+        if self.get(path).map(Node::is_symlink).unwrap_or(false) {
+            return self.remove(path).and(Ok(()));
+        }
         self.get_dir_writable(path)?;
 
-        let descendants = self.descendants(path);
-        let all_readable = descendants.iter().all(|(_, mode)| mode & 0o444 != 0);
+        let all_readable = self
+            .descendants(path)
+            .iter()
+            .all(|(_, node)| node.mode() & 0o444 != 0);
 
         if !all_readable {
             return Err(create_error(ErrorKind::PermissionDenied));
         }
 
-        for (child, _) in descendants {
-            self.remove(&child)?;
-        }
-
+        // the descendants go along with the directory
         self.remove(path).and(Ok(()))
     }
 
+    pub fn remove_dir_all_report(&mut self, path: &Path) -> Result<u64> {
+        let mut counted = HashSet::new();
+        let freed = match self.get(path)? {
+            Node::Dir(_) => self
+                .descendants(path)
+                .iter()
+                .filter_map(|(_, node)| match *node {
+                    Node::File(ref file) if counted.insert(file.contents.id()) => {
+                        Some(file.contents.borrow().len() as u64)
+                    }
+                    _ => None,
+                })
+                .sum(),
+            Node::File(_) | Node::Symlink(_) => 0,
+        };
+        self.remove_dir_all(path)?;
+        Ok(freed)
+    }
+
     pub fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let dir = self.get_dir(path)?;
+
+        // children are kept sorted by name, which makes listings reproducible
+        Ok(dir.children.keys().map(|name| path.join(name)).collect())
+    }
+
+    pub fn walk_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
         self.get_dir(path)?;
 
-        Ok(self.children(path))
+        Ok(self.descendants(path).into_iter().map(|(p, _)| p).collect())
     }
 
-    pub fn create_file(&mut self, path: &Path, buf: &[u8]) -> Result<()> {
-        let file = File::new(buf.to_vec());
+    pub fn find_duplicates(&self, path: &Path) -> Result<Vec<Vec<PathBuf>>> {
+        self.get_dir(path)?;
+
+        let files = self
+            .descendants(path)
+            .into_iter()
+            .filter_map(|(p, n)| match *n {
+                Node::File(ref f) => Some((p, f.contents.borrow().len() as u64)),
+                _ => None,
+            })
+            .collect();
+        group_duplicates(
+            files,
+            |p| self.get_file_if_readable(p).map(|f| hash_contents(&f.contents.borrow())),
+            |a, b| Ok(self.get_file_if_readable(a)?.contents.bytes() == self.get_file_if_readable(b)?.contents.bytes()),
+        )
+    }
+
+    pub fn read_dir_names(&self, path: &Path) -> Result<Vec<(OsString, NodeKind)>> {
+        let dir = self.get_dir(path)?;
+
+        Ok(dir.children.iter().map(|(name, n)| (name.clone(), n.kind())).collect())
+    }
+
+    pub fn dir_is_empty(&self, path: &Path) -> Result<bool> {
+        self.get_dir(path).map(|dir| dir.children.is_empty())
+    }
+
+    pub fn create_file(&mut self, path: &Path, buf: &[u8], mode: u32) -> Result<()> {
+        self.check_capacity(buf.len())?;
+        let file = File::new(buf.to_vec(), mode, &self.clock);
 
         self.insert(path.to_path_buf(), Node::File(file))
     }
 
     pub fn write_file(&mut self, path: &Path, buf: &[u8]) -> Result<()> {
-        self.get_file_if_writable(path)
-            .map(|ref mut f| *f.contents.borrow_mut() = buf.to_vec())
-            .or_else(|e| {
-                if e.kind() == ErrorKind::NotFound {
-                    self.create_file(path, buf)
-                } else {
-                    Err(e)
-                }
-            })
+        match self.get_file_if_writable(path) {
+            Ok(f) => {
+                let f = f.clone();
+                self.resize(&f, buf.len(), |f| f.replace_contents(buf))
+            }
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                self.create_file(path, buf, File::DEFAULT_MODE)
+            }
+            Err(e) => Err(e),
+        }
     }
 
-    pub fn overwrite_file(&self, path: &Path, buf: &[u8]) -> Result<()> {
-        self.get_file_if_writable(path)
-            .map(|ref mut f| *f.contents.borrow_mut() = buf.to_vec())
-    }
+    pub fn open_file(&mut self, path: &Path, options: &OpenOptions) -> Result<File> {
+        #[cfg(unix)]
+        let mode = options.mode.unwrap_or(File::DEFAULT_MODE);
+        #[cfg(not(unix))]
+        let mode = File::DEFAULT_MODE;
 
-    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
-        self.get_file_if_readable(path)
-            .map(|f| f.contents.borrow().to_vec())
+        let mut created = false;
+        if options.create_new {
+            // careful, check presence in a way that works even if
+            // we have no access to the file.
+            if self.get(path).is_ok() {
+                return Err(create_error(ErrorKind::AlreadyExists));
+            }
+            self.create_file(path, &[], mode)?;
+            created = true;
+        } else if options.create && self.get(path).is_err() {
+            self.create_file(path, &[], mode)?;
+            created = true;
+        }
+
+        let write = options.write || options.append;
+        let file = match self.get(path)? {
+            Node::File(ref file) => file.clone(),
+            Node::Dir(_) if write => return Err(create_error(ErrorKind::IsADirectory)),
+            Node::Dir(_) | Node::Symlink(_) => return Err(create_error(ErrorKind::Other)),
+        };
+        // the mode of a newly created file only applies to future opens
+        if !created
+            && ((options.read && !file.mode.can_read()) || (write && !file.mode.can_write())) {
+            return Err(create_error(ErrorKind::PermissionDenied));
+        }
+        if write && options.exclusive_write && file.writers.count() > 0 {
+            return Err(create_error(ErrorKind::WouldBlock));
+        }
+        if options.truncate {
+            self.resize(&file, 0, |f| f.replace_contents(&[]))?;
+        }
+        if options.read {
+            file.times.touch_accessed();
+        }
+        Ok(file)
     }
 
     pub fn get_file_if_readable(&self, path: &Path) -> Result<&File> {
@@ -150,162 +436,411 @@ impl Registry {
             // in a "permission denied" error.
             return Err(create_error(ErrorKind::PermissionDenied));
         }
-        match self.get_file(path) {
-            Ok(_) => self.remove(path).and(Ok(())),
+        match self.get(path) {
+            Ok(node) if !node.is_dir() => self.remove(path).and(Ok(())),
+            // what unlink(2) fails with on Linux if the path is a directory
+            #[cfg(target_os = "linux")]
+            Ok(_) => Err(Error::from_raw_os_error(libc::EISDIR)),
+            #[cfg(not(target_os = "linux"))]
+            Ok(_) => Err(create_error(ErrorKind::Other)),
             Err(e) => Err(e),
         }
     }
 
-    pub fn copy_file(&mut self, from: &Path, to: &Path) -> Result<()> {
-        match self.read_file(from) {
-            Ok(ref buf) => self.write_file(to, buf),
+    pub fn copy_file(&mut self, from: &Path, to: &Path) -> Result<u64> {
+        let original = match self.get_file_if_readable(from) {
+            Ok(f) => f.clone(),
             Err(ref err) if err.kind() == ErrorKind::Other => {
-                Err(create_error(ErrorKind::InvalidInput))
+                return Err(create_error(ErrorKind::InvalidInput))
             }
-            Err(err) => Err(err),
+            Err(err) => return Err(err),
+        };
+        let len = original.contents.borrow().len();
+
+        // the copy shares the bytes of the original until either changes
+        match self.get_file_if_writable(to) {
+            Ok(f) => {
+                let f = f.clone();
+                self.resize(&f, len, |f| f.copy_contents(&original))?;
+            }
+            Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                self.check_capacity(len)?;
+                let contents = original.contents.deep_clone();
+                let file = File::with_contents(contents, File::DEFAULT_MODE, &self.clock);
+                self.insert(to.to_path_buf(), Node::File(file))?;
+            }
+            Err(err) => return Err(err),
+        }
+        // like fs::copy, the copy gets the permissions of the original
+        self.set_mode(to, original.mode.get())?;
+        Ok(len as u64)
+    }
+
+    pub fn rename_noreplace(&mut self, from: &Path, to: &Path) -> Result<()> {
+        self.get(from)?;
+        if self.get(to).is_ok() {
+            return Err(create_error(ErrorKind::AlreadyExists));
         }
+        self.rename(from, to)
     }
 
     pub fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        // as on unix, renaming a node to itself does nothing
+        if from == to {
+            return self.get(from).and(Ok(()));
+        }
+        if self.is_recasing(from, to) {
+            return self.recase(from, to);
+        }
+        // unix tells apart why a rename onto an existing node fails, other platforms do not
+        let refused = |unix_kind| create_error(if cfg!(unix) { unix_kind } else { ErrorKind::Other });
         match (self.get(from), self.get(to)) {
-            (Ok(&Node::File(_)), Ok(&Node::File(_))) => {
-                self.remove_file(to)?;
-                self.rename_path(from, to.to_path_buf())
-            }
-            (Ok(&Node::File(_)), Err(ref err)) if err.kind() == ErrorKind::NotFound => {
+            (Ok(f), Ok(t)) if !f.is_dir() && !t.is_dir() => self.replace_path(from, to),
+            (Ok(f), Err(ref err)) if !f.is_dir() && err.kind() == ErrorKind::NotFound => {
                 self.rename_path(from, to.to_path_buf())
             }
-            (Ok(&Node::Dir(_)), Ok(&Node::Dir(_))) if self.descendants(to).is_empty() => {
-                self.remove(to)?;
-                self.move_dir(from, to)
+            (Ok(Node::Dir(_)), Ok(Node::Dir(dir))) if dir.children.is_empty() => {
+                self.replace_path(from, to)
             }
-            (Ok(&Node::File(_)), Ok(&Node::Dir(_)))
-            | (Ok(&Node::Dir(_)), Ok(&Node::File(_)))
-            | (Ok(&Node::Dir(_)), Ok(&Node::Dir(_))) => Err(create_error(ErrorKind::Other)),
+            (Ok(f), Ok(Node::Dir(_))) if !f.is_dir() => Err(refused(ErrorKind::IsADirectory)),
+            (Ok(Node::Dir(_)), Ok(t)) if !t.is_dir() => Err(refused(ErrorKind::NotADirectory)),
+            (Ok(_), Ok(_)) => Err(refused(ErrorKind::DirectoryNotEmpty)),
             (Ok(&Node::Dir(_)), Err(ref err)) if err.kind() == ErrorKind::NotFound => {
-                self.move_dir(from, to)
+                self.rename_path(from, to.to_path_buf())
             }
             (Err(err), _) => Err(err),
             (_, Err(err)) => Err(err),
         }
     }
 
-    pub fn readonly(&self, path: &Path) -> Result<bool> {
-        self.get(path).map(|node| match node {
-            Node::File(ref file) => !file.mode.can_write(),
-            Node::Dir(ref dir) => !dir.mode.can_write(),
-        })
+    pub fn hard_link(&mut self, src: &Path, dst: &Path) -> Result<()> {
+        // a link shares the contents and mode of the original
+        let node = match self.get(src)? {
+            Node::File(ref file) => Node::File(file.clone()),
+            Node::Symlink(ref link) => Node::Symlink(link.clone()),
+            Node::Dir(_) => return Err(create_error(ErrorKind::PermissionDenied)),
+        };
+
+        self.insert(dst.to_path_buf(), node)
     }
 
+    // The permissions of a symbolic link can not be changed.
     pub fn set_readonly(&self, path: &Path, readonly: bool) -> Result<()> {
         self.get(path).map(|node| match node {
             Node::File(ref file) =>
                     file.mode.make_readonly(readonly),
             Node::Dir(ref dir) =>
                     dir.mode.make_readonly(readonly),
+            Node::Symlink(_) => {}
         })
     }
 
     pub fn mode(&self, path: &Path) -> Result<u32> {
-        self.get(path).map(|node| match node {
-            Node::File(ref file) => file.mode.get(),
-            Node::Dir(ref dir) => dir.mode.get(),
-        })
+        self.get(path).map(Node::mode)
     }
 
     pub fn set_mode(&self, path: &Path, mode: u32) -> Result<()> {
         self.get(path).map(|node| match node {
             Node::File(ref file) => file.mode.set(mode),
             Node::Dir(ref dir) => dir.mode.set(mode),
+            Node::Symlink(_) => {}
+        })
+    }
+
+    pub fn chown(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.get(path).map(|node| match node {
+            Node::File(ref file) => file.owner.set(uid, gid),
+            Node::Dir(ref dir) => dir.owner.set(uid, gid),
+            Node::Symlink(_) => {}
+        })
+    }
+
+    pub fn set_hidden(&self, path: &Path, hidden: bool) -> Result<()> {
+        self.get(path).map(|node| node.hidden().set(hidden))
+    }
+
+    pub fn create_symlink(&mut self, target: &Path, path: &Path) -> Result<()> {
+        self.insert(path.to_path_buf(), Node::Symlink(Symlink::new(target.to_path_buf(), &self.clock)))
+    }
+
+    pub fn change_token(&self, path: &Path) -> Result<u64> {
+        self.get_file(path).map(|f| f.contents.version())
+    }
+
+    pub fn write_if_unchanged(&mut self, path: &Path, expected_token: u64, buf: &[u8]) -> Result<bool> {
+        if self.change_token(path)? != expected_token {
+            return Ok(false);
+        }
+        let file = self.get_file_if_writable(path)?.clone();
+        self.resize(&file, buf.len(), |f| f.replace_contents(buf))?;
+        Ok(true)
+    }
+
+    pub fn write_batch(&mut self, writes: &[(PathBuf, &[u8])]) -> Result<()> {
+        // check every write before making any, so that either all happen or none.
+        // Each file ends up as long as the last write to it, counted once even if
+        // it is written at several paths, or through several hard links.
+        let mut existing = HashMap::new(); // contents id -> (length now, length after)
+        let mut created = HashMap::new(); // path -> length after
+        for (path, buf) in writes {
+            match self.get_file_if_writable(path) {
+                Ok(f) => {
+                    existing.insert(f.contents.id(), (f.contents.borrow().len(), buf.len()));
+                }
+                Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                    self.check_insert(path)?;
+                    created.insert(path.as_path(), buf.len());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let growth = existing.values().map(|&(now, after)| after.saturating_sub(now)).sum::<usize>()
+            + created.values().sum::<usize>();
+        self.check_capacity(growth)?;
+
+        // the batch fits once done, even if an earlier write to a file would not
+        let capacity = self.capacity.take();
+        let result = writes.iter().try_for_each(|(path, buf)| self.write_file(path, buf));
+        self.capacity = capacity;
+        result
+    }
+
+    pub fn set_len(&mut self, path: &Path, size: usize) -> Result<()> {
+        let file = self.get_file_if_writable(path)?.clone();
+        self.resize(&file, size, |f| f.set_len(size))
+    }
+
+    pub fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        self.get(path).and_then(|node| match node {
+            Node::Symlink(ref link) => Ok(link.target.clone()),
+            Node::File(_) | Node::Dir(_) => Err(create_error(ErrorKind::InvalidInput)),
         })
     }
 
-    fn get(&self, path: &Path) -> Result<&Node> {
-        self.files
-            .get(path)
-            .ok_or_else(|| create_error(ErrorKind::NotFound))
+    /// Resolves the symbolic links in every component of `path`, except
+    /// for the final component if `follow` is false.
+    ///
+    /// The `..` and `.` parts of link targets are applied as they are met, so
+    /// `..` leads to the parent of the directory which the link is in.
+    ///
+    /// Fails after `MAX_SYMLINK_HOPS` links, with `ELOOP` on Linux like the kernel.
+    pub fn resolve(&self, path: &Path, follow: bool) -> Result<PathBuf> {
+        // each part, and whether it comes from the target of a link
+        let mut pending: Vec<(Component, bool)> = path.components().rev().map(|c| (c, false)).collect();
+        let mut resolved = PathBuf::new();
+        let mut hops = 0;
+
+        while let Some((component, from_link)) = pending.pop() {
+            match component {
+                Component::ParentDir if from_link => {
+                    resolved.pop();
+                    continue;
+                }
+                Component::CurDir if from_link => continue,
+                _ => resolved.push(component),
+            }
+            if pending.is_empty() && !follow {
+                break;
+            }
+            if let Ok(Node::Symlink(link)) = self.get(&resolved) {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    // what the kernel fails with on Linux, whose kind is not stable yet
+                    #[cfg(target_os = "linux")]
+                    return Err(Error::from_raw_os_error(libc::ELOOP));
+                    #[cfg(not(target_os = "linux"))]
+                    return Err(create_error(ErrorKind::Other));
+                }
+                // relative targets are relative to the directory holding the link,
+                // an absolute target replaces the whole path when pushed.
+                resolved.pop();
+                pending.extend(link.target.components().rev().map(|c| (c, true)));
+            }
+        }
+        Ok(resolved)
+    }
+
+    pub fn get(&self, path: &Path) -> Result<&Node> {
+        let mut components = path.components();
+        if components.next() != Some(Component::RootDir) {
+            return Err(create_error(ErrorKind::NotFound));
+        }
+        let mut node = &self.root;
+        for component in components {
+            node = match (component, node) {
+                (Component::Normal(name), Node::Dir(ref dir)) => stored_name(dir, name, self.case_insensitive)
+                    .and_then(|name| dir.children.get(name))
+                    .ok_or_else(|| create_error(ErrorKind::NotFound))?,
+                _ => return Err(create_error(ErrorKind::NotFound)),
+            };
+        }
+        Ok(node)
+    }
+
+    fn get_dir_mut(&mut self, path: &Path) -> Result<&mut Dir> {
+        let mut components = path.components();
+        if components.next() != Some(Component::RootDir) {
+            return Err(create_error(ErrorKind::NotFound));
+        }
+        let case_insensitive = self.case_insensitive;
+        let mut node = &mut self.root;
+        for component in components {
+            node = match (component, node) {
+                (Component::Normal(name), Node::Dir(dir)) => {
+                    let name = stored_name(dir, name, case_insensitive)
+                        .map(OsStr::to_os_string)
+                        .ok_or_else(|| create_error(ErrorKind::NotFound))?;
+                    dir.children.get_mut(&name).ok_or_else(|| create_error(ErrorKind::NotFound))?
+                }
+                _ => return Err(create_error(ErrorKind::NotFound)),
+            };
+        }
+        match node {
+            Node::Dir(dir) => Ok(dir),
+            Node::File(_) | Node::Symlink(_) => Err(create_error(ErrorKind::Other)),
+        }
     }
 
     pub fn get_dir(&self, path: &Path) -> Result<&Dir> {
         self.get(path).and_then(|node| match node {
             Node::Dir(ref dir) => Ok(dir),
-            Node::File(_) => Err(create_error(ErrorKind::Other)),
+            Node::File(_) | Node::Symlink(_) => Err(create_error(ErrorKind::Other)),
         })
     }
 
-    fn get_dir_writable(&self, path: &Path) -> Result<&Dir> {
+    pub fn get_dir_writable(&self, path: &Path) -> Result<&Dir> {
         self.get(path).and_then(|node| match node {
             Node::Dir(ref dir) if dir.mode.can_write() => Ok(dir),
             Node::Dir(_) => Err(create_error(ErrorKind::PermissionDenied)),
-            Node::File(_) => Err(create_error(ErrorKind::Other)),
+            Node::File(_) | Node::Symlink(_) => Err(create_error(ErrorKind::Other)),
         })
     }
 
     pub fn get_file(&self, path: &Path) -> Result<&File> {
         self.get(path).and_then(|node| match node {
             Node::File(ref file) => Ok(file),
-            Node::Dir(_) => Err(create_error(ErrorKind::Other)),
+            Node::Dir(_) | Node::Symlink(_) => Err(create_error(ErrorKind::Other)),
         })
     }
 
-    fn insert(&mut self, path: PathBuf, file: Node) -> Result<()> {
-        if self.files.contains_key(&path) {
+    // Fails if no node can be inserted at path.
+    fn check_insert(&self, path: &Path) -> Result<()> {
+        if self.get(path).is_ok() {
             return Err(create_error(ErrorKind::AlreadyExists));
-        } else if let Some(p) = path.parent() {
-            self.get_dir_writable(p)?;
         }
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(_)) => self.get_dir_writable(parent).and(Ok(())),
+            // the root, or a path ending in `..`, which names an existing directory
+            _ => Err(create_error(ErrorKind::AlreadyExists)),
+        }
+    }
 
-        self.files.insert(path, file);
-
-        Ok(())
+    fn insert(&mut self, path: PathBuf, node: Node) -> Result<()> {
+        self.check_insert(&path)?;
+        self.attach(&path, node)
     }
 
-    fn remove(&mut self, path: &Path) -> Result<Node> {
-        match self.files.remove(path) {
-            Some(f) => Ok(f),
-            None => Err(create_error(ErrorKind::NotFound)),
+    // Puts node at path, without checking whether anything is there already.
+    fn attach(&mut self, path: &Path, node: Node) -> Result<()> {
+        if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+            self.usage.link(&node);
+            self.get_dir_mut(parent)?.children.insert(name.to_os_string(), node);
         }
+        Ok(())
     }
 
-    fn descendants(&self, path: &Path) -> Vec<(PathBuf, u32)> {
-        self.files
-            .iter()
-            .filter(|(p, _)| p.starts_with(path) && *p != path)
-            .map(|(p, n)| {
-                (
-                    p.to_path_buf(),
-                    match n {
-                        Node::File(ref file) => file.mode.get(),
-                        Node::Dir(ref dir) => dir.mode.get(),
-                    },
-                )
-            })
-            .collect()
+    fn remove(&mut self, path: &Path) -> Result<Node> {
+        let case_insensitive = self.case_insensitive;
+        let node = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => self
+                .get_dir_mut(parent)
+                .ok()
+                .and_then(|dir| {
+                    let name = stored_name(dir, name, case_insensitive)?.to_os_string();
+                    dir.children.remove(&name)
+                })
+                .ok_or_else(|| create_error(ErrorKind::NotFound))?,
+            // the root cannot go, as everything else hangs off it
+            _ => return Err(create_error(ErrorKind::ResourceBusy)),
+        };
+        self.usage.unlink(&node);
+        Ok(node)
     }
 
-    fn children(&self, path: &Path) -> Vec<PathBuf> {
-        self.files
-            .keys()
-            .filter(|p| p.parent().map(|parent| parent == path).unwrap_or(false))
-            .map(|p| p.to_path_buf())
-            .collect()
+    // All nodes below the directory at path, each directory right before its descendants.
+    fn descendants(&self, path: &Path) -> Vec<(PathBuf, &Node)> {
+        let mut descendants = vec![];
+        if let Ok(node) = self.get(path) {
+            collect_descendants(path, node, &mut descendants);
+        }
+        descendants
     }
 
+    // Moves the node at from, and everything below it, to to.
     fn rename_path(&mut self, from: &Path, to: PathBuf) -> Result<()> {
-        let file = self.remove(from)?;
-        self.insert(to, file)
+        if to.starts_with(from) {
+            // a directory cannot move into itself
+            return Err(create_error(ErrorKind::InvalidInput));
+        }
+        self.check_insert(&to)?;
+        let node = self.remove(from)?;
+        self.insert(to, node)
+    }
+
+    // Moves the node at from over the node at to, which it replaces.
+    // Everything is checked before either node is touched, as the node at to
+    // may stand in the way of checking the new name once it is gone, if it
+    // only differs in case from the node at from.
+    fn replace_path(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if to.starts_with(from) {
+            // a directory cannot move into itself
+            return Err(create_error(ErrorKind::InvalidInput));
+        }
+        self.get_dir_writable(to.parent().unwrap_or(to))?;
+        self.remove(to)?;
+        let node = self.remove(from)?;
+        self.attach(to, node)
     }
 
-    fn move_dir(&mut self, from: &Path, to: &Path) -> Result<()> {
-        self.rename_path(from, to.to_path_buf())?;
-
-        for child in self.children(from) {
-            let stem = child.strip_prefix(from).unwrap_or(&child);
-            let new_path = to.join(stem);
-
-            self.rename(&child, &new_path)?;
+    // Whether from and to name the same node, by names which differ only in case.
+    fn is_recasing(&self, from: &Path, to: &Path) -> bool {
+        if !self.case_insensitive {
+            return false;
         }
+        match (from.parent(), from.file_name(), to.parent(), to.file_name()) {
+            (Some(from_parent), Some(from_name), Some(to_parent), Some(to_name)) => {
+                // names which are not valid Unicode have no case to change
+                let folded = fold_case(from_name);
+                folded.is_some()
+                    && folded == fold_case(to_name)
+                    && match (self.get_dir(from_parent), self.get_dir(to_parent)) {
+                        (Ok(dir), Ok(to_dir)) => {
+                            let stored = stored_name(dir, from_name, true);
+                            std::ptr::eq(dir, to_dir)
+                                && stored.is_some()
+                                && stored == stored_name(dir, to_name, true)
+                        }
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
 
+    // Gives the node at from the name of to, which differs only in case.
+    // The node keeps its place, as there is no other node to make way for.
+    fn recase(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let (parent, from_name, to_name) = match (from.parent(), from.file_name(), to.file_name()) {
+            (Some(parent), Some(from_name), Some(to_name)) => (parent, from_name, to_name),
+            _ => return Err(create_error(ErrorKind::InvalidInput)),
+        };
+        self.get_dir_writable(parent)?;
+        let dir = self.get_dir_mut(parent)?;
+        let stored = stored_name(dir, from_name, true)
+            .map(OsStr::to_os_string)
+            .ok_or_else(|| create_error(ErrorKind::NotFound))?;
+        let node = dir.children.remove(&stored).ok_or_else(|| create_error(ErrorKind::NotFound))?;
+        dir.children.insert(to_name.to_os_string(), node);
         Ok(())
     }
 
@@ -315,26 +850,140 @@ impl Registry {
         for (idx, chunk) in path.iter().enumerate() {
             if chunk == ".." {
                 sane_path.pop();
-            } else {
+            } else if chunk != "." {
+                // like fs::canonicalize, drop any `.`
                 sane_path.push(chunk);
             }
             if idx == last_idx {
                 // final component must exist
                 self.get(&sane_path)?;
             } else {
-                // non-final component must be a directory, unless we're on macos,
-                // which insists only that the partial path exist
-                if cfg!(target_os = "macos") {
-                    self.get(&sane_path)?;
-                } else {
-                    self.get_dir(&sane_path)?;
-                }
+                // non-final component must be a directory. macos insists only that
+                // the partial path exist, but the fake behaves alike on every platform.
+                self.get_dir(&sane_path)?;
             }
         }
         Ok(sane_path)
     }
 }
 
+// The name under which dir holds the child called name. It differs from
+// name only if case is ignored, which takes a look at every child.
+fn stored_name<'a>(dir: &'a Dir, name: &OsStr, case_insensitive: bool) -> Option<&'a OsStr> {
+    if let Some((stored, _)) = dir.children.get_key_value(name) {
+        return Some(stored);
+    }
+    if !case_insensitive {
+        return None;
+    }
+    let folded = fold_case(name)?;
+    dir.children
+        .keys()
+        .find(|stored| fold_case(stored).as_ref() == Some(&folded))
+        .map(OsString::as_os_str)
+}
+
+// The name with case folded away, if it is valid Unicode.
+fn fold_case(name: &OsStr) -> Option<String> {
+    name.to_str().map(str::to_lowercase)
+}
+
+// Copies node and everything below it, sharing nothing with the original, except
+// that hard links, which share a file, share the copy of the file in the copy too.
+fn deep_clone_node(node: &Node, clock: &Clock, copied_files: &mut HashMap<usize, File>) -> Node {
+    match *node {
+        Node::File(ref file) => Node::File(
+            copied_files
+                .entry(file.contents.id())
+                .or_insert_with(|| file.deep_clone(clock))
+                .clone(),
+        ),
+        Node::Dir(ref dir) => {
+            let mut copy = dir.deep_clone(clock);
+            copy.children = dir
+                .children
+                .iter()
+                .map(|(name, child)| (name.clone(), deep_clone_node(child, clock, copied_files)))
+                .collect();
+            Node::Dir(copy)
+        }
+        Node::Symlink(ref link) => Node::Symlink(link.deep_clone(clock)),
+    }
+}
+
+// The bytes taken up by the contents of the files in a registry.
+// Hard links share their contents, which count only once, as long as
+// any of the links is in the registry.
+#[derive(Debug, Default)]
+struct Usage {
+    bytes: u64,
+    // how many links to each contents are in the registry, by contents id
+    links: HashMap<usize, usize>,
+}
+
+impl Usage {
+    // Counts the files in node, which has been added to the registry.
+    fn link(&mut self, node: &Node) {
+        for_each_file(node, &mut |file| {
+            let links = self.links.entry(file.contents.id()).or_insert(0);
+            *links += 1;
+            if *links == 1 {
+                self.bytes += file.contents.borrow().len() as u64;
+            }
+        });
+    }
+
+    // Stops counting the files in node, which has left the registry.
+    fn unlink(&mut self, node: &Node) {
+        for_each_file(node, &mut |file| {
+            let id = file.contents.id();
+            let links = self.links.get_mut(&id).expect("file was never linked");
+            *links -= 1;
+            if *links == 0 {
+                self.links.remove(&id);
+                self.bytes -= file.contents.borrow().len() as u64;
+            }
+        });
+    }
+
+    // Accounts for the contents of file going from before to after bytes,
+    // if the file is in the registry rather than just open.
+    fn resized(&mut self, file: &File, before: usize, after: usize) {
+        if self.links.contains_key(&file.contents.id()) {
+            self.bytes = self.bytes + after as u64 - before as u64;
+        }
+    }
+}
+
+// The number of nodes in node, counting node itself.
+fn count_nodes(node: &Node) -> usize {
+    match *node {
+        Node::Dir(ref dir) => 1 + dir.children.values().map(count_nodes).sum::<usize>(),
+        Node::File(_) | Node::Symlink(_) => 1,
+    }
+}
+
+// Calls f on node if it is a file, or on every file below it if it is a directory.
+fn for_each_file<F: FnMut(&File)>(node: &Node, f: &mut F) {
+    match *node {
+        Node::File(ref file) => f(file),
+        Node::Dir(ref dir) => dir.children.values().for_each(|child| for_each_file(child, f)),
+        Node::Symlink(_) => {}
+    }
+}
+
+// Adds the nodes below node, which is at path, to nodes,
+// each directory right before its descendants.
+fn collect_descendants<'a>(path: &Path, node: &'a Node, nodes: &mut Vec<(PathBuf, &'a Node)>) {
+    if let Node::Dir(ref dir) = *node {
+        for (name, child) in &dir.children {
+            let child_path = path.join(name);
+            nodes.push((child_path.clone(), child));
+            collect_descendants(&child_path, child, nodes);
+        }
+    }
+}
+
 pub fn create_error(kind: ErrorKind) -> Error {
     // Based on private std::io::ErrorKind::as_str()
     let description = match kind {
@@ -356,6 +1005,11 @@ pub fn create_error(kind: ErrorKind) -> Error {
         ErrorKind::Interrupted => "operation interrupted",
         ErrorKind::Other => "other os error",
         ErrorKind::UnexpectedEof => "unexpected end of file",
+        ErrorKind::IsADirectory => "is a directory",
+        ErrorKind::NotADirectory => "not a directory",
+        ErrorKind::ResourceBusy => "resource busy",
+        ErrorKind::DirectoryNotEmpty => "directory not empty",
+        ErrorKind::Unsupported => "unsupported",
         _ => "other",
     };
 
@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::node::{reserve_ino, Clock, Dir, File, Node, Symlink};
+use super::registry::Registry;
+use super::FakeSnapshot;
+
+/// How a snapshot is laid out when serialized.
+/// Nodes are sorted by path, so the same tree always serializes the same way.
+#[derive(Serialize, Deserialize)]
+struct SerializedRegistry {
+    cwd: PathBuf,
+    nodes: BTreeMap<PathBuf, SerializedNode>,
+}
+
+// Files which share an ino are hard links to one file. Snapshots
+// serialized without inos get new ones, and no hard links.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SerializedNode {
+    File {
+        bytes: Vec<u8>,
+        mode: u32,
+        #[serde(default)]
+        ino: u64,
+    },
+    Dir {
+        mode: u32,
+        #[serde(default)]
+        ino: u64,
+    },
+    Symlink {
+        target: PathBuf,
+        #[serde(default)]
+        ino: u64,
+    },
+}
+
+impl From<&Node> for SerializedNode {
+    fn from(node: &Node) -> Self {
+        match *node {
+            Node::File(ref file) => SerializedNode::File {
+                bytes: file.contents.borrow().clone(),
+                mode: file.mode.get(),
+                ino: file.ino,
+            },
+            Node::Dir(ref dir) => SerializedNode::Dir { mode: dir.mode.get(), ino: dir.ino },
+            Node::Symlink(ref link) => SerializedNode::Symlink {
+                target: link.target.clone(),
+                ino: link.ino,
+            },
+        }
+    }
+}
+
+impl SerializedNode {
+    // Files whose ino is in files become links to the file there.
+    fn into_node(self, clock: &Clock, files: &mut HashMap<u64, File>) -> Node {
+        match self {
+            SerializedNode::File { ino, .. } if files.contains_key(&ino) => {
+                Node::File(files[&ino].clone())
+            }
+            SerializedNode::File { bytes, mode, ino } => {
+                let mut file = File::new(bytes, mode, clock);
+                if ino != 0 {
+                    reserve_ino(ino);
+                    file.ino = ino;
+                    files.insert(ino, file.clone());
+                }
+                Node::File(file)
+            }
+            SerializedNode::Dir { mode, ino } => {
+                let mut dir = Dir::new(mode, clock);
+                if ino != 0 {
+                    reserve_ino(ino);
+                    dir.ino = ino;
+                }
+                Node::Dir(dir)
+            }
+            SerializedNode::Symlink { target, ino } => {
+                let mut link = Symlink::new(target, clock);
+                if ino != 0 {
+                    reserve_ino(ino);
+                    link.ino = ino;
+                }
+                Node::Symlink(link)
+            }
+        }
+    }
+}
+
+impl Serialize for FakeSnapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let registry = &self.0;
+        SerializedRegistry {
+            cwd: registry.cwd().to_path_buf(),
+            nodes: registry
+                .nodes()
+                .into_iter()
+                .map(|(path, node)| (path, SerializedNode::from(node)))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FakeSnapshot {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedRegistry::deserialize(deserializer)?;
+        let clock = Clock::default();
+        let mut linked = HashMap::new();
+        let files: HashMap<PathBuf, Node> = serialized
+            .nodes
+            .into_iter()
+            .map(|(path, node)| (path, node.into_node(&clock, &mut linked)))
+            .collect();
+
+        // from_parts checks that the nodes form a tree, hanging off the root
+        let is_dir = |path: &Path| files.get(path).is_some_and(Node::is_dir);
+        for path in files.keys().chain(Some(&serialized.cwd)) {
+            if !path.is_absolute() {
+                return Err(D::Error::custom(format!("{} is not absolute", path.display())));
+            }
+        }
+        // the cwd may have been removed before the snapshot, like on the OS
+        if files.contains_key(&serialized.cwd) && !is_dir(&serialized.cwd) {
+            return Err(D::Error::custom(format!("{} is not a directory", serialized.cwd.display())));
+        }
+
+        Registry::from_parts(serialized.cwd, files, clock).map(FakeSnapshot).map_err(D::Error::custom)
+    }
+}
@@ -1,24 +1,49 @@
 #![warn(clippy::all)]
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::ffi::OsString;
+use std::hash::{Hash, Hasher};
 use std::io::{self, Result};
 use std::fmt;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+pub use caching::CachingFileSystem;
 #[cfg(feature = "fake")]
-pub use fake::{FakeFileSystem};
-pub use os::OsFileSystem;
+pub use fake::{FakeFileSystem, FakeSnapshot, FsOp};
+pub use os::{OsFile, OsFileSystem};
 #[cfg(feature = "temp")]
 pub use os::OsTempDir;
 
+mod caching;
 #[cfg(feature = "fake")]
 mod fake;
 mod os;
 
 /// Provides standard file system operations.
 pub trait FileSystem: Clone + Send + Sync {
-    type DirEntry: DirEntry;
+    type DirEntry: DirEntry<Metadata=Self::Metadata>;
     type ReadDir: ReadDir<Self::DirEntry>;
+    type WalkDir: WalkDir<Self::DirEntry>;
+    /// An open file.
+    ///
+    /// A file can always be seeked, but only be read from if it was opened for reading, and
+    /// only be written to or resized if it was opened for writing or appending. Otherwise, the
+    /// FakeFileSystem fails with [`io::ErrorKind::PermissionDenied`], and the OsFileSystem
+    /// fails with whatever the OS reports, usually `EBADF`.
+    ///
+    /// An open file sees the live contents of the file it was opened at, never a snapshot.
+    /// Anything which changes that file in place, such as [`write`], [`create`], [`append`],
+    /// [`copy_file`] onto it, or a write through another handle, shows up in the next read,
+    /// from the file's own cursor on. Replacing the file instead, by removing it or renaming
+    /// another node onto its path, leaves the open file reading the contents it had.
+    ///
+    /// [`write`]: #method.write
+    /// [`create`]: #tymethod.create
+    /// [`append`]: #method.append
+    /// [`copy_file`]: #tymethod.copy_file
+    /// [`io::ErrorKind::PermissionDenied`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.PermissionDenied
     type File: io::Read + io::Seek + io::Write + FileExt<Metadata=Self::Metadata> + fmt::Debug;
     type Permissions: Permissions;
     type Metadata: Metadata<Permissions=Self::Permissions>;
@@ -36,15 +61,118 @@ pub trait FileSystem: Clone + Send + Sync {
     /// [`fs::File::create`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.create
     fn create<P: AsRef<Path>>(&self, path: P) -> Result<Self::File>;
 
+    /// Like [`create`], but hints that about `capacity` bytes will be written to the file.
+    ///
+    /// The FakeFileSystem preallocates the file's contents, so a long sequence of writes does not
+    /// repeatedly grow them. By default, and so for the OsFileSystem, the hint is ignored.
+    ///
+    /// [`create`]: #tymethod.create
+    fn create_with_capacity<P: AsRef<Path>>(&self, path: P, capacity: usize) -> Result<Self::File> {
+        let _ = capacity;
+        self.create(path)
+    }
+
+    /// Reads the entire contents of a file into a bytes vector.
+    /// This is based on [`fs::read`].
+    ///
+    /// [`fs::read`]: https://doc.rust-lang.org/std/fs/fn.read.html
+    fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        let mut contents = vec![];
+        io::Read::read_to_end(&mut self.open(path)?, &mut contents)?;
+        Ok(contents)
+    }
+
+    /// Like [`read`], but fails with [`io::ErrorKind::FileTooLarge`] if the file is
+    /// longer than `max` bytes, so a huge file cannot exhaust memory.
+    ///
+    /// The length is checked before reading, and reading stops after `max` bytes
+    /// in case the file grows meanwhile.
+    ///
+    /// [`read`]: #method.read
+    /// [`io::ErrorKind::FileTooLarge`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.FileTooLarge
+    fn read_capped<P: AsRef<Path>>(&self, path: P, max: usize) -> Result<Vec<u8>> {
+        let file = self.open(path)?;
+        if file.metadata()?.len() > max as u64 {
+            return Err(io::Error::from(io::ErrorKind::FileTooLarge));
+        }
+        let mut contents = vec![];
+        io::Read::read_to_end(&mut io::Read::take(file, max as u64 + 1), &mut contents)?;
+        if contents.len() > max {
+            return Err(io::Error::from(io::ErrorKind::FileTooLarge));
+        }
+        Ok(contents)
+    }
+
+    /// Reads the entire contents of a file into a string.
+    /// This is based on [`fs::read_to_string`].
+    ///
+    /// Fails with `InvalidData` if the contents are not valid UTF-8.
+    ///
+    /// [`fs::read_to_string`]: https://doc.rust-lang.org/std/fs/fn.read_to_string.html
+    fn read_to_string<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+    }
+
+    /// Writes `contents` to a file, creating it if it does not exist,
+    /// and replacing its contents if it does.
+    /// This is based on [`fs::write`].
+    ///
+    /// [`fs::write`]: https://doc.rust-lang.org/std/fs/fn.write.html
+    fn write<P, C>(&self, path: P, contents: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        io::Write::write_all(&mut self.create(path)?, contents.as_ref())
+    }
+
+    /// Appends `contents` to a file, creating it if it does not exist.
+    fn append<P, C>(&self, path: P, contents: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        let options = OpenOptions::new().create(true).append(true).write(true);
+        io::Write::write_all(&mut self.open_with_options(path, &options)?, contents.as_ref())
+    }
+
+    /// Writes `contents` to a file like [`write`] does, then makes sure both the file
+    /// and its name in the parent directory survive a crash, by syncing the file
+    /// and then the directory.
+    ///
+    /// [`write`]: #method.write
+    fn create_durable<P, C>(&self, path: P, contents: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        let path = path.as_ref();
+        let mut file = self.create(path)?;
+        io::Write::write_all(&mut file, contents.as_ref())?;
+        file.sync_all()?;
+        self.sync_dir(parent_dir(path))
+    }
+
+    /// Syncs the entries of the directory at `path` to disk, so that nodes
+    /// created in, renamed into, or removed from it stay that way after a crash.
+    ///
+    /// Only unix can sync a directory. Elsewhere, this merely checks that `path`
+    /// is a directory.
+    fn sync_dir<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+
     /// Opens a file at path with the options specified by self.
     /// This is based on [`fs::OpenOptions::open`].
     ///
-    /// On the FakeFileSystem, currently the only supported options are:
-    /// - `new().create(true).write(true).truncate(true)`, equivalent to `File::create`
-    /// - `new().read(true)`, equivalent to `File::open`
-    /// - `new().write(true)`
+    /// Invalid combinations of options, such as `truncate` without `write`,
+    /// are rejected with [`io::ErrorKind::InvalidInput`], as on unix.
+    /// Opening a directory for writing or appending fails with [`io::ErrorKind::IsADirectory`].
+    /// So does creating a file at a path ending in a separator, which names a directory,
+    /// while opening an existing file at such a path fails with `NotADirectory`.
     ///
     /// [`fs::OpenOptions::open`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html#method.open
+    /// [`io::ErrorKind::InvalidInput`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidInput
+    /// [`io::ErrorKind::IsADirectory`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.IsADirectory
     fn open_with_options<P: AsRef<Path>>(&self, path: P, options: &OpenOptions) -> Result<Self::File>;
 
     /// Changes the permissions found on a file or a directory.
@@ -53,12 +181,89 @@ pub trait FileSystem: Clone + Send + Sync {
     /// [`fs::set_permissions`]: https://doc.rust-lang.org/std/fs/fn.set_permissions.html
     fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Self::Permissions) -> Result<()>;
 
+    /// Changes the user and group owning a file or a directory, leaving either
+    /// alone if it is `None`. Symbolic links are followed.
+    /// This is based on [`os::unix::fs::chown`].
+    ///
+    /// On the FakeFileSystem, anyone may change the owner.
+    ///
+    /// [`os::unix::fs::chown`]: https://doc.rust-lang.org/std/os/unix/fs/fn.chown.html
+    #[cfg(unix)]
+    fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<u32>, gid: Option<u32>) -> Result<()>;
+
     /// Given a path, query the file system to get information about a file, directory, etc.
     /// This is based on [`fs::metadata`].
     ///
     /// [`fs::metadata`]: https://doc.rust-lang.org/std/fs/fn.metadata.html
     fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata>;
 
+    /// Queries the metadata about a file without following symbolic links.
+    /// This is based on [`fs::symlink_metadata`].
+    ///
+    /// [`fs::symlink_metadata`]: https://doc.rust-lang.org/std/fs/fn.symlink_metadata.html
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata>;
+
+    /// Returns the kind of the node at `path`, without following symbolic links,
+    /// so a link is reported as such rather than as the kind of its target.
+    /// This is based on [`fs::symlink_metadata`] and [`fs::Metadata::file_type`].
+    ///
+    /// [`fs::symlink_metadata`]: https://doc.rust-lang.org/std/fs/fn.symlink_metadata.html
+    /// [`fs::Metadata::file_type`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.file_type
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<NodeKind> {
+        self.symlink_metadata(path).map(|metadata| metadata.kind())
+    }
+
+    /// Returns the length and modification time of a file, which together tell
+    /// whether it probably changed, as rsync's quick check does.
+    /// Both come from a single call to [`metadata`].
+    ///
+    /// [`metadata`]: #tymethod.metadata
+    fn sig<P: AsRef<Path>>(&self, path: P) -> Result<(u64, SystemTime)> {
+        let metadata = self.metadata(path)?;
+        Ok((metadata.len(), metadata.modified()?))
+    }
+
+    /// Returns a token which changes whenever the contents of the file at `path` change,
+    /// and which stays the same otherwise.
+    ///
+    /// On the OsFileSystem, the token is derived from the modification time and length of
+    /// the file, so changes which preserve the length and happen within the granularity of
+    /// the file system's timestamps may go unnoticed.
+    fn change_token<P: AsRef<Path>>(&self, path: P) -> Result<u64>;
+
+    /// Replaces the contents of the file at `path` with `contents`, but only if its
+    /// [`change_token`] still equals `expected_token`.
+    /// Returns false, without writing, if the file changed in the meantime.
+    ///
+    /// On the FakeFileSystem, the check and the write happen atomically.
+    /// On the OsFileSystem, they do not.
+    ///
+    /// [`change_token`]: #tymethod.change_token
+    fn write_if_unchanged<P, C>(&self, path: P, expected_token: u64, contents: C) -> Result<bool>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>;
+
+    /// Writes each of `writes`, as with [`write`]: the contents to the path,
+    /// in order.
+    ///
+    /// On the FakeFileSystem, this is all or nothing: if any write would fail,
+    /// because its file is read-only or its parent does not exist for example,
+    /// none of the files change. On the OsFileSystem, the files are written one
+    /// after the other, and those written before a failure stay written.
+    ///
+    /// [`write`]: #method.write
+    fn write_batch(&self, writes: &[(PathBuf, Vec<u8>)]) -> Result<()>;
+
+    /// Truncates or extends the file at `path` to `size` bytes.
+    /// This is based on opening the file for writing and calling [`fs::File::set_len`].
+    ///
+    /// Fails with [`io::ErrorKind::PermissionDenied`] if the file is read-only.
+    ///
+    /// [`fs::File::set_len`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.set_len
+    /// [`io::ErrorKind::PermissionDenied`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.PermissionDenied
+    fn set_len<P: AsRef<Path>>(&self, path: P, size: u64) -> Result<()>;
+
     /// Returns the current working directory.
     /// This is based on [`std::env::current_dir`].
     ///
@@ -70,6 +275,21 @@ pub trait FileSystem: Clone + Send + Sync {
     /// [`std::env::set_current_dir`]: https://doc.rust-lang.org/std/env/fn.set_current_dir.html
     fn set_current_dir<P: AsRef<Path>>(&self, path: P) -> Result<()>;
 
+    /// Returns true if the path points at an existing node.
+    /// This is based on [`std::path::Path::exists`]
+    ///
+    /// [`std::path::Path::exists`]: https://doc.rust-lang.org/std/path/struct.Path.html#method.exists
+    fn exists<P: AsRef<Path>>(&self, path: P) -> bool;
+
+    /// Returns `Ok(true)` if the path points at an existing node, and `Ok(false)`
+    /// if it does not. Unlike [`exists`], errors other than the node not being
+    /// there, such as `PermissionDenied` for a parent directory, are returned.
+    /// This is based on [`std::path::Path::try_exists`]
+    ///
+    /// [`exists`]: #tymethod.exists
+    /// [`std::path::Path::try_exists`]: https://doc.rust-lang.org/std/path/struct.Path.html#method.try_exists
+    fn try_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool>;
+
     /// Returns true if the path exists on disk and is pointing at a directory.
     /// This is based on [`std::path::Path::is_dir`]
     ///
@@ -82,14 +302,91 @@ pub trait FileSystem: Clone + Send + Sync {
     /// [`std::path::Path::is_file`]: https://doc.rust-lang.org/std/path/struct.Path.html#method.is_file
     fn is_file<P: AsRef<Path>>(&self, path: P) -> bool;
 
+    /// Returns whether the node at `path` is hidden. On Windows, this is the case
+    /// if it has the hidden attribute, elsewhere if its name starts with a dot.
+    /// A symbolic link is not followed.
+    ///
+    /// Fails if there is no node at `path`.
+    fn is_hidden<P: AsRef<Path>>(&self, path: P) -> Result<bool>;
+
+    /// Returns whether the permissions of the node at `path` allow reading it,
+    /// without opening it. On unix, this is the case if any of its read bits
+    /// is set, whoever owns it. Elsewhere, every node is readable.
+    /// A symbolic link is followed.
+    ///
+    /// Fails if there is no node at `path`.
+    fn is_readable<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let permissions = self.metadata(path)?.permissions();
+        #[cfg(unix)]
+        {
+            Ok(permissions.mode() & 0o444 != 0)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = permissions;
+            Ok(true)
+        }
+    }
+
+    /// Returns whether the permissions of the node at `path` allow writing it,
+    /// without opening it. On unix, this is the case if any of its write bits
+    /// is set, whoever owns it. Elsewhere, it is if the node is not read-only.
+    /// A symbolic link is followed.
+    ///
+    /// Fails if there is no node at `path`.
+    fn is_writable<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let permissions = self.metadata(path)?.permissions();
+        #[cfg(unix)]
+        {
+            Ok(permissions.mode() & 0o222 != 0)
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(!permissions.readonly())
+        }
+    }
+
+    /// Returns whether a file could be written at `path`, without trying to.
+    ///
+    /// If a file is at `path`, this is whether [`is_writable`] holds for it.
+    /// If nothing is, it is whether a file could be created there, ie. whether
+    /// [`is_writable`] holds for the directory which would hold it. A directory
+    /// at `path`, or a parent which is not a directory, can never be written.
+    ///
+    /// Fails if neither `path` nor its parent exists.
+    ///
+    /// The FakeFileSystem also answers false while [`set_readonly_fs`] is in effect.
+    ///
+    /// [`is_writable`]: #method.is_writable
+    /// [`set_readonly_fs`]: struct.FakeFileSystem.html#method.set_readonly_fs
+    fn writable<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref();
+        match self.metadata(path) {
+            Ok(metadata) => Ok(!metadata.is_dir() && self.is_writable(path)?),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                let parent = parent_dir(path);
+                Ok(self.metadata(parent)?.is_dir() && self.is_writable(parent)?)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Creates a new directory.
     /// This is based on [`std::fs::create_dir`].
     ///
     /// [`std::fs::create_dir`]: https://doc.rust-lang.org/std/fs/fn.create_dir.html
     fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()>;
     /// Recursively creates a directory and any missing parents.
-    /// This is based on [`std::fs::create_dir`].
+    /// This is based on [`std::fs::create_dir_all`].
+    ///
+    /// If a file is in the way, this fails with [`io::ErrorKind::AlreadyExists`] when the file
+    /// is at `path` itself, and with [`io::ErrorKind::NotADirectory`] when it is at one of the
+    /// parents. No directories are created in that case.
+    ///
+    /// Symbolic links to directories are followed, both at `path` and in its parents.
     ///
+    /// [`io::ErrorKind::AlreadyExists`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.AlreadyExists
+    /// [`io::ErrorKind::NotADirectory`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.NotADirectory
     /// [`std::fs::create_dir_all`]: https://doc.rust-lang.org/std/fs/fn.create_dir_all.html
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()>;
     /// Removes an empty directory.
@@ -97,31 +394,167 @@ pub trait FileSystem: Clone + Send + Sync {
     ///
     /// [`std::fs::remove_dir`]: https://doc.rust-lang.org/std/fs/fn.remove_dir.html
     fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+    /// Removes an empty directory, and then each of its ancestors which is left empty,
+    /// like `rmdir -p`. Stops at the first ancestor which can not be removed, eg. because
+    /// it is not empty, or at the root.
+    fn remove_dir_and_empty_parents<P: AsRef<Path>>(&self, path: P) -> Result<()>;
     /// Removes a directory and any child files or directories.
     /// This is based on [`std::fs::remove_dir_all`].
     ///
     /// [`std::fs::remove_dir_all`]: https://doc.rust-lang.org/std/fs/fn.remove_dir_all.html
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+    /// Removes a directory and any child files or directories, like [`remove_dir_all`],
+    /// and returns the total length of the files removed.
+    ///
+    /// A file with several hard links in the tree counts once, except on the
+    /// OsFileSystem outside unix. Removing a symbolic link frees nothing.
+    ///
+    /// [`remove_dir_all`]: #tymethod.remove_dir_all
+    fn remove_dir_all_report<P: AsRef<Path>>(&self, path: P) -> Result<u64>;
     /// Returns an iterator over the entries in a directory.
     /// This is based on [`std::fs::read_dir`].
     ///
+    /// The FakeFileSystem returns the entries sorted by file name, so listings are
+    /// reproducible. The OsFileSystem returns them in whatever order the OS does.
+    ///
+    /// The FakeFileSystem lists the names in the directory when it is read, without
+    /// holding on to the file system while iterating. Entries created or removed
+    /// meanwhile are therefore not reflected in the listing, which the OsFileSystem
+    /// leaves unspecified.
+    ///
     /// [`std::fs::read_dir`]: https://doc.rust-lang.org/std/fs/fn.read_dir.html
     fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadDir>;
 
+    /// Returns an iterator over all entries below a directory, depth-first.
+    /// Every directory is returned before the entries in it.
+    ///
+    /// Symbolic links are returned, but not followed, so links to directories
+    /// cannot cause cycles. Entries within a directory are ordered as by [`read_dir`].
+    ///
+    /// [`read_dir`]: #tymethod.read_dir
+    fn walk_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::WalkDir>;
+    /// Returns the names and kinds of the entries in a directory.
+    ///
+    /// Unlike [`read_dir`], this does not build a full path for every entry,
+    /// which makes it cheaper for listing large directories.
+    /// Symbolic links are reported as such, rather than as the kind of their target.
+    /// The entries are ordered as by [`read_dir`].
+    ///
+    /// [`read_dir`]: #tymethod.read_dir
+    fn read_dir_names<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(OsString, NodeKind)>>;
+
+    /// Returns whether the directory at `path` has no entries.
+    ///
+    /// This stops at the first entry, which makes it cheaper than counting
+    /// the entries of a large directory, for example before [`remove_dir`].
+    ///
+    /// [`remove_dir`]: #tymethod.remove_dir
+    fn dir_is_empty<P: AsRef<Path>>(&self, path: P) -> Result<bool>;
+
+    /// Returns the paths of the entries in a directory whose names match
+    /// the shell-style `pattern`.
+    ///
+    /// In the pattern, `*` matches any run of characters, `?` any single character,
+    /// and `[...]` any character of a class such as `[abc]` or `[a-z]`, or, when it
+    /// starts with `!`, any character not in it. A `[` which is never closed matches itself.
+    /// Names which are not valid Unicode never match.
+    /// The entries are ordered as by [`read_dir`].
+    ///
+    /// [`read_dir`]: #tymethod.read_dir
+    fn read_dir_glob<P: AsRef<Path>, S: AsRef<str>>(&self, dir: P, pattern: S) -> Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        let pattern: Vec<char> = pattern.as_ref().chars().collect();
+        Ok(self.read_dir_names(dir)?
+            .into_iter()
+            .filter(|(name, _)| name.to_str().is_some_and(|name| glob_matches(&pattern, name)))
+            .map(|(name, _)| dir.join(name))
+            .collect())
+    }
+
     /// Removes the file at `path`.
     /// This is based on [`std::fs::remove_file`].
     ///
     /// [`std::fs::remove_file`]: https://doc.rust-lang.org/std/fs/fn.remove_file.html
     fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()>;
-    /// Copies the file at path `from` to the path `to`.
+    /// Copies the file at path `from` to the path `to`, including its permissions.
+    /// Returns the number of bytes copied.
     /// This is based on [`std::fs::copy`].
     ///
     /// [`std::fs::copy`]: https://doc.rust-lang.org/std/fs/fn.copy.html
-    fn copy_file<P, Q>(&self, from: P, to: Q) -> Result<()>
+    fn copy_file<P, Q>(&self, from: P, to: Q) -> Result<u64>
     where
         P: AsRef<Path>,
         Q: AsRef<Path>;
 
+    /// Like [`copy_file`], but then reads back both files and fails with
+    /// [`io::ErrorKind::InvalidData`] if their contents differ.
+    ///
+    /// [`copy_file`]: #tymethod.copy_file
+    /// [`io::ErrorKind::InvalidData`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidData
+    fn copy_file_verified<P, Q>(&self, from: P, to: Q) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let len = self.copy_file(&from, &to)?;
+        if !readers_equal(self.open(&from)?, self.open(&to)?)? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "copy differs from its source"));
+        }
+        Ok(len)
+    }
+
+    /// Recursively copies the directory at `from`, and everything in it, to `to`.
+    /// Returns the total number of bytes copied.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if `from` is not a directory, or if `to`
+    /// is `from` or lies inside it, as the copy would then never end.
+    /// Symbolic links are copied as links on unix, and followed elsewhere.
+    ///
+    /// [`io::ErrorKind::InvalidInput`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidInput
+    fn copy_dir_all<P, Q>(&self, from: P, to: Q) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        copy_dir(self, from.as_ref(), to.as_ref(), false)
+    }
+
+    /// Like [`copy_dir_all`], but also gives every file and directory it copies
+    /// the user and group owning the original, with [`chown`]. The links it
+    /// copies are owned by whoever copies them.
+    ///
+    /// [`copy_dir_all`]: #method.copy_dir_all
+    /// [`chown`]: #tymethod.chown
+    #[cfg(unix)]
+    fn copy_dir_all_preserving_owners<P, Q>(&self, from: P, to: Q) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        copy_dir(self, from.as_ref(), to.as_ref(), true)
+    }
+
+    /// Returns the groups of files below `root` which have identical contents.
+    /// Files are sorted within a group, and groups are sorted by their first file.
+    ///
+    /// Only files of equal size have their contents hashed, and only files of equal
+    /// hash are then compared byte by byte.
+    fn find_duplicates<P: AsRef<Path>>(&self, root: P) -> Result<Vec<Vec<PathBuf>>> {
+        let mut files = vec![];
+        for entry in self.walk_dir(root)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                files.push((entry.path(), metadata.len()));
+            }
+        }
+        group_duplicates(
+            files,
+            |path| Ok(hash_contents(&self.read(path)?)),
+            |a, b| readers_equal(self.open(a)?, self.open(b)?),
+        )
+    }
+
     /// Renames a file or directory.
     /// If both `from` and `to` are files, `to` will be replaced.
     /// Based on [`std::fs::rename`].
@@ -132,12 +565,94 @@ pub trait FileSystem: Clone + Send + Sync {
         P: AsRef<Path>,
         Q: AsRef<Path>;
 
+    /// Renames a file or directory, like [`rename`], but fails with
+    /// [`io::ErrorKind::AlreadyExists`] if `to` exists, whatever it is.
+    ///
+    /// On Linux, the OsFileSystem checks and renames in one step, using
+    /// `renameat2` with `RENAME_NOREPLACE`. Elsewhere, or on file systems which
+    /// do not support that, it checks before renaming, so another process may
+    /// still create `to` in between.
+    ///
+    /// [`rename`]: #tymethod.rename
+    /// [`io::ErrorKind::AlreadyExists`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.AlreadyExists
+    fn rename_noreplace<P, Q>(&self, from: P, to: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>;
+
+    /// Moves everything in the directory at `from` into the directory at `to`,
+    /// then removes `from`. Unlike [`rename`], `to` may already have entries:
+    /// directories present in both are merged in turn, and any other entry in
+    /// both fails with [`io::ErrorKind::AlreadyExists`]. If `overwrite` is set, a file
+    /// or link from `from` replaces a file or link of the same name in `to` instead.
+    ///
+    /// If `to` does not exist, this is the same as [`rename`]. On failure, the
+    /// entries moved so far stay moved.
+    ///
+    /// [`rename`]: #tymethod.rename
+    /// [`io::ErrorKind::AlreadyExists`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.AlreadyExists
+    fn merge_dir<P, Q>(&self, from: P, to: Q, overwrite: bool) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        merge_dir(self, from.as_ref(), to.as_ref(), overwrite)
+    }
+
+    /// Creates a new hard link at `dst`, pointing to the same file as `src`.
+    /// Writes through either path are visible through the other one.
+    /// This is based on [`std::fs::hard_link`].
+    ///
+    /// [`std::fs::hard_link`]: https://doc.rust-lang.org/std/fs/fn.hard_link.html
+    fn hard_link<P, Q>(&self, src: P, dst: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>;
+
     /// Returns the canonical, absolute form of a path with all intermediate components
     /// normalized and symbolic links resolved.
     /// This is based on [`fs::canonicalize`].
     ///
     /// [`fs::canonicalize`]: https://doc.rust-lang.org/std/fs/fn.canonicalize.html
     fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf>;
+
+    /// Like [`canonicalize`], but the final component of `path` need not exist.
+    ///
+    /// Everything but the final component is canonicalized, and must exist. A
+    /// missing final component is then appended as is, which suits the
+    /// destination of a planned write.
+    ///
+    /// [`canonicalize`]: #tymethod.canonicalize
+    fn canonicalize_lenient<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        let path = path.as_ref();
+        match self.canonicalize(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            result => return result,
+        }
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) if parent.as_os_str().is_empty() => {
+                Ok(self.current_dir()?.join(name))
+            }
+            (Some(parent), Some(name)) => Ok(self.canonicalize(parent)?.join(name)),
+            _ => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    /// Reads a symbolic link, returning the path it points to, exactly as it was created.
+    /// This is based on [`fs::read_link`].
+    ///
+    /// [`fs::read_link`]: https://doc.rust-lang.org/std/fs/fn.read_link.html
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf>;
+
+    /// Creates a new symbolic link at `link`, pointing to `original`.
+    /// This is based on [`os::unix::fs::symlink`].
+    ///
+    /// [`os::unix::fs::symlink`]: https://doc.rust-lang.org/std/os/unix/fs/fn.symlink.html
+    #[cfg(unix)]
+    fn symlink<P, Q>(&self, original: P, link: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>;
 }
 
 /// Entries returned by the ReadDir iterator.
@@ -145,6 +660,9 @@ pub trait FileSystem: Clone + Send + Sync {
 ///
 /// [`fs::DirEntry`]: https://doc.rust-lang.org/std/fs/struct.DirEntry.html
 pub trait DirEntry {
+    type Metadata: Metadata;
+    type FileType: FileType;
+
     /// Returns the bare file name of this directory entry without any other leading path component.
     /// This is based on [`fs::DirEntry::file_name`].
     ///
@@ -156,10 +674,26 @@ pub trait DirEntry {
     ///
     /// [`fs::DirEntry::path`]: https://doc.rust-lang.org/std/fs/struct.DirEntry.html#method.path
     fn path(&self) -> PathBuf;
+
+    /// Returns the metadata for the file that this entry represents,
+    /// without following a symbolic link.
+    /// This is based on [`fs::DirEntry::metadata`].
+    ///
+    /// [`fs::DirEntry::metadata`]: https://doc.rust-lang.org/std/fs/struct.DirEntry.html#method.metadata
+    fn metadata(&self) -> Result<Self::Metadata>;
+
+    /// Returns the type of the file that this entry represents,
+    /// without following a symbolic link.
+    /// This is based on [`fs::DirEntry::file_type`].
+    ///
+    /// [`fs::DirEntry::file_type`]: https://doc.rust-lang.org/std/fs/struct.DirEntry.html#method.file_type
+    fn file_type(&self) -> Result<Self::FileType>;
 }
 
 pub trait ReadDir<T: DirEntry>: Iterator<Item = Result<T>> {}
 
+pub trait WalkDir<T: DirEntry>: Iterator<Item = Result<T>> {}
+
 /// Provides functions which are not modelled as traits in [`fs::File`]
 ///
 /// [`fs::File`]: https://doc.rust-lang.org/std/fs/struct.File.html
@@ -169,12 +703,20 @@ pub trait FileExt {
     /// Queries metadata about the underlying file.
     /// This is based on [`fs::File::metadata`].
     ///
+    /// Unlike [`FileSystem::metadata`], this keeps working after the file is removed,
+    /// and goes on reporting the length and permissions of the file it was opened on,
+    /// including writes made through this file since.
+    ///
     /// [`fs::File::metadata`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.metadata
+    /// [`FileSystem::metadata`]: trait.FileSystem.html#tymethod.metadata
     fn metadata(&self) -> Result<Self::Metadata>;
 
     /// Truncates or extends the underlying file, updating the size of this file to become size.
     /// This is based on [`fs::File::set_len`]
     ///
+    /// Only the way the file was opened matters, as with a file descriptor: a file opened
+    /// for writing can still be resized after its permissions were made read-only.
+    ///
     /// [`fs::File::set_len`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.set_len
     fn set_len(&self, size: u64) -> Result<()>;
 
@@ -190,6 +732,111 @@ pub trait FileExt {
     ///
     /// [`fs::File::sync_data`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.sync_data
     fn sync_data(&self) -> Result<()>;
+
+    /// Changes the timestamps of the underlying file.
+    /// Timestamps which are not set in `times` are left unchanged.
+    /// This is based on [`fs::File::set_times`]
+    ///
+    /// [`fs::File::set_times`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.set_times
+    fn set_times(&self, times: FileTimes) -> Result<()>;
+
+    /// Returns whether the contents or length of the underlying file changed since it was
+    /// opened, whether through this file, another file, or a path.
+    ///
+    /// The FakeFileSystem compares the version of the contents, see [`change_token`].
+    /// The OsFileSystem compares the length and modification time the file had when it was
+    /// opened, so a change which keeps the length within one tick of the file system's
+    /// timestamps goes unnoticed. A bare [`fs::File`] does not remember the state of the
+    /// file when it was opened, so it fails with [`io::ErrorKind::Unsupported`].
+    ///
+    /// [`change_token`]: trait.FileSystem.html#tymethod.change_token
+    /// [`fs::File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+    /// [`io::ErrorKind::Unsupported`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.Unsupported
+    fn has_changed_since_open(&self) -> Result<bool>;
+
+    /// Creates a new handle to the underlying file.
+    /// This is based on [`fs::File::try_clone`].
+    ///
+    /// The FakeFileSystem's clones share the contents of the file, but each has a cursor
+    /// of its own. The OsFileSystem's clones also share the cursor, as std's do.
+    ///
+    /// [`fs::File::try_clone`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.try_clone
+    fn try_clone(&self) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Reads into `buf` from the given offset, returning the number of bytes read.
+    /// The cursor of the file is neither used nor moved.
+    /// This is based on [`std::os::unix::fs::FileExt::read_at`].
+    ///
+    /// [`std::os::unix::fs::FileExt::read_at`]: https://doc.rust-lang.org/std/os/unix/fs/trait.FileExt.html#tymethod.read_at
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize>;
+
+    /// Writes `buf` at the given offset, returning the number of bytes written.
+    /// The cursor of the file is neither used nor moved. Writing beyond the end
+    /// of the file fills the gap with zeros.
+    /// This is based on [`std::os::unix::fs::FileExt::write_at`].
+    ///
+    /// [`std::os::unix::fs::FileExt::write_at`]: https://doc.rust-lang.org/std/os/unix/fs/trait.FileExt.html#tymethod.write_at
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize>;
+}
+
+/// The kind of a node in a file system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Dir,
+    Symlink,
+    /// A named pipe, on unix.
+    Fifo,
+    /// A unix domain socket.
+    Socket,
+    /// A character device, on unix.
+    CharDevice,
+    /// A block device, on unix.
+    BlockDevice,
+    /// Anything else, which none of the other kinds describe.
+    Other,
+}
+
+/// The type of a file.
+/// This is based on [`fs::FileType`].
+///
+/// [`fs::FileType`]: https://doc.rust-lang.org/std/fs/struct.FileType.html
+pub trait FileType: fmt::Debug {
+    /// Returns true if this file type is a directory.
+    /// This is based on [`fs::FileType::is_dir`].
+    ///
+    /// [`fs::FileType::is_dir`]: https://doc.rust-lang.org/std/fs/struct.FileType.html#method.is_dir
+    fn is_dir(&self) -> bool;
+
+    /// Returns true if this file type is a regular file.
+    /// This is based on [`fs::FileType::is_file`].
+    ///
+    /// [`fs::FileType::is_file`]: https://doc.rust-lang.org/std/fs/struct.FileType.html#method.is_file
+    fn is_file(&self) -> bool;
+
+    /// Returns true if this file type is a symbolic link.
+    /// This is based on [`fs::FileType::is_symlink`].
+    ///
+    /// [`fs::FileType::is_symlink`]: https://doc.rust-lang.org/std/fs/struct.FileType.html#method.is_symlink
+    fn is_symlink(&self) -> bool;
+}
+
+impl FileType for NodeKind {
+    fn is_dir(&self) -> bool {
+        *self == NodeKind::Dir
+    }
+
+    fn is_file(&self) -> bool {
+        *self == NodeKind::File
+    }
+
+    fn is_symlink(&self) -> bool {
+        *self == NodeKind::Symlink
+    }
 }
 
 /// Metadata information about a file.
@@ -212,7 +859,22 @@ pub trait Metadata: fmt::Debug {
     /// [`fs::Metadata::is_file`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.is_file
     fn is_file(&self) -> bool;
 
+    /// Returns true if this metadata is for a symbolic link.
+    /// This is based on [`fs::Metadata::is_symlink`].
+    ///
+    /// [`fs::Metadata::is_symlink`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.is_symlink
+    fn is_symlink(&self) -> bool;
+
+    /// Returns the kind of node this metadata is for, which unlike [`is_dir`],
+    /// [`is_file`] and [`is_symlink`] can be matched on exhaustively.
+    ///
+    /// [`is_dir`]: #tymethod.is_dir
+    /// [`is_file`]: #tymethod.is_file
+    /// [`is_symlink`]: #tymethod.is_symlink
+    fn kind(&self) -> NodeKind;
+
     /// Returns the size of the file, in bytes, this metadata is for.
+    /// For a symbolic link, this is the length of the path it points to.
     /// This is based on [`fs::Metadata::len`].
     ///
     /// [`fs::Metadata::len`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.len
@@ -223,6 +885,64 @@ pub trait Metadata: fmt::Debug {
     ///
     /// [`fs::Metadata::permissions`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html?search=#method.permissions
     fn permissions(&self) -> Self::Permissions;
+
+    /// Returns the last modification time listed in this metadata.
+    /// This is based on [`fs::Metadata::modified`].
+    ///
+    /// [`fs::Metadata::modified`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.modified
+    fn modified(&self) -> Result<SystemTime>;
+
+    /// Returns the last access time listed in this metadata.
+    /// This is based on [`fs::Metadata::accessed`].
+    ///
+    /// On the FakeFileSystem, a file is accessed when it is opened for reading, or read from.
+    ///
+    /// [`fs::Metadata::accessed`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.accessed
+    fn accessed(&self) -> Result<SystemTime>;
+
+    /// Returns the creation time listed in this metadata.
+    /// This is based on [`fs::Metadata::created`].
+    ///
+    /// On the OsFileSystem, this fails on platforms or file systems which do not record it.
+    /// On the FakeFileSystem, it always succeeds.
+    ///
+    /// [`fs::Metadata::created`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.created
+    fn created(&self) -> Result<SystemTime>;
+
+    /// Returns true if this metadata is for a sparse file, ie. a file where
+    /// fewer bytes are allocated than its length suggests.
+    /// This is based on comparing [`os::unix::fs::MetadataExt::blocks`] with the length.
+    ///
+    /// [`os::unix::fs::MetadataExt::blocks`]: https://doc.rust-lang.org/std/os/unix/fs/trait.MetadataExt.html#tymethod.blocks
+    #[cfg(unix)]
+    fn is_sparse(&self) -> bool;
+
+    /// Returns the user ID of the owner of the node this metadata is for.
+    /// This is based on [`os::unix::fs::MetadataExt::uid`].
+    ///
+    /// On the FakeFileSystem, nodes are owned by root until changed with [`chown`].
+    ///
+    /// [`os::unix::fs::MetadataExt::uid`]: https://doc.rust-lang.org/std/os/unix/fs/trait.MetadataExt.html#tymethod.uid
+    /// [`chown`]: trait.FileSystem.html#tymethod.chown
+    #[cfg(unix)]
+    fn uid(&self) -> u32;
+
+    /// Returns the group ID of the owner of the node this metadata is for.
+    /// This is based on [`os::unix::fs::MetadataExt::gid`].
+    ///
+    /// [`os::unix::fs::MetadataExt::gid`]: https://doc.rust-lang.org/std/os/unix/fs/trait.MetadataExt.html#tymethod.gid
+    #[cfg(unix)]
+    fn gid(&self) -> u32;
+
+    /// Returns the inode number of the node this metadata is for.
+    /// This is based on [`os::unix::fs::MetadataExt::ino`].
+    ///
+    /// Hard links to a file share its inode number, while distinct nodes differ.
+    /// On the FakeFileSystem, each node is numbered when it is created.
+    ///
+    /// [`os::unix::fs::MetadataExt::ino`]: https://doc.rust-lang.org/std/os/unix/fs/trait.MetadataExt.html#tymethod.ino
+    #[cfg(unix)]
+    fn ino(&self) -> u64;
 }
 
 /// Representation of the various permissions on a file.
@@ -290,9 +1010,15 @@ pub struct OpenOptions {
     append: bool,
     create: bool,
     create_new: bool,
+    create_parents: bool,
+    exclusive_write: bool,
     read: bool,
     truncate: bool,
     write: bool,
+    // None unless set, as files are opened this way either way
+    cloexec: Option<bool>,
+    #[cfg(unix)]
+    mode: Option<u32>,
 }
 
 impl OpenOptions {
@@ -303,6 +1029,10 @@ impl OpenOptions {
     /// Sets the option for the append mode.
     /// This is based on [`fs::OpenOptions::append`].
     ///
+    /// Reads and seeks share a single cursor with writes. Every write goes to
+    /// the end of the file, and leaves the cursor at the new end of the file,
+    /// as on Linux. A read following a write will therefore return no data.
+    ///
     /// [`fs::OpenOptions::append`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html#method.append
     pub fn append(mut self, append: bool) -> Self {
         self.append = append;
@@ -353,4 +1083,351 @@ impl OpenOptions {
         self.write = write;
         self
     }
+
+    /// Sets the mode bits that a new file will be created with.
+    /// This is based on [`os::unix::fs::OpenOptionsExt::mode`].
+    ///
+    /// [`os::unix::fs::OpenOptionsExt::mode`]: https://doc.rust-lang.org/std/os/unix/fs/trait.OpenOptionsExt.html#tymethod.mode
+    #[cfg(unix)]
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets the option for creating missing parent directories.
+    /// This is an extension of this crate and has no counterpart in [`fs::OpenOptions`].
+    ///
+    /// When a file is opened with `create` or `create_new`, its missing parent
+    /// directories are first created, as if by [`FileSystem::create_dir_all`].
+    ///
+    /// [`fs::OpenOptions`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html
+    pub fn create_parents(mut self, create_parents: bool) -> Self {
+        self.create_parents = create_parents;
+        self
+    }
+
+    /// Sets the option for closing the file when the process executes another program,
+    /// like `O_CLOEXEC`.
+    /// This is an extension of this crate and has no counterpart in [`fs::OpenOptions`].
+    ///
+    /// Like std, both the OsFileSystem and the FakeFileSystem always open files this way,
+    /// so `true` is merely accepted, for code ported from C that sets it. With `false`,
+    /// opening fails with [`io::ErrorKind::Unsupported`], as std offers no way to keep
+    /// a file open across an exec.
+    ///
+    /// [`fs::OpenOptions`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html
+    /// [`io::ErrorKind::Unsupported`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.Unsupported
+    pub fn cloexec(mut self, cloexec: bool) -> Self {
+        self.cloexec = Some(cloexec);
+        self
+    }
+
+    /// Sets the option for refusing to write to a file which is already open for writing.
+    /// This is an extension of this crate and has no counterpart in [`fs::OpenOptions`].
+    ///
+    /// On the FakeFileSystem, opening a file for writing with this option fails with
+    /// [`io::ErrorKind::WouldBlock`] while any other handle which may write to it is
+    /// open, which models exclusive writer protocols. Handles opened without the option
+    /// are not refused. The OsFileSystem cannot tell, and fails with
+    /// [`io::ErrorKind::Unsupported`] instead.
+    ///
+    /// [`fs::OpenOptions`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html
+    /// [`io::ErrorKind::WouldBlock`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.WouldBlock
+    /// [`io::ErrorKind::Unsupported`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.Unsupported
+    pub fn exclusive_write(mut self, exclusive_write: bool) -> Self {
+        self.exclusive_write = exclusive_write;
+        self
+    }
+}
+
+/// Representation of the various timestamps on a file.
+/// This is based on [`fs::FileTimes`].
+///
+/// [`fs::FileTimes`]: https://doc.rust-lang.org/std/fs/struct.FileTimes.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FileTimes {
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+}
+
+impl FileTimes {
+    /// Constructs a FileTimes with no timestamps set.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the last access time of a file.
+    /// This is based on [`fs::FileTimes::set_accessed`].
+    ///
+    /// [`fs::FileTimes::set_accessed`]: https://doc.rust-lang.org/std/fs/struct.FileTimes.html#method.set_accessed
+    pub fn set_accessed(mut self, t: SystemTime) -> Self {
+        self.accessed = Some(t);
+        self
+    }
+
+    /// Sets the last modified time of a file.
+    /// This is based on [`fs::FileTimes::set_modified`].
+    ///
+    /// [`fs::FileTimes::set_modified`]: https://doc.rust-lang.org/std/fs/struct.FileTimes.html#method.set_modified
+    pub fn set_modified(mut self, t: SystemTime) -> Self {
+        self.modified = Some(t);
+        self
+    }
+}
+
+// The directory holding the node at path, which for a bare name is the current one.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+// Whether the last component of path names a hidden node outside Windows.
+fn has_hidden_name(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+// Whether name matches the shell-style pattern, as read_dir_glob describes.
+fn glob_matches(pattern: &[char], name: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // where to resume after the last `*`, should the rest fail to match
+    let mut backtrack = None;
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match glob_class(&pattern[p..], name[n]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                None if name[n] == '[' => Some(1),
+                None => None,
+            },
+            Some(&c) if c == name[n] => Some(1),
+            _ => None,
+        };
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((star_p, star_n))) => {
+                backtrack = Some((star_p, star_n + 1));
+                p = star_p;
+                n = star_n + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Matches c against the character class at the start of pattern, returning
+// whether it matched and the length of the class, or None if it is never closed.
+fn glob_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = pattern.get(i) == Some(&'!');
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let start = i;
+    loop {
+        match *pattern.get(i)? {
+            // a `]` right at the start is part of the class
+            ']' if i > start => return Some((matched != negated, i + 1)),
+            low => match (pattern.get(i + 1), pattern.get(i + 2)) {
+                (Some('-'), Some(&high)) if high != ']' => {
+                    matched |= low <= c && c <= high;
+                    i += 3;
+                }
+                _ => {
+                    matched |= low == c;
+                    i += 1;
+                }
+            },
+        }
+    }
+}
+
+// Copies the directory tree at from to to, as copy_dir_all does,
+// giving the copies the owners of their originals if preserve_owners is true.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn copy_dir<T: FileSystem>(fs: &T, from: &Path, to: &Path, preserve_owners: bool) -> Result<u64> {
+    if !fs.metadata(from)?.is_dir() {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+    // copying into the copy being made would never end
+    if canonicalize_missing(fs, to)?.starts_with(fs.canonicalize(from)?) {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+    copy_tree(fs, from, to, preserve_owners)
+}
+
+// Canonicalizes path, which may end in any number of components that do not exist yet.
+fn canonicalize_missing<T: FileSystem>(fs: &T, path: &Path) -> Result<PathBuf> {
+    let mut missing = vec![];
+    let mut existing = path;
+    loop {
+        let canonical = if existing.as_os_str().is_empty() {
+            fs.current_dir()
+        } else {
+            fs.canonicalize(existing)
+        };
+        match (canonical, existing.parent(), existing.file_name()) {
+            (Ok(canonical), _, _) => return Ok(missing.iter().rev().fold(canonical, |path, name| path.join(name))),
+            (Err(ref e), Some(parent), Some(name)) if e.kind() == io::ErrorKind::NotFound => {
+                missing.push(name);
+                existing = parent;
+            }
+            (Err(e), _, _) => return Err(e),
+        }
+    }
+}
+
+fn copy_tree<T: FileSystem>(fs: &T, from: &Path, to: &Path, preserve_owners: bool) -> Result<u64> {
+    fs.create_dir_all(to)?;
+
+    let mut copied = 0;
+    for entry in fs.read_dir(from)? {
+        let entry = entry?;
+        let (from, to) = (entry.path(), to.join(entry.file_name()));
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copied += copy_tree(fs, &from, &to, preserve_owners)?;
+        } else if cfg!(unix) && file_type.is_symlink() {
+            #[cfg(unix)]
+            fs.symlink(fs.read_link(from)?, to)?;
+        } else {
+            copied += fs.copy_file(&from, &to)?;
+            #[cfg(unix)]
+            copy_owner(fs, &from, &to, preserve_owners)?;
+        }
+    }
+    #[cfg(unix)]
+    copy_owner(fs, from, to, preserve_owners)?;
+    Ok(copied)
+}
+
+fn merge_dir<T: FileSystem>(fs: &T, from: &Path, to: &Path, overwrite: bool) -> Result<()> {
+    if !fs.symlink_metadata(from)?.is_dir() {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+    match fs.symlink_metadata(to) {
+        Ok(ref metadata) if metadata.is_dir() => {}
+        Ok(_) => return Err(io::Error::from(io::ErrorKind::AlreadyExists)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return fs.rename(from, to),
+        Err(err) => return Err(err),
+    }
+
+    // list the entries up front, as moving them changes the directory
+    let entries = fs.read_dir(from)?.collect::<Result<Vec<_>>>()?;
+    for entry in entries {
+        let (from, to) = (entry.path(), to.join(entry.file_name()));
+        let is_dir = entry.file_type()?.is_dir();
+        match fs.symlink_metadata(&to) {
+            Ok(ref metadata) if is_dir && metadata.is_dir() => {
+                merge_dir(fs, &from, &to, overwrite)?
+            }
+            Ok(ref metadata) if overwrite && !is_dir && !metadata.is_dir() => fs.rename(&from, &to)?,
+            Ok(_) => return Err(io::Error::from(io::ErrorKind::AlreadyExists)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => fs.rename(&from, &to)?,
+            Err(err) => return Err(err),
+        }
+    }
+    fs.remove_dir(from)
+}
+
+#[cfg(unix)]
+fn copy_owner<T: FileSystem>(fs: &T, from: &Path, to: &Path, preserve_owners: bool) -> Result<()> {
+    if preserve_owners {
+        let metadata = fs.metadata(from)?;
+        fs.chown(to, Some(metadata.uid()), Some(metadata.gid()))?;
+    }
+    Ok(())
+}
+
+fn hash_contents(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Reads from both readers till the end, and tells whether they gave the same bytes.
+fn readers_equal<R: io::Read, S: io::Read>(mut a: R, mut b: S) -> Result<bool> {
+    let mut buf_a = [0; 8192];
+    let mut buf_b = [0; 8192];
+    loop {
+        let len = read_full(&mut a, &mut buf_a)?;
+        if len != read_full(&mut b, &mut buf_b)? || buf_a[..len] != buf_b[..len] {
+            return Ok(false);
+        }
+        if len == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+// Reads until buf is full or the reader is exhausted, returning how much was read.
+fn read_full<R: io::Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+// Groups the files with equal contents, given their paths and lengths.
+// Only files of equal length are hashed, and only files of equal hash are
+// compared, so that a hash collision cannot pass for a duplicate.
+fn group_duplicates<F, G>(files: Vec<(PathBuf, u64)>, mut hash: F, mut equal: G) -> Result<Vec<Vec<PathBuf>>>
+where
+    F: FnMut(&Path) -> Result<u64>,
+    G: FnMut(&Path, &Path) -> Result<bool>,
+{
+    let mut by_len: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for (path, len) in files {
+        by_len.entry(len).or_default().push(path);
+    }
+
+    let mut groups = vec![];
+    for paths in by_len.into_values().filter(|paths| paths.len() > 1) {
+        let mut by_hash: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            by_hash.entry(hash(&path)?).or_default().push(path);
+        }
+        for paths in by_hash.into_values().filter(|paths| paths.len() > 1) {
+            let mut by_contents: Vec<Vec<PathBuf>> = vec![];
+            for path in paths {
+                let mut same = None;
+                for (i, group) in by_contents.iter().enumerate() {
+                    if equal(&group[0], &path)? {
+                        same = Some(i);
+                        break;
+                    }
+                }
+                match same {
+                    Some(i) => by_contents[i].push(path),
+                    None => by_contents.push(vec![path]),
+                }
+            }
+            groups.extend(by_contents.into_iter().filter(|paths| paths.len() > 1));
+        }
+    }
+    for group in groups.iter_mut() {
+        group.sort();
+    }
+    groups.sort();
+    Ok(groups)
 }
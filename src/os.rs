@@ -1,12 +1,17 @@
+use std::collections::hash_map::DefaultHasher;
+#[cfg(unix)]
+use std::collections::HashSet;
 use std::env;
 use std::ffi::OsString;
 use std::fs::{self};
-use std::io::{Result};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Result};
 #[cfg(unix)]
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-use super::{DirEntry, FileSystem, ReadDir, FileExt, Metadata, Permissions};
+use super::{DirEntry, FileSystem, FileType, ReadDir, WalkDir, FileExt, FileTimes, Metadata, NodeKind, Permissions};
 #[cfg(feature = "temp")]
 use super::{TempDir, TempFileSystem};
 
@@ -43,27 +48,43 @@ impl OsFileSystem {
 impl FileSystem for OsFileSystem {
     type DirEntry = fs::DirEntry;
     type ReadDir = fs::ReadDir;
-    type File = fs::File;
+    type WalkDir = OsWalkDir;
+    type File = OsFile;
     type Permissions = fs::Permissions;
     type Metadata = fs::Metadata;
 
     fn open<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
-        fs::File::open(path)
+        fs::File::open(path).and_then(OsFile::new)
     }
 
     fn create<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
-        fs::File::create(path)
+        fs::File::create(path).and_then(OsFile::new)
     }
 
     fn open_with_options<P: AsRef<Path>>(&self, path: P, options: &crate::OpenOptions) -> Result<Self::File> {
-        fs::OpenOptions::new()
+        if options.exclusive_write || options.cloexec == Some(false) {
+            return Err(io::Error::from(io::ErrorKind::Unsupported));
+        }
+        let mut os_options = fs::OpenOptions::new();
+        os_options
             .append(options.append)
             .create(options.create)
             .create_new(options.create_new)
             .read(options.read)
             .truncate(options.truncate)
-            .write(options.write)
-            .open(path)
+            .write(options.write);
+        #[cfg(unix)]
+        {
+            if let Some(mode) = options.mode {
+                os_options.mode(mode);
+            }
+        }
+        if options.create_parents && (options.create || options.create_new) {
+            if let Some(parent) = path.as_ref().parent() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        os_options.open(path).and_then(OsFile::new)
     }
 
     fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Self::Permissions) -> Result<()> {
@@ -74,6 +95,10 @@ impl FileSystem for OsFileSystem {
         fs::metadata(path)
     }
 
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        fs::symlink_metadata(path)
+    }
+
     fn current_dir(&self) -> Result<PathBuf> {
         env::current_dir()
     }
@@ -82,6 +107,14 @@ impl FileSystem for OsFileSystem {
         env::set_current_dir(path)
     }
 
+    fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        path.as_ref().exists()
+    }
+
+    fn try_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        path.as_ref().try_exists()
+    }
+
     fn is_dir<P: AsRef<Path>>(&self, path: P) -> bool {
         path.as_ref().is_dir()
     }
@@ -90,6 +123,40 @@ impl FileSystem for OsFileSystem {
         path.as_ref().is_file()
     }
 
+    #[cfg(windows)]
+    fn is_hidden<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+
+        Ok(fs::symlink_metadata(path)?.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+    }
+
+    #[cfg(not(windows))]
+    fn is_hidden<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref();
+        fs::symlink_metadata(path)?;
+        Ok(super::has_hidden_name(path))
+    }
+
+    #[cfg(unix)]
+    fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        std::os::unix::fs::chown(path, uid, gid)
+    }
+
+    #[cfg(unix)]
+    fn sync_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::File::open(path)?.sync_all()
+    }
+
+    #[cfg(not(unix))]
+    fn sync_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if fs::metadata(path)?.is_dir() {
+            Ok(())
+        } else {
+            Err(io::Error::from(io::ErrorKind::NotADirectory))
+        }
+    }
+
     fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         fs::create_dir(path)
     }
@@ -102,24 +169,77 @@ impl FileSystem for OsFileSystem {
         fs::remove_dir(path)
     }
 
+    fn remove_dir_and_empty_parents<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        fs::remove_dir(path)?;
+        for ancestor in path.ancestors().skip(1).filter(|a| a.parent().is_some()) {
+            if fs::remove_dir(ancestor).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         fs::remove_dir_all(path)
     }
 
+    fn remove_dir_all_report<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        let path = path.as_ref();
+        let mut freed = 0;
+        if !fs::symlink_metadata(path)?.file_type().is_symlink() {
+            #[cfg(unix)]
+            let mut counted = HashSet::new();
+            for entry in self.walk_dir(path)? {
+                // the metadata of an entry does not follow symbolic links
+                let metadata = entry?.metadata()?;
+                #[cfg(unix)]
+                let first_link = counted.insert(MetadataExt::ino(&metadata));
+                #[cfg(not(unix))]
+                let first_link = true;
+                if metadata.is_file() && first_link {
+                    freed += metadata.len();
+                }
+            }
+        }
+        fs::remove_dir_all(path)?;
+        Ok(freed)
+    }
+
     fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadDir> {
         fs::read_dir(path)
     }
 
+    fn walk_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::WalkDir> {
+        fs::read_dir(path).map(|dir| OsWalkDir {
+            stack: vec![dir],
+            pending: None,
+        })
+    }
+
+    fn read_dir_names<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(OsString, NodeKind)>> {
+        fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                Ok((entry.file_name(), node_kind(entry.file_type()?)))
+            })
+            .collect()
+    }
+
+    fn dir_is_empty<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        Ok(fs::read_dir(path)?.next().transpose()?.is_none())
+    }
+
     fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         fs::remove_file(path)
     }
 
-    fn copy_file<P, Q>(&self, from: P, to: Q) -> Result<()>
+    fn copy_file<P, Q>(&self, from: P, to: Q) -> Result<u64>
     where
         P: AsRef<Path>,
         Q: AsRef<Path>,
     {
-        fs::copy(from, to).and(Ok(()))
+        fs::copy(from, to)
     }
 
     fn rename<P, Q>(&self, from: P, to: Q) -> Result<()>
@@ -130,9 +250,180 @@ impl FileSystem for OsFileSystem {
         fs::rename(from, to)
     }
 
+    fn rename_noreplace<P, Q>(&self, from: P, to: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        #[cfg(target_os = "linux")]
+        {
+            match renameat2_noreplace(from.as_ref(), to.as_ref()) {
+                // the kernel or the file system does not support the flag
+                Err(ref e) if e.raw_os_error() == Some(libc::EINVAL)
+                    || e.raw_os_error() == Some(libc::ENOSYS) => {}
+                result => return result,
+            }
+        }
+        if fs::symlink_metadata(to.as_ref()).is_ok() {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+        }
+        fs::rename(from, to)
+    }
+
+    fn hard_link<P, Q>(&self, src: P, dst: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        fs::hard_link(src, dst)
+    }
+
     fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
         fs::canonicalize(path)
     }
+
+    fn change_token<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        let metadata = fs::metadata(path)?;
+        let mut hasher = DefaultHasher::new();
+        metadata.modified()?.hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        #[cfg(unix)]
+        MetadataExt::ino(&metadata).hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    fn write_if_unchanged<P, C>(&self, path: P, expected_token: u64, contents: C) -> Result<bool>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        if self.change_token(&path)? != expected_token {
+            return Ok(false);
+        }
+        fs::write(path, contents).and(Ok(true))
+    }
+
+    fn write_batch(&self, writes: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+        for (path, contents) in writes {
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+
+    fn set_len<P: AsRef<Path>>(&self, path: P, size: u64) -> Result<()> {
+        fs::OpenOptions::new().write(true).open(path)?.set_len(size)
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    #[cfg(unix)]
+    fn symlink<P, Q>(&self, original: P, link: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        std::os::unix::fs::symlink(original, link)
+    }
+}
+
+/// An open file of the [`OsFileSystem`].
+///
+/// This is a wrapper around a [`fs::File`], which also remembers the length and
+/// modification time of the file from when it was opened.
+///
+/// [`OsFileSystem`]: struct.OsFileSystem.html
+/// [`fs::File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+#[derive(Debug)]
+pub struct OsFile {
+    file: fs::File,
+    opened: (u64, Option<SystemTime>),
+}
+
+// What has_changed_since_open compares: the length and modification time.
+fn file_sig(metadata: &fs::Metadata) -> (u64, Option<SystemTime>) {
+    (metadata.len(), metadata.modified().ok())
+}
+
+impl OsFile {
+    fn new(file: fs::File) -> Result<Self> {
+        let opened = file_sig(&file.metadata()?);
+        Ok(OsFile { file, opened })
+    }
+
+    /// Returns the underlying [`fs::File`].
+    ///
+    /// [`fs::File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+    pub fn into_inner(self) -> fs::File {
+        self.file
+    }
+}
+
+impl AsRef<fs::File> for OsFile {
+    fn as_ref(&self) -> &fs::File {
+        &self.file
+    }
+}
+
+impl io::Read for OsFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl io::Write for OsFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+    }
+}
+
+impl io::Seek for OsFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl FileExt for OsFile {
+    type Metadata = fs::Metadata;
+
+    fn metadata(&self) -> Result<Self::Metadata> {
+        self.file.metadata()
+    }
+
+    fn set_len(&self, size: u64) -> Result<()> {
+        self.file.set_len(size)
+    }
+    fn sync_all(&self) -> Result<()> {
+        self.file.sync_all()
+    }
+    fn sync_data(&self) -> Result<()> {
+        self.file.sync_data()
+    }
+    fn set_times(&self, times: FileTimes) -> Result<()> {
+        FileExt::set_times(&self.file, times)
+    }
+    fn has_changed_since_open(&self) -> Result<bool> {
+        Ok(file_sig(&self.file.metadata()?) != self.opened)
+    }
+    fn try_clone(&self) -> Result<Self> {
+        Ok(OsFile {
+            file: self.file.try_clone()?,
+            opened: self.opened,
+        })
+    }
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        std::os::unix::fs::FileExt::read_at(&self.file, buf, offset)
+    }
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        std::os::unix::fs::FileExt::write_at(&self.file, buf, offset)
+    }
 }
 
 impl FileExt for fs::File {
@@ -151,6 +442,76 @@ impl FileExt for fs::File {
     fn sync_data(&self) -> Result<()> {
         self.sync_data()
     }
+    fn set_times(&self, times: FileTimes) -> Result<()> {
+        let mut std_times = fs::FileTimes::new();
+        if let Some(t) = times.accessed {
+            std_times = std_times.set_accessed(t);
+        }
+        if let Some(t) = times.modified {
+            std_times = std_times.set_modified(t);
+        }
+        self.set_times(std_times)
+    }
+    fn has_changed_since_open(&self) -> Result<bool> {
+        // a fs::File does not remember anything about the file from when it was opened
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+    fn try_clone(&self) -> Result<Self> {
+        self.try_clone()
+    }
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        std::os::unix::fs::FileExt::write_at(self, buf, offset)
+    }
+}
+
+fn node_kind(file_type: fs::FileType) -> NodeKind {
+    #[cfg(unix)]
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_symlink() {
+        return NodeKind::Symlink;
+    } else if file_type.is_dir() {
+        return NodeKind::Dir;
+    } else if file_type.is_file() {
+        return NodeKind::File;
+    }
+    #[cfg(unix)]
+    {
+        if file_type.is_fifo() {
+            return NodeKind::Fifo;
+        } else if file_type.is_socket() {
+            return NodeKind::Socket;
+        } else if file_type.is_char_device() {
+            return NodeKind::CharDevice;
+        } else if file_type.is_block_device() {
+            return NodeKind::BlockDevice;
+        }
+    }
+    NodeKind::Other
+}
+
+// Renames from to to, unless to exists, in a single step.
+#[cfg(target_os = "linux")]
+fn renameat2_noreplace(from: &Path, to: &Path) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let from = CString::new(from.as_os_str().as_bytes())?;
+    let to = CString::new(to.as_os_str().as_bytes())?;
+    // both paths stay alive, and nul-terminated, for the duration of the call
+    let ret = unsafe {
+        libc::renameat2(libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), libc::RENAME_NOREPLACE)
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
 }
 
 impl Metadata for fs::Metadata {
@@ -164,6 +525,14 @@ impl Metadata for fs::Metadata {
         self.is_file()
     }
 
+    fn is_symlink(&self) -> bool {
+        self.is_symlink()
+    }
+
+    fn kind(&self) -> NodeKind {
+        node_kind(self.file_type())
+    }
+
     fn len(&self) -> u64 {
         self.len()
     }
@@ -171,6 +540,39 @@ impl Metadata for fs::Metadata {
     fn permissions(&self) -> Self::Permissions {
         self.permissions()
     }
+
+    fn modified(&self) -> Result<SystemTime> {
+        self.modified()
+    }
+
+    fn accessed(&self) -> Result<SystemTime> {
+        self.accessed()
+    }
+
+    fn created(&self) -> Result<SystemTime> {
+        self.created()
+    }
+
+    #[cfg(unix)]
+    fn is_sparse(&self) -> bool {
+        // st_blocks is always expressed in 512-byte units
+        MetadataExt::blocks(self) * 512 < self.len()
+    }
+
+    #[cfg(unix)]
+    fn uid(&self) -> u32 {
+        MetadataExt::uid(self)
+    }
+
+    #[cfg(unix)]
+    fn gid(&self) -> u32 {
+        MetadataExt::gid(self)
+    }
+
+    #[cfg(unix)]
+    fn ino(&self) -> u64 {
+        MetadataExt::ino(self)
+    }
 }
 
 impl Permissions for fs::Permissions {
@@ -199,6 +601,9 @@ impl Permissions for fs::Permissions {
 }
 
 impl DirEntry for fs::DirEntry {
+    type Metadata = fs::Metadata;
+    type FileType = fs::FileType;
+
     fn file_name(&self) -> OsString {
         self.file_name()
     }
@@ -206,10 +611,72 @@ impl DirEntry for fs::DirEntry {
     fn path(&self) -> PathBuf {
         self.path()
     }
+
+    fn metadata(&self) -> Result<Self::Metadata> {
+        self.metadata()
+    }
+
+    fn file_type(&self) -> Result<Self::FileType> {
+        self.file_type()
+    }
+}
+
+impl FileType for fs::FileType {
+    fn is_dir(&self) -> bool {
+        self.is_dir()
+    }
+
+    fn is_file(&self) -> bool {
+        self.is_file()
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.is_symlink()
+    }
 }
 
 impl ReadDir<fs::DirEntry> for fs::ReadDir {}
 
+#[derive(Debug)]
+pub struct OsWalkDir {
+    stack: Vec<fs::ReadDir>,
+    /// Directory returned last, to descend into next
+    pending: Option<PathBuf>,
+}
+
+impl Iterator for OsWalkDir {
+    type Item = Result<fs::DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(dir) = self.pending.take() {
+            match fs::read_dir(dir) {
+                Ok(dir) => self.stack.push(dir),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        while let Some(dir) = self.stack.last_mut() {
+            match dir.next() {
+                Some(Ok(entry)) => {
+                    // file_type() does not follow symbolic links
+                    match entry.file_type() {
+                        Ok(file_type) if file_type.is_dir() => self.pending = Some(entry.path()),
+                        Ok(_) => {}
+                        Err(e) => return Some(Err(e)),
+                    }
+                    return Some(Ok(entry));
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+impl WalkDir<fs::DirEntry> for OsWalkDir {}
+
 #[cfg(feature = "temp")]
 impl TempFileSystem for OsFileSystem {
     type TempDir = OsTempDir;
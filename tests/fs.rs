@@ -1,8 +1,13 @@
 use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use file_objects_rs::{DirEntry, FakeFileSystem, FileSystem, OsFileSystem, TempDir, TempFileSystem};
-use file_objects_rs::{FileExt, Metadata, OpenOptions, Permissions};
+#[cfg(feature = "serde")]
+use file_objects_rs::FakeSnapshot;
+use file_objects_rs::{CachingFileSystem, DirEntry, FakeFileSystem, FileSystem, OsFileSystem, TempDir, TempFileSystem};
+use file_objects_rs::{FileExt, FileTimes, FileType, FsOp, Metadata, NodeKind, OpenOptions, Permissions};
 
 macro_rules! make_test {
     ($test:ident, $fs:expr) => {
@@ -36,12 +41,34 @@ macro_rules! test_fs {
             make_test!(is_file_returns_false_if_node_is_dir, $fs);
             make_test!(is_file_returns_false_if_node_does_not_exist, $fs);
 
+            #[cfg(unix)]
+            make_test!(is_hidden_returns_true_if_name_starts_with_dot, $fs);
+            make_test!(is_hidden_fails_if_node_does_not_exist, $fs);
+            #[cfg(unix)]
+            make_test!(is_readable_and_is_writable_follow_mode, $fs);
+            make_test!(is_readable_fails_if_node_does_not_exist, $fs);
+            #[cfg(unix)]
+            make_test!(writable_follows_mode_of_file_or_parent_dir, $fs);
+            make_test!(writable_is_false_for_dir, $fs);
+            make_test!(writable_fails_if_parent_does_not_exist, $fs);
+
             make_test!(create_dir_creates_new_dir, $fs);
             make_test!(create_dir_fails_if_dir_already_exists, $fs);
             make_test!(create_dir_fails_if_parent_does_not_exist, $fs);
 
             make_test!(create_dir_all_creates_dirs_in_path, $fs);
             make_test!(create_dir_all_still_succeeds_if_any_dir_already_exists, $fs);
+            make_test!(create_dir_all_fails_if_node_is_a_file, $fs);
+            make_test!(create_dir_all_fails_if_ancestor_is_a_file, $fs);
+            make_test!(create_dir_all_normalizes_redundant_separators, $fs);
+            make_test!(create_dir_all_with_redundant_separators_finds_existing_dirs, $fs);
+            make_test!(create_dir_all_succeeds_if_racing_other_threads, $fs);
+            #[cfg(unix)]
+            make_test!(create_dir_all_follows_symlink_to_dir_in_path, $fs);
+            #[cfg(unix)]
+            make_test!(create_dir_all_succeeds_if_node_is_symlink_to_dir, $fs);
+            #[cfg(unix)]
+            make_test!(create_dir_all_fails_if_node_is_dangling_symlink, $fs);
 
             make_test!(remove_dir_deletes_dir, $fs);
             make_test!(remove_dir_does_not_affect_parent, $fs);
@@ -49,6 +76,10 @@ macro_rules! test_fs {
             make_test!(remove_dir_fails_if_node_is_a_file, $fs);
             make_test!(remove_dir_fails_if_dir_is_not_empty, $fs);
 
+            make_test!(remove_dir_and_empty_parents_removes_empty_ancestors, $fs);
+            make_test!(remove_dir_and_empty_parents_stops_at_non_empty_ancestor, $fs);
+            make_test!(remove_dir_and_empty_parents_fails_if_dir_is_not_empty, $fs);
+
             make_test!(remove_dir_all_removes_dir_and_contents, $fs);
             make_test!(remove_dir_all_fails_if_node_is_a_file, $fs);
             #[cfg(unix)]
@@ -64,14 +95,62 @@ macro_rules! test_fs {
             #[cfg(unix)]
             make_test!(remove_dir_all_fails_if_descendant_not_readable, $fs);
 
+            make_test!(remove_dir_all_report_returns_length_of_removed_files, $fs);
+            #[cfg(unix)]
+            make_test!(remove_dir_all_report_counts_hard_linked_file_once, $fs);
+            make_test!(remove_dir_all_report_fails_if_node_does_not_exist, $fs);
+
             make_test!(read_dir_returns_dir_entries, $fs);
             make_test!(read_dir_fails_if_node_does_not_exist, $fs);
             make_test!(read_dir_fails_if_node_is_a_file, $fs);
 
+            make_test!(walk_dir_returns_all_descendants_depth_first, $fs);
+            #[cfg(unix)]
+            make_test!(walk_dir_does_not_follow_symlinks, $fs);
+            make_test!(walk_dir_fails_if_node_does_not_exist, $fs);
+
+            make_test!(find_duplicates_groups_files_with_equal_contents, $fs);
+
+            make_test!(exists_returns_true_for_files_and_dirs, $fs);
+            make_test!(exists_returns_false_if_node_does_not_exist, $fs);
+            make_test!(try_exists_returns_false_if_node_does_not_exist, $fs);
+            #[cfg(unix)]
+            make_test!(try_exists_fails_if_parent_is_not_readable, $fs);
+
+            make_test!(dir_entry_metadata_describes_entry, $fs);
+            #[cfg(unix)]
+            make_test!(dir_entry_metadata_does_not_follow_symlink, $fs);
+            make_test!(dir_entry_metadata_fails_if_entry_was_removed, $fs);
+            make_test!(dir_entry_file_type_reports_files_and_dirs, $fs);
+            #[cfg(unix)]
+            make_test!(dir_entry_file_type_reports_symlinks, $fs);
+
+            make_test!(read_dir_names_returns_names_and_kinds, $fs);
+            #[cfg(unix)]
+            make_test!(read_dir_names_reports_symlinks_as_symlinks, $fs);
+            make_test!(read_dir_names_fails_if_node_does_not_exist, $fs);
+
+            make_test!(dir_is_empty_is_true_for_empty_dir, $fs);
+            make_test!(dir_is_empty_is_false_for_dir_with_child, $fs);
+            make_test!(dir_is_empty_fails_if_node_is_a_file, $fs);
+            make_test!(dir_is_empty_fails_if_node_does_not_exist, $fs);
+
+            make_test!(read_dir_glob_matches_star, $fs);
+            make_test!(read_dir_glob_matches_question_mark, $fs);
+            make_test!(read_dir_glob_matches_character_class, $fs);
+            make_test!(read_dir_glob_fails_if_node_does_not_exist, $fs);
+
             make_test!(write_file_writes_to_new_file, $fs);
             make_test!(write_file_overwrites_contents_of_existing_file, $fs);
             make_test!(write_file_fails_if_file_is_readonly, $fs);
             make_test!(write_file_fails_if_node_is_a_directory, $fs);
+            make_test!(write_file_fails_if_path_ends_in_separator, $fs);
+
+            make_test!(append_creates_file_then_appends_to_it, $fs);
+            make_test!(append_fails_if_node_is_a_directory, $fs);
+
+            make_test!(create_durable_writes_contents, $fs);
+            make_test!(sync_dir_fails_if_node_does_not_exist, $fs);
 
             make_test!(overwrite_file_overwrites_contents_of_existing_file, $fs);
             make_test!(overwrite_file_fails_if_node_does_not_exist, $fs);
@@ -81,6 +160,9 @@ macro_rules! test_fs {
             make_test!(read_file_returns_contents_as_bytes, $fs);
             make_test!(read_file_fails_if_file_does_not_exist, $fs);
 
+            make_test!(read_capped_reads_file_within_cap, $fs);
+            make_test!(read_capped_fails_if_file_exceeds_cap, $fs);
+
             make_test!(read_file_to_string_returns_contents_as_string, $fs);
             make_test!(read_file_to_string_fails_if_file_does_not_exist, $fs);
             make_test!(read_file_to_string_fails_if_contents_are_not_utf8, $fs);
@@ -90,6 +172,7 @@ macro_rules! test_fs {
 
             make_test!(open_object_writes_bytes_to_buffer, $fs);
             make_test!(open_object_fails_if_file_does_not_exist, $fs);
+            make_test!(open_object_reads_empty_file, $fs);
 
             make_test!(create_file_writes_to_new_file, $fs);
             make_test!(create_file_fails_if_file_already_exists, $fs);
@@ -97,13 +180,34 @@ macro_rules! test_fs {
             make_test!(remove_file_removes_a_file, $fs);
             make_test!(remove_file_fails_if_file_does_not_exist, $fs);
             make_test!(remove_file_fails_if_node_is_a_directory, $fs);
+            #[cfg(unix)]
+            make_test!(remove_file_removes_symlink_but_not_target, $fs);
+            #[cfg(target_os = "linux")]
+            make_test!(remove_file_of_directory_fails_with_eisdir, $fs);
 
             make_test!(copy_file_copies_a_file, $fs);
             make_test!(copy_file_overwrites_destination_file, $fs);
+            make_test!(copy_file_copy_is_independent_of_original, $fs);
+            make_test!(copy_file_overwritten_copy_is_independent_of_original, $fs);
             make_test!(copy_file_fails_if_original_file_does_not_exist, $fs);
             make_test!(copy_file_fails_if_destination_file_is_readonly, $fs);
             make_test!(copy_file_fails_if_original_node_is_directory, $fs);
             make_test!(copy_file_fails_if_destination_node_is_directory, $fs);
+            make_test!(copy_file_verified_copies_and_verifies_a_file, $fs);
+            make_test!(copy_file_verified_fails_if_original_file_does_not_exist, $fs);
+            make_test!(copy_dir_all_copies_tree, $fs);
+            make_test!(copy_dir_all_fails_if_original_node_is_a_file, $fs);
+            make_test!(copy_dir_all_fails_if_original_node_does_not_exist, $fs);
+            make_test!(copy_dir_all_fails_if_destination_is_inside_original, $fs);
+            make_test!(copy_dir_all_fails_if_destination_is_original, $fs);
+            #[cfg(unix)]
+            make_test!(copy_dir_all_copies_symlinks_as_symlinks, $fs);
+            #[cfg(unix)]
+            make_test!(copy_dir_all_fails_if_descendant_not_readable, $fs);
+            #[cfg(unix)]
+            make_test!(copy_file_preserves_mode, $fs);
+            #[cfg(unix)]
+            make_test!(copy_file_replaces_mode_of_destination_file, $fs);
 
             make_test!(rename_renames_a_file, $fs);
             make_test!(rename_renames_a_directory, $fs);
@@ -116,6 +220,31 @@ macro_rules! test_fs {
                 $fs
             );
             make_test!(rename_fails_if_destination_directory_is_not_empty, $fs);
+            make_test!(rename_to_itself_does_nothing, $fs);
+            #[cfg(unix)]
+            make_test!(rename_fails_if_destination_is_inside_original, $fs);
+
+            make_test!(rename_noreplace_renames_file, $fs);
+            make_test!(rename_noreplace_fails_if_destination_file_exists, $fs);
+            make_test!(rename_noreplace_fails_if_destination_dir_exists, $fs);
+            make_test!(rename_noreplace_fails_if_original_path_does_not_exist, $fs);
+
+            make_test!(merge_dir_merges_trees_without_collisions, $fs);
+            make_test!(merge_dir_renames_if_destination_does_not_exist, $fs);
+            make_test!(merge_dir_fails_on_file_collision_without_overwrite, $fs);
+            make_test!(merge_dir_overwrites_file_collision, $fs);
+
+            make_test!(hard_link_shares_contents, $fs);
+            make_test!(hard_link_survives_removal_of_original, $fs);
+            #[cfg(unix)]
+            make_test!(hard_link_shares_permissions, $fs);
+            #[cfg(unix)]
+            make_test!(hard_link_shares_ino, $fs);
+            #[cfg(unix)]
+            make_test!(distinct_nodes_have_distinct_inos, $fs);
+            make_test!(hard_link_fails_if_destination_exists, $fs);
+            make_test!(hard_link_fails_if_source_does_not_exist, $fs);
+            make_test!(hard_link_fails_if_source_is_a_directory, $fs);
 
             make_test!(readonly_returns_write_permission, $fs);
             make_test!(readonly_fails_if_node_does_not_exist, $fs);
@@ -139,18 +268,29 @@ macro_rules! test_fs {
             make_test!(open_object_reads_ok_after_parent_dir_moved, $fs);
             make_test!(open_object_reads_ok_after_file_updated, $fs);
             make_test!(open_object_reads_ok_after_file_shrunk, $fs);
+            make_test!(open_object_reads_ok_after_file_truncated_by_set_len, $fs);
+            make_test!(open_object_sees_file_truncated_by_create, $fs);
+            make_test!(open_object_sees_write_through_other_handle, $fs);
+            make_test!(open_object_sees_append, $fs);
+            make_test!(open_object_sees_copy_onto_file, $fs);
+            make_test!(open_object_keeps_contents_after_rename_onto_file, $fs);
 
             make_test!(open_object_can_seek_from_start_then_read, $fs);
             make_test!(open_object_can_seek_from_current_then_read, $fs);
             make_test!(open_object_can_seek_from_end_then_read, $fs);
+            make_test!(open_object_seeks_from_end_of_file_extended_by_other_handle, $fs);
             make_test!(open_object_fails_if_seeks_before_byte_0, $fs);
             make_test!(open_object_can_seek_and_read_beyond_eof, $fs);
 
             make_test!(create_objects_write_independently, $fs);
             make_test!(create_object_cannot_overwrite_dir, $fs);
+            make_test!(create_object_fails_if_path_ends_in_separator, $fs);
+            make_test!(create_object_fails_if_file_path_ends_in_separator, $fs);
+            make_test!(open_object_fails_if_file_path_ends_in_separator, $fs);
             make_test!(create_object_writes_chunked, $fs);
             make_test!(create_object_writes_ok_beyond_eof, $fs);
             make_test!(create_object_writes_ok_after_file_deleted, $fs);
+            make_test!(create_object_metadata_ok_after_file_deleted, $fs);
             make_test!(create_object_writes_ok_after_file_overwritten, $fs);
             make_test!(create_object_writes_ok_after_parent_dir_deleted, $fs);
             make_test!(create_object_writes_ok_after_file_renamed, $fs);
@@ -165,18 +305,39 @@ macro_rules! test_fs {
             make_test!(create_object_can_seek_then_extend, $fs);
 
             make_test!(create_object_writes_to_new_file, $fs);
+            make_test!(create_with_capacity_creates_empty_file, $fs);
+            make_test!(create_with_capacity_truncates_existing_file, $fs);
             make_test!(create_object_fails_if_file_is_readonly, $fs);
 
             make_test!(open_object_cannot_write, $fs);
             make_test!(create_object_cannot_read, $fs);
+            make_test!(file_operations_are_allowed_by_access_mode, $fs);
 
             make_test!(set_len_on_create_object_truncates_file, $fs);
             make_test!(set_len_on_create_object_extends_file, $fs);
             make_test!(set_len_on_create_object_doesnt_change_cursor, $fs);
+            make_test!(set_len_shrink_leaves_nothing_to_read_past_new_end, $fs);
+            make_test!(set_len_extend_reads_gap_as_zeros, $fs);
+            make_test!(set_len_on_create_object_succeeds_after_file_made_readonly, $fs);
+            make_test!(fs_set_len_truncates_file, $fs);
+            make_test!(fs_set_len_fails_if_file_is_readonly, $fs);
+            make_test!(fs_set_len_fails_if_node_does_not_exist, $fs);
+            make_test!(try_clone_shares_contents, $fs);
+            make_test!(has_changed_since_open_after_write_through_path, $fs);
+            make_test!(has_changed_since_open_after_write_through_other_handle, $fs);
+            #[cfg(unix)]
+            make_test!(read_at_reads_at_offset_without_moving_cursor, $fs);
+            #[cfg(unix)]
+            make_test!(write_at_writes_at_offset_without_moving_cursor, $fs);
+            #[cfg(unix)]
+            make_test!(write_at_beyond_eof_fills_gap_with_zeros, $fs);
+            #[cfg(unix)]
+            make_test!(read_at_fails_if_not_opened_for_reading, $fs);
 
             make_test!(open_object_metadata_is_file, $fs);
             make_test!(open_object_metadata_has_correct_len, $fs);
             make_test!(open_object_metadata_len_is_immutable, $fs);
+            make_test!(open_object_metadata_again_has_current_len, $fs);
             make_test!(create_object_metadata_is_file, $fs);
             make_test!(create_object_metadata_has_correct_len, $fs);
             make_test!(create_object_metadata_len_is_immutable, $fs);
@@ -186,9 +347,62 @@ macro_rules! test_fs {
             make_test!(fs_file_metadata_len_is_immutable, $fs);
             make_test!(fs_file_metadata_fails_if_file_doesn_exist, $fs);
 
+            make_test!(change_token_changes_after_write, $fs);
+            make_test!(change_token_is_stable_across_reads, $fs);
+            make_test!(change_token_changes_after_file_replaced, $fs);
+            make_test!(change_token_fails_if_file_does_not_exist, $fs);
+
+            make_test!(write_if_unchanged_writes_if_token_matches, $fs);
+            make_test!(write_if_unchanged_does_not_write_if_file_changed, $fs);
+            make_test!(write_if_unchanged_fails_if_file_does_not_exist, $fs);
+
+            make_test!(write_batch_writes_all_files, $fs);
+
+            make_test!(created_is_unchanged_by_write, $fs);
+            make_test!(modified_is_updated_by_write, $fs);
+            make_test!(set_times_sets_modified_in_the_past, $fs);
+            make_test!(set_times_leaves_unset_times_unchanged, $fs);
+            make_test!(sig_changes_with_modified_and_len, $fs);
+
             make_test!(fs_dir_metadata_is_dir, $fs);
             make_test!(fs_dir_metadata_has_correct_len, $fs);
 
+            #[cfg(unix)]
+            make_test!(metadata_kind_matches_node, $fs);
+            #[cfg(unix)]
+            make_test!(symlink_metadata_len_is_length_of_target_path, $fs);
+            #[cfg(unix)]
+            make_test!(symlink_metadata_of_dangling_symlink_succeeds, $fs);
+            make_test!(file_type_reports_files_and_dirs, $fs);
+            #[cfg(unix)]
+            make_test!(file_type_reports_symlink_rather_than_target, $fs);
+            make_test!(file_type_fails_if_node_does_not_exist, $fs);
+            #[cfg(unix)]
+            make_test!(create_new_fails_if_node_is_dangling_symlink, $fs);
+            #[cfg(unix)]
+            make_test!(symlink_can_be_opened, $fs);
+            #[cfg(unix)]
+            make_test!(symlink_to_dir_is_followed_in_path, $fs);
+            #[cfg(unix)]
+            make_test!(symlink_target_may_lead_to_parent_dir, $fs);
+            #[cfg(target_os = "linux")]
+            make_test!(symlink_loop_fails_with_eloop, $fs);
+            #[cfg(unix)]
+            make_test!(symlink_fails_if_link_already_exists, $fs);
+
+            #[cfg(unix)]
+            make_test!(read_link_returns_unresolved_target, $fs);
+            #[cfg(unix)]
+            make_test!(read_link_does_not_follow_chain, $fs);
+            make_test!(read_link_fails_if_node_is_a_file, $fs);
+            make_test!(read_link_fails_if_node_is_a_directory, $fs);
+            make_test!(read_link_fails_if_node_does_not_exist, $fs);
+
+            #[cfg(unix)]
+            make_test!(set_len_on_create_object_makes_file_sparse, $fs);
+            #[cfg(unix)]
+            make_test!(fully_written_file_is_not_sparse, $fs);
+
             make_test!(writable_object_does_not_create_file, $fs);
             make_test!(writable_object_sets_cursor_to_beginning, $fs);
             make_test!(writable_object_allows_append, $fs);
@@ -196,6 +410,39 @@ macro_rules! test_fs {
             make_test!(writable_object_allows_write_short, $fs);
             make_test!(writable_object_allows_write_long, $fs);
             make_test!(writable_object_extends_file, $fs);
+            make_test!(writable_object_seeks_without_read_access, $fs);
+
+            make_test!(open_with_options_create_without_truncate_patches_file, $fs);
+            make_test!(open_with_options_write_truncate_empties_existing_file, $fs);
+            make_test!(open_with_options_write_truncate_fails_if_file_does_not_exist, $fs);
+            #[cfg(unix)]
+            make_test!(open_with_options_create_without_truncate_preserves_mode, $fs);
+            make_test!(open_with_options_create_append_creates_file, $fs);
+            make_test!(open_with_options_create_parents_creates_missing_dirs, $fs);
+            make_test!(open_with_options_accepts_cloexec, $fs);
+            make_test!(open_with_options_fails_without_cloexec, $fs);
+            make_test!(open_with_options_without_create_parents_fails_if_parent_missing, $fs);
+            make_test!(open_with_options_fails_for_every_write_mode_if_node_is_a_directory, $fs);
+            make_test!(open_with_options_create_append_appends_to_file, $fs);
+            make_test!(open_with_options_append_writers_keep_each_others_writes, $fs);
+            make_test!(open_with_options_append_writers_racing_keep_all_writes, $fs);
+            make_test!(open_with_options_read_write_can_read_and_write, $fs);
+            make_test!(open_with_options_create_new_read_write_starts_empty, $fs);
+            make_test!(open_with_options_read_append_moves_cursor_to_end, $fs);
+            make_test!(open_with_options_read_write_append_reads_anywhere_and_appends, $fs);
+            make_test!(open_with_options_read_write_append_fails_if_file_does_not_exist, $fs);
+            make_test!(open_with_options_fails_without_access_mode, $fs);
+            make_test!(open_with_options_fails_if_truncate_without_write, $fs);
+            make_test!(open_with_options_fails_if_create_new_without_write, $fs);
+            make_test!(open_with_options_create_new_fails_if_file_exists, $fs);
+            make_test!(open_with_options_create_new_fails_if_dir_exists, $fs);
+            make_test!(open_with_options_fails_if_append_and_truncate, $fs);
+            #[cfg(unix)]
+            make_test!(open_with_options_creates_file_with_mode, $fs);
+            #[cfg(unix)]
+            make_test!(open_with_options_can_write_to_new_readonly_file, $fs);
+            #[cfg(unix)]
+            make_test!(open_with_options_mode_does_not_affect_existing_file, $fs);
 
             make_test!(canonicalize_ok_if_root, $fs);
             make_test!(canonicalize_fails_if_empty, $fs);
@@ -205,14 +452,21 @@ macro_rules! test_fs {
             make_test!(canonicalize_ok_if_file_exists, $fs);
             make_test!(canonicalize_fails_if_file_doesnt_exist, $fs);
             make_test!(canonicalize_ok_with_dotdot_if_paths_exist, $fs);
+            make_test!(canonicalize_ok_with_dot_if_paths_exist, $fs);
             make_test!(canonicalize_fails_with_dotdot_if_path_doesnt_exist, $fs);
             make_test!(canonicalize_cant_go_lower_than_root, $fs);
+            make_test!(canonicalize_fails_after_file_removed, $fs);
+            make_test!(canonicalize_follows_renamed_ancestor, $fs);
+            make_test!(canonicalize_fails_after_dotdot_target_removed, $fs);
+            #[cfg(unix)]
+            make_test!(canonicalize_follows_replaced_symlink, $fs);
 
             #[cfg(not(target_os = "macos"))]
             make_test!(canonicalize_fails_if_subpath_is_file, $fs);
 
-            #[cfg(target_os = "macos")]
-            make_test!(canonicalize_ok_if_subpath_is_file, $fs);
+            make_test!(canonicalize_lenient_matches_canonicalize_for_existing_path, $fs);
+            make_test!(canonicalize_lenient_appends_missing_final_component, $fs);
+            make_test!(canonicalize_lenient_fails_if_intermediate_is_missing, $fs);
 
             #[cfg(unix)]
             make_test!(mode_returns_permissions, $fs);
@@ -223,6 +477,13 @@ macro_rules! test_fs {
             make_test!(set_mode_sets_permissions, $fs);
             #[cfg(unix)]
             make_test!(set_mode_fails_if_node_does_not_exist, $fs);
+            #[cfg(unix)]
+            make_test!(set_mode_leaves_modified_time_alone, $fs);
+
+            #[cfg(unix)]
+            make_test!(chown_keeps_owner_given_as_none, $fs);
+            #[cfg(unix)]
+            make_test!(chown_fails_if_node_does_not_exist, $fs);
 
             make_test!(temp_dir_creates_tempdir, $fs);
             make_test!(temp_dir_creates_unique_dir, $fs);
@@ -233,1576 +494,4377 @@ macro_rules! test_fs {
 
 test_fs!(os, OsFileSystem::new);
 test_fs!(fake, FakeFileSystem::new);
+test_fs!(caching, || CachingFileSystem::new(FakeFileSystem::new()));
 
-// Used to be part of the public API.
-// Keep around for the tests.
-fn read_file<T: FileSystem, P: AsRef<Path>>(fs: &T, path: P) -> io::Result<Vec<u8>> {
-    let mut reader = fs.open(path)?;
-    let mut result = vec![];
-    reader.read_to_end(&mut result)?;
-    Ok(result)
-}
+// Only the fake can promise an order, the OS cannot.
+#[test]
+fn fake_read_dir_returns_entries_sorted_by_name() {
+    let fs = FakeFileSystem::new();
+    let dir = fs.current_dir().unwrap().join("dir");
+    fs.create_dir(&dir).unwrap();
+    let names = ["b", "d", "a", "e", "c"];
+    for name in names.iter() {
+        fs.create(dir.join(name)).unwrap();
+    }
 
-// Used to be part of the public API.
-// Keep around for the tests.
-fn read_file_to_string<T: FileSystem, P: AsRef<Path>>(fs: &T, path: P) -> io::Result<String> {
-    let mut reader = fs.open(path)?;
-    let mut result = vec![];
-    reader.read_to_end(&mut result)?;
-    String::from_utf8(result)
-        .map_err(|_| io::Error::new(ErrorKind::InvalidData, "Invalid Data"))
+    let entries: Vec<_> = fs.read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+    let typed: Vec<_> = fs.read_dir_names(&dir).unwrap().into_iter().map(|(name, _)| name).collect();
+
+    assert_eq!(entries, ["a", "b", "c", "d", "e"]);
+    assert_eq!(typed, entries);
 }
 
-// Used to be part of the public API.
-// Keep around for the tests.
-fn read_file_into<T, P, B>(fs: &T, path: P, mut buf: B) -> io::Result<usize>
-        where
-            T: FileSystem,
-            P: AsRef<Path>,
-            B: AsMut<Vec<u8>> {
+// Reading a huge directory must not keep other threads out of the fake meanwhile.
+#[test]
+fn fake_read_dir_does_not_block_other_threads_while_iterating() {
+    let fs = FakeFileSystem::new();
+    fs.create_dir("/dir").unwrap();
+    for i in 0..100_000 {
+        fs.write(format!("/dir/{}", i), "").unwrap();
+    }
 
-    let mut reader = fs.open(path)?;
-    reader.read_to_end(buf.as_mut())
-}
+    let mut entries = fs.read_dir("/dir").unwrap();
+    let first = entries.next().unwrap().unwrap();
+    let other = fs.clone();
+    thread::spawn(move || other.write("/dir/new.txt", "test text")).join().unwrap().unwrap();
 
-// Used to be part of the public API.
-// Keep around for the tests.
-fn create_file<T, P, B>(fs: &T, path: P, buf: B) -> io::Result<()>
-where
-    T: FileSystem,
-    P: AsRef<Path>,
-    B: AsRef<[u8]>,
-{
-    let opts = OpenOptions::new().write(true).create_new(true);
-    let mut writer = fs.open_with_options(path, &opts)?;
-    writer.write_all(buf.as_ref())
+    assert!(first.metadata().unwrap().is_file());
+    assert_eq!(entries.count(), 99_999);
+    assert!(fs.is_file("/dir/new.txt"));
 }
 
-// Used to be part of the public API.
-// Keep around for the tests.
-fn write_file<T, P, B>(fs: &T, path: P, buf: B) -> io::Result<()>
-where
-    T: FileSystem,
-    P: AsRef<Path>,
-    B: AsRef<[u8]>
-{
-    let mut writer = fs.create(path)?;
-    writer.write_all(buf.as_ref())
+#[test]
+fn fake_from_iter_creates_files_and_parent_dirs() {
+    let fs: FakeFileSystem = vec![
+        ("/a/b.txt", "test text"),
+        ("c/d/e.txt", "more text"),
+        ("f.txt", ""),
+    ]
+    .into_iter()
+    .collect();
+
+    assert!(fs.is_dir("/a"));
+    assert!(fs.is_dir("/c/d"));
+    assert_eq!(fs.read_to_string("/a/b.txt").unwrap(), "test text");
+    assert_eq!(fs.read_to_string("/c/d/e.txt").unwrap(), "more text");
+    assert_eq!(fs.read_to_string("/f.txt").unwrap(), "");
+}
+
+#[test]
+#[should_panic]
+fn fake_from_iter_panics_if_ancestor_is_a_file() {
+    let _ = FakeFileSystem::from_iter(vec![("/a", "test text"), ("/a/b.txt", "test text")]);
 }
 
-// Used to be part of the public API.
-// Keep around for the tests.
-fn overwrite_file<T, P, B>(fs: &T, path: P, buf: B) -> io::Result<()>
-where
-    T: FileSystem,
-    P: AsRef<Path>,
-    B: AsRef<[u8]>
-{
-    let opts = OpenOptions::new().write(true).truncate(true);
-    let mut writer = fs.open_with_options(path, &opts)?;
-    writer.write_all(buf.as_ref())
+#[test]
+fn fake_restore_undoes_changes_since_snapshot() {
+    let fs: FakeFileSystem = vec![("/dir/test.txt", "test text")].into_iter().collect();
+    let snapshot = fs.snapshot();
+
+    fs.write("/dir/test.txt", "new text").unwrap();
+    set_readonly(&fs, "/dir/test.txt", true).unwrap();
+    fs.write("/new.txt", "new text").unwrap();
+    fs.set_current_dir("/dir").unwrap();
+    fs.restore(snapshot);
+
+    assert_eq!(fs.read_to_string("/dir/test.txt").unwrap(), "test text");
+    assert!(!readonly(&fs, "/dir/test.txt").unwrap());
+    assert!(!fs.exists("/new.txt"));
+    assert_eq!(fs.current_dir().unwrap(), Path::new("/"));
 }
 
-// Used to be part of the public API.
-// Keep around for the tests.
-fn set_readonly<T: FileSystem, P: AsRef<Path>>(fs: &T, path: P, readonly: bool) -> io::Result<()>
-{
-    let mut p = fs.metadata(&path)?.permissions();
-    p.set_readonly(readonly);
-    fs.set_permissions(&path, p)
+#[test]
+fn fake_snapshot_is_unaffected_by_open_files() {
+    let fs: FakeFileSystem = vec![("/test.txt", "test text")].into_iter().collect();
+    let mut writer = fs.open_with_options("/test.txt", &OpenOptions::new().write(true)).unwrap();
+    let snapshot = fs.snapshot();
+
+    fs.restore(snapshot.clone());
+    writer.write_all(b"next").unwrap();
+    fs.restore(snapshot);
+
+    assert_eq!(fs.read_to_string("/test.txt").unwrap(), "test text");
 }
 
-// Used to be part of the public API.
-// Keep around for the tests.
-fn readonly<P: AsRef<Path>, T: FileSystem>(fs: &T, path: P) -> io::Result<bool>
-{
-    Ok(fs.metadata(&path)?.permissions().readonly())
+#[test]
+fn fake_restore_keeps_hard_links_linked() {
+    let fs: FakeFileSystem = vec![("/test.txt", "test text")].into_iter().collect();
+    fs.hard_link("/test.txt", "/link.txt").unwrap();
+    let snapshot = fs.snapshot();
+
+    fs.restore(snapshot);
+    fs.write("/test.txt", "new text").unwrap();
+
+    assert_eq!(fs.read_to_string("/link.txt").unwrap(), "new text");
 }
 
-// Used to be part of the public API.
-// Keep around for the tests.
-#[cfg(unix)]
-fn set_mode<P: AsRef<Path>, T: FileSystem>(fs: &T, path: P, mode: u32) -> io::Result<()> {
-    let mut perms = fs.metadata(&path)?.permissions();
-    perms.set_mode(mode);
-    fs.set_permissions(&path, perms)
+#[test]
+fn fake_restore_keeps_inos() {
+    let fs: FakeFileSystem = vec![("/dir/a.txt", "test text")].into_iter().collect();
+    fs.symlink("/dir/a.txt", "/link").unwrap();
+    let ino = |path: &str| fs.symlink_metadata(path).unwrap().ino();
+    let inos = [ino("/dir"), ino("/dir/a.txt"), ino("/link")];
+    let snapshot = fs.snapshot();
+
+    fs.remove_dir_all("/dir").unwrap();
+    fs.restore(snapshot.clone());
+    fs.restore(snapshot);
+
+    assert_eq!([ino("/dir"), ino("/dir/a.txt"), ino("/link")], inos);
+    fs.write("/b.txt", "").unwrap();
+    assert!(!inos.contains(&ino("/b.txt")));
 }
 
-// Used to be part of the public API.
-// Keep around for the tests.
-#[cfg(unix)]
-fn mode<P: AsRef<Path>, T: FileSystem>(fs: &T, path: P) -> io::Result<u32> {
-    Ok(fs.metadata(&path)?.permissions().mode())
+#[cfg(feature = "serde")]
+#[test]
+fn fake_snapshot_round_trips_inos_and_hard_links_through_serde() {
+    let fs: FakeFileSystem = vec![("/dir/a.txt", "test text")].into_iter().collect();
+    fs.hard_link("/dir/a.txt", "/b.txt").unwrap();
+    let ino = |fs: &FakeFileSystem, path: &str| fs.metadata(path).unwrap().ino();
+
+    let json = serde_json::to_string(&fs.snapshot()).unwrap();
+    let restored = FakeFileSystem::new();
+    restored.restore(serde_json::from_str(&json).unwrap());
+
+    assert_eq!(ino(&restored, "/dir"), ino(&fs, "/dir"));
+    assert_eq!(ino(&restored, "/b.txt"), ino(&fs, "/dir/a.txt"));
+    restored.write("/b.txt", "new text").unwrap();
+    assert_eq!(restored.read_to_string("/dir/a.txt").unwrap(), "new text");
+    assert_eq!(restored.total_bytes(), 8);
 }
 
-fn set_current_dir_fails_if_node_does_not_exists<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("does_not_exist");
+#[cfg(feature = "serde")]
+#[test]
+fn fake_snapshot_round_trips_through_serde() {
+    let fs: FakeFileSystem = vec![("/dir/test.txt", &b"test text"[..]), ("/binary", &[0, 159, 146, 150][..])]
+        .into_iter()
+        .collect();
+    set_readonly(&fs, "/dir/test.txt", true).unwrap();
+    fs.symlink("/dir/test.txt", "/link").unwrap();
+    fs.set_current_dir("/dir").unwrap();
 
-    let result = fs.set_current_dir(path);
+    let json = serde_json::to_string(&fs.snapshot()).unwrap();
+    let restored = FakeFileSystem::new();
+    restored.restore(serde_json::from_str(&json).unwrap());
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(restored.read("/binary").unwrap(), [0, 159, 146, 150]);
+    assert_eq!(restored.read_to_string("/link").unwrap(), "test text");
+    assert!(readonly(&restored, "/dir/test.txt").unwrap());
+    assert_eq!(restored.current_dir().unwrap(), Path::new("/dir"));
 }
 
-fn set_current_dir_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("file");
+#[cfg(feature = "serde")]
+#[test]
+fn fake_snapshot_round_trips_removed_current_dir_through_serde() {
+    let fs = FakeFileSystem::new();
+    fs.create_dir("/dir").unwrap();
+    fs.set_current_dir("/dir").unwrap();
+    fs.remove_dir("/dir").unwrap();
 
-    create_file(fs, &path, "").unwrap();
+    let json = serde_json::to_string(&fs.snapshot()).unwrap();
+    let restored = FakeFileSystem::new();
+    restored.restore(serde_json::from_str(&json).unwrap());
 
-    let result = fs.set_current_dir(path);
+    assert_eq!(restored.current_dir().unwrap_err().kind(), ErrorKind::NotFound);
+    restored.create_dir("/dir").unwrap();
+    assert_eq!(restored.current_dir().unwrap(), Path::new("/dir"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn fake_snapshot_deserialize_fails_if_parent_is_missing() {
+    let json = r#"{"cwd":"/","nodes":{"/":{"type":"dir","mode":420},"/a/b":{"type":"dir","mode":420}}}"#;
+
+    let result: serde_json::Result<FakeSnapshot> = serde_json::from_str(json);
 
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
 }
 
-fn is_dir_returns_true_if_node_is_dir<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_dir");
+#[cfg(feature = "serde")]
+#[test]
+fn fake_snapshot_deserialize_fails_if_path_has_no_name() {
+    let json = r#"{"cwd":"/","nodes":{"/":{"type":"dir","mode":420},"/a":{"type":"dir","mode":420},"/a/..":{"type":"file","bytes":[],"mode":420}}}"#;
 
-    fs.create_dir(&path).unwrap();
+    let result: serde_json::Result<FakeSnapshot> = serde_json::from_str(json);
 
-    assert!(fs.is_dir(&path));
+    assert!(result.unwrap_err().to_string().contains("/a/.. has no name"));
 }
 
-fn is_dir_returns_false_if_node_is_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_dir");
+#[cfg(feature = "serde")]
+#[test]
+fn fake_snapshot_deserialize_fails_if_root_is_not_a_directory() {
+    let json = r#"{"cwd":"/","nodes":{"/":{"type":"file","bytes":[],"mode":420}}}"#;
 
-    create_file(fs, &path, "").unwrap();
+    let result: serde_json::Result<FakeSnapshot> = serde_json::from_str(json);
 
-    assert!(!fs.is_dir(&path));
+    assert!(result.is_err());
 }
 
-fn is_dir_returns_false_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    assert!(!fs.is_dir(parent.join("does_not_exist")));
+#[test]
+fn fake_time_granularity_rounds_timestamps_down() {
+    let fs = FakeFileSystem::new();
+    fs.set_time_granularity(Duration::from_secs(2));
+    fs.write("/a.txt", "test text").unwrap();
+    fs.write("/b.txt", "test text").unwrap();
+
+    let time = |secs: f64| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(secs);
+    fs.open("/a.txt").unwrap().set_times(FileTimes::new().set_modified(time(10.3))).unwrap();
+    fs.open("/b.txt").unwrap().set_times(FileTimes::new().set_modified(time(11.9))).unwrap();
+
+    let a = fs.metadata("/a.txt").unwrap().modified().unwrap();
+    let b = fs.metadata("/b.txt").unwrap().modified().unwrap();
+    assert_eq!(a, time(10.0));
+    assert_eq!(a, b);
+}
+
+#[test]
+fn fake_time_granularity_gives_quick_writes_equal_mtimes() {
+    let fs = FakeFileSystem::new();
+    fs.set_time_granularity(Duration::from_secs(2));
+    let window = |t: SystemTime| t.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() / 2;
+
+    let start = SystemTime::now();
+    fs.write("/a.txt", "test text").unwrap();
+    fs.write("/b.txt", "test text").unwrap();
+    let end = SystemTime::now();
+
+    let a = fs.metadata("/a.txt").unwrap().modified().unwrap();
+    let b = fs.metadata("/b.txt").unwrap().modified().unwrap();
+    assert_eq!(a.duration_since(SystemTime::UNIX_EPOCH).unwrap().subsec_nanos(), 0);
+    // only if both writes fell within one interval are their mtimes equal
+    if window(start) == window(end) {
+        assert_eq!(a, b);
+    }
 }
 
-fn is_file_returns_true_if_node_is_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_file");
+#[test]
+fn fake_load_from_os_copies_tree() {
+    let os = OsFileSystem::new();
+    let temp_dir = os.temp_dir("test").unwrap();
+    let root = temp_dir.path();
+    os.create_dir_all(root.join("a/b")).unwrap();
+    os.write(root.join("a/b/c.txt"), "test text").unwrap();
+    os.write(root.join("binary"), [0, 159, 146, 150]).unwrap();
+    #[cfg(unix)]
+    {
+        set_mode(&os, root.join("binary"), 0o600).unwrap();
+        os.symlink("a/b/c.txt", root.join("link")).unwrap();
+    }
 
-    create_file(fs, &path, "").unwrap();
+    let fs = FakeFileSystem::load_from_os(root).unwrap();
 
-    assert!(fs.is_file(&path));
+    assert!(fs.is_dir("/a/b"));
+    assert_eq!(fs.read_to_string("/a/b/c.txt").unwrap(), "test text");
+    assert_eq!(fs.read("/binary").unwrap(), [0, 159, 146, 150]);
+    #[cfg(unix)]
+    {
+        assert_eq!(mode(&fs, "/binary").unwrap(), 0o600);
+        assert_eq!(fs.read_link("/link").unwrap(), Path::new("a/b/c.txt"));
+        assert_eq!(fs.read_to_string("/link").unwrap(), "test text");
+    }
 }
 
-fn is_file_returns_false_if_node_is_dir<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_dir");
+#[test]
+fn fake_load_from_os_fails_if_root_does_not_exist() {
+    let os = OsFileSystem::new();
+    let temp_dir = os.temp_dir("test").unwrap();
 
-    fs.create_dir(&path).unwrap();
+    let result = FakeFileSystem::load_from_os(temp_dir.path().join("does_not_exist"));
 
-    assert!(!fs.is_file(&path));
+    match result {
+        Ok(_) => panic!("should be an err"),
+        Err(err) => assert_eq!(err.kind(), ErrorKind::NotFound),
+    }
 }
 
-fn is_file_returns_false_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    assert!(!fs.is_file(parent.join("does_not_exist")));
-}
+#[test]
+fn fake_total_bytes_and_node_count_describe_contents() {
+    let fs: FakeFileSystem = vec![("/a/b.txt", "test text"), ("/c.txt", "more")].into_iter().collect();
+    fs.hard_link("/c.txt", "/d.txt").unwrap();
 
-fn create_dir_creates_new_dir<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_dir");
+    assert_eq!(fs.total_bytes(), 13);
+    assert_eq!(fs.node_count(), 5);
 
-    let result = fs.create_dir(&path);
+    fs.remove_dir_all("/a").unwrap();
 
-    assert!(result.is_ok());
-    assert!(fs.is_dir(path));
+    assert_eq!(fs.total_bytes(), 4);
+    assert_eq!(fs.node_count(), 3);
 }
 
-fn create_dir_fails_if_dir_already_exists<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_dir");
+#[test]
+fn fake_removing_root_fails_with_resource_busy() {
+    let fs = FakeFileSystem::new();
+    assert_eq!(fs.remove_dir("/").unwrap_err().kind(), ErrorKind::ResourceBusy);
 
-    fs.create_dir(&path).unwrap();
+    fs.write("/a.txt", "test text").unwrap();
+    assert_eq!(fs.remove_dir_all("/").unwrap_err().kind(), ErrorKind::ResourceBusy);
+    assert!(fs.is_file("/a.txt"));
+}
 
-    let result = fs.create_dir(&path);
+#[test]
+fn fake_writable_is_false_on_readonly_fs() {
+    let fs: FakeFileSystem = vec![("/dir/a.txt", "test text")].into_iter().collect();
+    fs.set_readonly_fs(true);
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert!(!fs.writable("/dir/a.txt").unwrap());
+    assert!(!fs.writable("/dir/b.txt").unwrap());
+    assert_eq!(fs.writable("/missing/b.txt").unwrap_err().kind(), ErrorKind::NotFound);
+
+    fs.set_readonly_fs(false);
+
+    assert!(fs.writable("/dir/a.txt").unwrap());
+    assert!(fs.writable("/dir/b.txt").unwrap());
 }
 
-fn create_dir_fails_if_parent_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("parent/new_dir");
+#[test]
+fn caching_writable_is_false_on_readonly_fake_fs() {
+    let fake: FakeFileSystem = vec![("/dir/a.txt", "test text")].into_iter().collect();
+    let fs = CachingFileSystem::new(fake.clone());
+    fake.set_readonly_fs(true);
 
-    let result = fs.create_dir(&path);
+    assert!(!fs.writable("/dir/a.txt").unwrap());
+    assert!(!fs.writable("/dir/b.txt").unwrap());
+}
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+#[test]
+fn fake_inject_error_fails_the_operation() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    fs.inject_error("/a.txt", FsOp::Open, ErrorKind::PermissionDenied);
+    fs.inject_error("/b.txt", FsOp::Create, ErrorKind::StorageFull);
+
+    assert_eq!(fs.open("/a.txt").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(fs.create("/b.txt").unwrap_err().kind(), ErrorKind::StorageFull);
+    assert!(!fs.exists("/b.txt"));
+    // other operations on the same path are unaffected
+    assert!(fs.metadata("/a.txt").is_ok());
+    assert!(fs.create("/a.txt").is_ok());
 }
 
-fn create_dir_all_creates_dirs_in_path<T: FileSystem>(fs: &T, parent: &Path) {
-    let result = fs.create_dir_all(parent.join("a/b/c"));
+#[test]
+fn fake_inject_error_fails_reads_through_open_file() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    let mut file = fs.open("/a.txt").unwrap();
+    fs.inject_error("/a.txt", FsOp::Read, ErrorKind::UnexpectedEof);
 
-    assert!(result.is_ok());
-    assert!(fs.is_dir(parent.join("a")));
-    assert!(fs.is_dir(parent.join("a/b")));
-    assert!(fs.is_dir(parent.join("a/b/c")));
+    let mut contents = String::new();
+    let result = file.read_to_string(&mut contents);
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
 }
 
-fn create_dir_all_still_succeeds_if_any_dir_already_exists<T: FileSystem>(fs: &T, parent: &Path) {
-    fs.create_dir_all(parent.join("a/b")).unwrap();
+#[test]
+fn fake_clear_errors_removes_injected_errors() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    fs.inject_error("/a.txt", FsOp::Write, ErrorKind::Other);
+    assert!(fs.write("/a.txt", "new text").is_err());
 
-    let result = fs.create_dir_all(parent.join("a/b/c"));
+    fs.clear_errors();
 
-    assert!(result.is_ok());
-    assert!(fs.is_dir(parent.join("a")));
-    assert!(fs.is_dir(parent.join("a/b")));
-    assert!(fs.is_dir(parent.join("a/b/c")));
+    assert!(fs.write("/a.txt", "new text").is_ok());
+    assert_eq!(fs.read_to_string("/a.txt").unwrap(), "new text");
 }
 
-fn remove_dir_deletes_dir<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("dir");
+#[test]
+fn fake_copy_file_verified_fails_if_copy_is_corrupted() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    fs.inject_corruption("/b.txt");
 
-    fs.create_dir(&path).unwrap();
+    assert_eq!(fs.copy_file("/a.txt", "/b.txt").unwrap(), 9);
+    assert_ne!(fs.read("/b.txt").unwrap(), b"test text");
 
-    let result = fs.remove_dir(&path);
+    let result = fs.copy_file_verified("/a.txt", "/b.txt");
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
 
-    assert!(result.is_ok());
-    assert!(!fs.is_dir(&path));
+    fs.clear_errors();
+    assert_eq!(fs.copy_file_verified("/a.txt", "/b.txt").unwrap(), 9);
+    assert_eq!(fs.read("/b.txt").unwrap(), b"test text");
 }
 
-fn remove_dir_does_not_affect_parent<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("parent/child");
-
-    fs.create_dir_all(&path).unwrap();
+#[test]
+fn fake_set_hidden_marks_node_hidden() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    assert!(!fs.is_hidden("/a.txt").unwrap());
 
-    let result = fs.remove_dir(&path);
+    fs.set_hidden("/a.txt", true).unwrap();
+    assert!(fs.is_hidden("/a.txt").unwrap());
 
-    assert!(result.is_ok());
-    assert!(fs.is_dir(parent.join("parent")));
-    assert!(!fs.is_dir(parent.join("child")));
+    fs.set_hidden("/a.txt", false).unwrap();
+    assert!(!fs.is_hidden("/a.txt").unwrap());
 }
 
-fn remove_dir_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let result = fs.remove_dir(parent.join("does_not_exist"));
+#[test]
+fn fake_capacity_fails_writes_beyond_it() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    fs.set_capacity(12);
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(fs.write("/b.txt", "more text").unwrap_err().kind(), ErrorKind::StorageFull);
+    assert_eq!(fs.set_len("/a.txt", 13).unwrap_err().kind(), ErrorKind::StorageFull);
+    assert_eq!(fs.copy_file("/a.txt", "/c.txt").unwrap_err().kind(), ErrorKind::StorageFull);
+    assert!(fs.write("/b.txt", "abc").is_ok());
+    assert_eq!(fs.total_bytes(), 12);
 }
 
-fn remove_dir_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("file");
+#[test]
+fn fake_capacity_is_reclaimed_by_remove_dir_all_report() {
+    let fs: FakeFileSystem = vec![("/dir/a.txt", "12345"), ("/dir/sub/b.txt", "1234567890")]
+        .into_iter()
+        .collect();
+    fs.set_capacity(20);
+    assert_eq!(fs.write("/c.txt", "1234567890").unwrap_err().kind(), ErrorKind::StorageFull);
 
-    create_file(fs, &path, "").unwrap();
+    assert_eq!(fs.remove_dir_all_report("/dir").unwrap(), 15);
 
-    let result = fs.remove_dir(&path);
-
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
-    assert!(fs.is_file(&path));
+    assert_eq!(fs.total_bytes(), 0);
+    assert!(fs.write("/c.txt", "1234567890").is_ok());
 }
 
-fn remove_dir_fails_if_dir_is_not_empty<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("dir");
-    let child = path.join("file");
-
-    fs.create_dir(&path).unwrap();
-    create_file(fs, &child, "").unwrap();
+#[test]
+fn fake_capacity_fails_writes_through_open_file() {
+    let fs = FakeFileSystem::new();
+    fs.set_capacity(4);
+    let mut file = fs.create("/a.txt").unwrap();
 
-    let result = fs.remove_dir(&path);
+    file.write_all(b"abcd").unwrap();
+    let result = file.write_all(b"e");
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
-    assert!(fs.is_dir(&path));
-    assert!(fs.is_file(&child));
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::StorageFull);
+    assert_eq!(fs.read("/a.txt").unwrap(), b"abcd");
 }
 
-fn remove_dir_all_removes_dir_and_contents<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("dir");
-    let child = path.join("file");
-
-    fs.create_dir(&path).unwrap();
-    create_file(fs, &child, "").unwrap();
+#[test]
+fn fake_capacity_is_freed_by_removing_files() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    fs.set_capacity(10);
+    assert!(fs.write("/b.txt", "more text").is_err());
 
-    let result = fs.remove_dir_all(&path);
+    fs.remove_file("/a.txt").unwrap();
 
-    assert!(result.is_ok());
-    assert!(!fs.is_dir(&path));
-    assert!(!fs.is_file(&child));
-    assert!(fs.is_dir(parent));
+    assert!(fs.write("/b.txt", "more text").is_ok());
 }
 
-fn remove_dir_all_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("file");
+#[test]
+fn fake_capacity_holds_for_concurrent_writers() {
+    let fs = FakeFileSystem::new();
+    fs.set_capacity(1000);
+    fs.write("/a.txt", "").unwrap();
 
-    create_file(fs, &path, "").unwrap();
+    thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                let mut file = fs.open_with_options("/a.txt", &OpenOptions::new().append(true)).unwrap();
+                while file.write(b"abc").is_ok() {}
+            });
+        }
+    });
 
-    let result = fs.remove_dir_all(&path);
+    assert_eq!(fs.total_bytes(), 999);
+    assert_eq!(fs.read("/a.txt").unwrap().len(), 999);
+}
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
-    assert!(fs.is_file(&path));
+#[test]
+fn fake_capacity_counts_hard_links_until_the_last_is_removed() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    fs.hard_link("/a.txt", "/b.txt").unwrap();
+    fs.set_capacity(10);
+
+    fs.remove_file("/a.txt").unwrap();
+    assert_eq!(fs.total_bytes(), 9);
+    assert_eq!(fs.write("/c.txt", "ab").unwrap_err().kind(), ErrorKind::StorageFull);
+
+    fs.remove_file("/b.txt").unwrap();
+    assert_eq!(fs.total_bytes(), 0);
+    assert!(fs.write("/c.txt", "ab").is_ok());
 }
 
-#[cfg(unix)]
-fn remove_dir_all_removes_dir_and_contents_if_descendant_not_writable<
-    T: FileSystem,
->(
-    fs: &T,
-    parent: &Path,
-) {
-    let mode = 0o555;
+#[test]
+fn fake_create_durable_syncs_file_and_directory() {
+    let fs = FakeFileSystem::new();
+    fs.create_dir("/dir").unwrap();
 
-    let path = parent.join("dir");
-    let child = path.join("child");
+    fs.create_durable("/dir/a.txt", "test text").unwrap();
 
-    fs.create_dir(&path).unwrap();
-    fs.create_dir(&child).unwrap();
+    assert_eq!(fs.file_syncs(), 1);
+    assert_eq!(fs.dir_syncs(), 1);
+}
 
-    set_mode(fs, &child, mode).unwrap();
+#[test]
+fn fake_readonly_fs_fails_changes() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    let mut file = fs.open_with_options("/a.txt", &OpenOptions::new().write(true)).unwrap();
+    fs.set_readonly_fs(true);
 
-    let result = fs.remove_dir_all(&path);
+    assert_eq!(fs.create("/b.txt").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(fs.write("/a.txt", "new text").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(fs.create_dir("/dir").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(fs.remove_file("/a.txt").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(fs.rename("/a.txt", "/b.txt").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(fs.copy_file("/a.txt", "/b.txt").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    let result = fs.open_with_options("/a.txt", &OpenOptions::new().append(true));
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(file.write(b"new").unwrap_err().kind(), ErrorKind::PermissionDenied);
 
-    assert!(result.is_ok());
-    assert!(!fs.is_dir(&path));
-    assert!(!fs.is_dir(&child));
+    assert_eq!(fs.read_to_string("/a.txt").unwrap(), "test text");
+    assert_eq!(fs.metadata("/a.txt").unwrap().len(), 9);
 }
 
-#[cfg(unix)]
-fn remove_dir_all_removes_dir_and_contents_if_descendant_not_executable<
-    T: FileSystem,
->(
-    fs: &T,
-    parent: &Path,
-) {
-    let mode = 0o666;
+#[test]
+fn fake_readonly_fs_can_be_turned_off() {
+    let fs = FakeFileSystem::new();
+    fs.set_readonly_fs(true);
+    assert!(fs.create_dir("/dir").is_err());
 
-    let path = parent.join("dir");
-    let child = path.join("child");
+    fs.set_readonly_fs(false);
 
-    fs.create_dir(&path).unwrap();
-    fs.create_dir(&child).unwrap();
+    assert!(fs.create_dir("/dir").is_ok());
+}
 
-    set_mode(fs, &child, mode).unwrap();
+#[cfg(unix)]
+#[test]
+fn fake_chown_changes_owner() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    assert_eq!(fs.metadata("/a.txt").unwrap().uid(), 0);
 
-    let result = fs.remove_dir_all(&path);
+    fs.chown("/a.txt", Some(1000), None).unwrap();
+    fs.chown("/a.txt", None, Some(100)).unwrap();
 
-    assert!(result.is_ok());
-    assert!(!fs.is_dir(&path));
-    assert!(!fs.is_dir(&child));
+    let metadata = fs.metadata("/a.txt").unwrap();
+    assert_eq!(metadata.uid(), 1000);
+    assert_eq!(metadata.gid(), 100);
 }
 
 #[cfg(unix)]
-fn remove_dir_all_fails_if_descendant_not_readable<T: FileSystem>(
-    fs: &T,
-    parent: &Path,
-) {
-    let mode = 0o333;
+#[test]
+fn fake_copy_dir_all_preserving_owners_copies_owners() {
+    let fs: FakeFileSystem = vec![("/from/dir/a.txt", "test text")].into_iter().collect();
+    fs.chown("/from/dir", Some(1000), Some(1000)).unwrap();
+    fs.chown("/from/dir/a.txt", Some(1001), Some(100)).unwrap();
 
-    let path = parent.join("dir");
-    let child = path.join("child");
+    fs.copy_dir_all_preserving_owners("/from", "/to").unwrap();
 
-    fs.create_dir(&path).unwrap();
-    fs.create_dir(&child).unwrap();
+    let dir = fs.metadata("/to/dir").unwrap();
+    let file = fs.metadata("/to/dir/a.txt").unwrap();
+    assert_eq!((dir.uid(), dir.gid()), (1000, 1000));
+    assert_eq!((file.uid(), file.gid()), (1001, 100));
 
-    set_mode(fs, &child, mode).unwrap();
+    fs.copy_dir_all("/from", "/plain").unwrap();
+    assert_eq!(fs.metadata("/plain/dir/a.txt").unwrap().uid(), 0);
+}
 
-    let result = fs.remove_dir_all(&path);
+#[test]
+fn fake_write_batch_writes_nothing_if_a_file_is_readonly() {
+    let fs = FakeFileSystem::new();
+    fs.write("/a.txt", "old a").unwrap();
+    fs.write("/b.txt", "old b").unwrap();
+    set_readonly(&fs, "/b.txt", true).unwrap();
+
+    let result = fs.write_batch(&[
+        (PathBuf::from("/a.txt"), b"new a".to_vec()),
+        (PathBuf::from("/b.txt"), b"new b".to_vec()),
+        (PathBuf::from("/c.txt"), b"new c".to_vec()),
+    ]);
 
-    assert!(result.is_err());
     assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
-    assert!(fs.is_dir(&path));
-    assert!(fs.is_dir(&child));
+    assert_eq!(fs.read_to_string("/a.txt").unwrap(), "old a");
+    assert_eq!(fs.read_to_string("/b.txt").unwrap(), "old b");
+    assert!(!fs.exists("/c.txt"));
 }
 
-fn read_dir_returns_dir_entries<T: FileSystem>(fs: &T, parent: &Path) {
-    let file1 = parent.join("file1");
-    let file2 = parent.join("file2");
-    let dir1 = parent.join("dir1");
-    let dir2 = parent.join("dir2");
-    let file3 = dir1.join("file3");
-    let file4 = dir2.join("file4");
+#[test]
+fn fake_write_batch_writes_nothing_if_a_parent_does_not_exist() {
+    let fs = FakeFileSystem::new();
+    fs.write("/a.txt", "old a").unwrap();
 
-    create_file(fs, &file1, "").unwrap();
-    create_file(fs, &file2, "").unwrap();
-    fs.create_dir(&dir1).unwrap();
-    fs.create_dir(&dir2).unwrap();
-    create_file(fs, &file3, "").unwrap();
-    create_file(fs, &file4, "").unwrap();
+    let result = fs.write_batch(&[
+        (PathBuf::from("/a.txt"), b"new a".to_vec()),
+        (PathBuf::from("/missing/b.txt"), b"new b".to_vec()),
+    ]);
 
-    let result = fs.read_dir(parent);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(fs.read_to_string("/a.txt").unwrap(), "old a");
+}
+
+#[test]
+fn fake_write_batch_counts_each_file_once_against_capacity() {
+    let fs = FakeFileSystem::new();
+    fs.write("/a.txt", "").unwrap();
+    fs.hard_link("/a.txt", "/b.txt").unwrap();
+    fs.set_capacity(10);
+
+    let result = fs.write_batch(&[
+        (PathBuf::from("/a.txt"), b"123456".to_vec()),
+        (PathBuf::from("/b.txt"), b"1234".to_vec()),
+        (PathBuf::from("/c.txt"), b"123456".to_vec()),
+        (PathBuf::from("/c.txt"), b"12".to_vec()),
+    ]);
 
     assert!(result.is_ok());
+    assert_eq!(fs.read_to_string("/a.txt").unwrap(), "1234");
+    assert_eq!(fs.read_to_string("/c.txt").unwrap(), "12");
+    assert_eq!(fs.total_bytes(), 6);
+}
 
-    let mut entries: Vec<PathBuf> = result.unwrap().map(|e| e.unwrap().path()).collect();
-    let expected_paths = &mut [file1, file2, dir1, dir2];
+#[test]
+fn fake_read_dir_lists_entries_as_they_were_when_read() {
+    let fs = FakeFileSystem::new();
+    fs.create_dir_all("/dir").unwrap();
+    fs.write("/dir/a", "").unwrap();
+    fs.write("/dir/b", "").unwrap();
 
-    entries.sort();
-    expected_paths.sort();
+    let entries = fs.read_dir("/dir").unwrap();
+    fs.write("/dir/c", "").unwrap();
+    fs.remove_file("/dir/b").unwrap();
 
-    assert_eq!(&entries, expected_paths);
+    let entries: Vec<_> = entries.map(|e| e.unwrap()).collect();
+    let names: Vec<_> = entries.iter().map(|e| e.file_name()).collect();
+    assert_eq!(names, vec!["a", "b"]);
+    assert!(entries[0].metadata().is_ok());
+    assert_eq!(entries[1].metadata().unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn read_dir_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("does_not_exist");
-    let result = fs.read_dir(&path);
+#[test]
+fn fake_exclusive_write_fails_while_first_writer_is_open() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    let options = OpenOptions::new().write(true).exclusive_write(true);
 
-    assert!(result.is_err());
+    let first = fs.open_with_options("/a.txt", &options).unwrap();
+    let result = fs.open_with_options("/a.txt", &options);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
 
-    match result {
-        Ok(_) => panic!("should be an err"),
-        Err(err) => assert_eq!(err.kind(), ErrorKind::NotFound),
-    }
+    drop(first);
+    assert!(fs.open_with_options("/a.txt", &options).is_ok());
 }
 
-fn read_dir_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("file");
+#[test]
+fn fake_exclusive_write_is_refused_by_any_writer_but_not_by_readers() {
+    let fs: FakeFileSystem = vec![("/a.txt", "test text")].into_iter().collect();
+    let options = OpenOptions::new().write(true).exclusive_write(true);
 
-    create_file(fs, &path, "").unwrap();
+    let reader = fs.open("/a.txt").unwrap();
+    let exclusive = fs.open_with_options("/a.txt", &options).unwrap();
+    drop(exclusive);
 
-    let result = fs.read_dir(&path);
+    let writer = fs.open_with_options("/a.txt", &OpenOptions::new().append(true)).unwrap();
+    let clone = writer.try_clone().unwrap();
+    drop(writer);
+    let result = fs.open_with_options("/a.txt", &options);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::WouldBlock);
 
-    assert!(result.is_err());
-    match result {
-        Ok(_) => panic!("should be an err"),
-        Err(err) => assert_eq!(err.kind(), ErrorKind::Other),
-    }
+    drop(clone);
+    assert!(fs.open_with_options("/a.txt", &options).is_ok());
+    drop(reader);
 }
 
-fn create_object_writes_to_new_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_file");
-    let mut writer = fs.create(&path).unwrap();
-    let result = writer.write_all(b"new contents");
-
-    assert!(result.is_ok());
+#[test]
+fn fake_case_insensitive_finds_node_by_any_case() {
+    let fs = FakeFileSystem::new();
+    fs.set_case_insensitive(true);
+    fs.create_dir("/Dir").unwrap();
+    fs.write("/dir/file.txt", "test text").unwrap();
 
-    let contents = read_file(fs, path).unwrap();
+    assert_eq!(fs.read("/DIR/FILE.TXT").unwrap(), b"test text");
+    fs.write("/Dir/File.txt", "new text").unwrap();
+    assert_eq!(fs.create_dir("/DIR").unwrap_err().kind(), ErrorKind::AlreadyExists);
 
-    assert_eq!(&contents, b"new contents");
+    // names keep the case they were created with
+    assert_eq!(fs.read_dir_names("/").unwrap(), vec![("Dir".into(), NodeKind::Dir)]);
+    assert_eq!(fs.read_dir_names("/Dir").unwrap(), vec![("file.txt".into(), NodeKind::File)]);
+    assert_eq!(fs.read("/dir/file.txt").unwrap(), b"new text");
 }
 
-fn create_object_fails_if_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
+#[test]
+fn fake_case_insensitive_rename_changes_only_case() {
+    let fs = FakeFileSystem::new();
+    fs.set_case_insensitive(true);
+    fs.write("/file.txt", "test text").unwrap();
 
-    create_file(fs, &path, "").unwrap();
-    set_readonly(fs, &path, true).unwrap();
+    fs.rename("/file.txt", "/File.txt").unwrap();
 
-    let result = fs.create(&path);
+    let names: Vec<_> = fs.read_dir("/").unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(names, vec!["File.txt"]);
+    assert_eq!(fs.read("/File.txt").unwrap(), b"test text");
+}
+
+#[cfg(unix)]
+#[test]
+fn fake_case_insensitive_rename_keeps_node_if_dir_is_readonly() {
+    let fs = FakeFileSystem::new();
+    fs.set_case_insensitive(true);
+    fs.create_dir("/dir").unwrap();
+    fs.write("/dir/file.txt", "test text").unwrap();
+    set_mode(&fs, "/dir", 0o555).unwrap();
+
+    let result = fs.rename("/dir/file.txt", "/dir/File.txt");
 
-    assert!(result.is_err());
     assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(fs.read_dir_names("/dir").unwrap(), vec![("file.txt".into(), NodeKind::File)]);
+    assert_eq!(fs.read("/dir/file.txt").unwrap(), b"test text");
 }
 
-fn write_file_writes_to_new_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_file");
-    let result = write_file(fs, &path, "new contents");
+#[cfg(unix)]
+#[test]
+fn fake_case_insensitive_rename_replaces_node_with_non_unicode_name() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
 
-    assert!(result.is_ok());
+    let fs = FakeFileSystem::new();
+    fs.set_case_insensitive(true);
+    let from = Path::new("/").join(OsStr::from_bytes(b"a\xff"));
+    let to = Path::new("/").join(OsStr::from_bytes(b"b\xff"));
+    fs.write(&from, "first").unwrap();
+    fs.write(&to, "second").unwrap();
 
-    let contents = String::from_utf8(read_file(fs, path).unwrap()).unwrap();
+    fs.rename(&from, &to).unwrap();
 
-    assert_eq!(&contents, "new contents");
+    assert!(!fs.exists(&from));
+    assert_eq!(fs.read(&to).unwrap(), b"first");
 }
 
-fn write_file_overwrites_contents_of_existing_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
+#[test]
+fn fake_case_insensitive_rename_replaces_node_differing_only_in_case() {
+    let fs = FakeFileSystem::new();
+    fs.write("/x", "first").unwrap();
+    fs.write("/X", "second").unwrap();
+    fs.set_case_insensitive(true);
 
-    write_file(fs, &path, "old contents").unwrap();
+    fs.rename("/x", "/X").unwrap();
 
-    let result = write_file(fs, &path, "new contents");
+    assert_eq!(fs.read_dir_names("/").unwrap(), vec![("X".into(), NodeKind::File)]);
+    assert_eq!(fs.read("/X").unwrap(), b"first");
+    assert_eq!(fs.total_bytes(), 5);
+}
 
-    assert!(result.is_ok());
+#[test]
+fn fake_case_sensitive_rename_keeps_names_distinct() {
+    let fs = FakeFileSystem::new();
+    fs.write("/file.txt", "first").unwrap();
+    fs.write("/File.txt", "second").unwrap();
 
-    let contents = String::from_utf8(read_file(fs, path).unwrap()).unwrap();
+    fs.rename("/file.txt", "/File.txt").unwrap();
 
-    assert_eq!(&contents, "new contents");
+    let names: Vec<_> = fs.read_dir("/").unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(names, vec!["File.txt"]);
+    assert_eq!(fs.read("/File.txt").unwrap(), b"first");
+    assert!(!fs.exists("/file.txt"));
 }
 
-fn write_file_fails_if_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
-
-    create_file(fs, &path, "").unwrap();
-    set_readonly(fs, &path, true).unwrap();
+#[test]
+fn fake_create_dir_all_names_the_file_in_the_way() {
+    let fs = FakeFileSystem::new();
+    fs.create_dir("/a").unwrap();
+    fs.write("/a/b", b"").unwrap();
 
-    let result = write_file(fs, &path, "test contents");
+    let err = fs.create_dir_all("/a/b/c").unwrap_err();
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(err.kind(), ErrorKind::NotADirectory);
+    assert_eq!(err.to_string(), "/a/b is not a directory");
+    assert!(fs.is_file("/a/b"));
 }
 
-fn write_file_fails_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_dir");
-
-    fs.create_dir(&path).unwrap();
+#[test]
+fn fake_access_mode_errors_are_permission_denied() {
+    let fs = FakeFileSystem::new();
 
-    let result = write_file(fs, &path, "test contents");
+    let read = fs.create("/test.txt").unwrap().read(&mut [0; 4]);
+    let write = fs.open("/test.txt").unwrap().write(b"test");
+    let set_len = fs.open("/test.txt").unwrap().set_len(2);
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+    assert_eq!(read.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(write.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(set_len.unwrap_err().kind(), ErrorKind::PermissionDenied);
 }
 
-fn overwrite_file_overwrites_contents_of_existing_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
-
-    write_file(fs, &path, "old contents").unwrap();
+// Only the fake always updates the access time, the OS may be mounted with noatime.
+#[test]
+fn fake_accessed_is_updated_by_read() {
+    let fs = FakeFileSystem::new();
+    fs.write("/test.txt", "test text").unwrap();
+    let past = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    fs.open("/test.txt").unwrap().set_times(FileTimes::new().set_accessed(past)).unwrap();
 
-    let result = overwrite_file(fs, &path, "new contents");
+    fs.read("/test.txt").unwrap();
 
-    assert!(result.is_ok());
+    assert!(fs.metadata("/test.txt").unwrap().accessed().unwrap() > past);
+}
 
-    let contents = String::from_utf8(read_file(fs, path).unwrap()).unwrap();
+#[test]
+fn fake_canonicalize_fails_if_subpath_is_file_on_every_platform() {
+    let fs = FakeFileSystem::new();
+    let temp_dir = fs.temp_dir("test").unwrap();
 
-    assert_eq!(&contents, "new contents");
+    canonicalize_fails_if_subpath_is_file(&fs, temp_dir.path());
 }
 
-fn overwrite_file_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("new_file");
-    let result = overwrite_file(fs, &path, "new contents");
+#[test]
+fn fake_max_path_depth_fails_for_deeper_path() {
+    let fs = FakeFileSystem::new();
+    fs.set_max_path_depth(Some(3));
+
+    let result = fs.create_dir_all("/a/b/c/d");
 
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(!fs.exists("/a"));
 }
 
-fn overwrite_file_fails_if_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
+#[test]
+fn fake_max_path_depth_applies_to_queries() {
+    let fs = FakeFileSystem::new();
+    fs.create_dir_all("/a/b/c/d").unwrap();
+    fs.write("/a/b/c/d/e.txt", "test text").unwrap();
+    fs.set_max_path_depth(Some(3));
 
-    create_file(fs, &path, "").unwrap();
-    set_readonly(fs, &path, true).unwrap();
+    assert!(!fs.exists("/a/b/c/d"));
+    assert!(!fs.is_dir("/a/b/c/d"));
+    assert!(!fs.is_file("/a/b/c/d/e.txt"));
+    assert_eq!(fs.try_exists("/a/b/c/d").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(fs.canonicalize("/a/b/c/d").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(fs.is_hidden("/a/b/c/d").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(fs.set_current_dir("/a/b/c/d").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(fs.sync_dir("/a/b/c/d").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(fs.find_duplicates("/a/b/c/d").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(fs.is_dir("/a/b/c"));
+}
 
-    let result = overwrite_file(fs, &path, "test contents");
+#[test]
+fn fake_max_path_depth_can_be_removed() {
+    let fs = FakeFileSystem::new();
+    fs.set_max_path_depth(Some(3));
+    assert!(fs.create_dir_all("/a/b/c/d").is_err());
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    fs.set_max_path_depth(None);
+
+    assert!(fs.create_dir_all("/a/b/c/d").is_ok());
+    assert!(fs.is_dir("/a/b/c/d"));
 }
 
-fn overwrite_file_fails_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_dir");
+#[test]
+fn fake_max_path_depth_allows_path_within_limit() {
+    let fs = FakeFileSystem::new();
+    fs.set_max_path_depth(Some(3));
 
-    fs.create_dir(&path).unwrap();
+    let result = fs.create_dir_all("/a/b/c");
 
-    let result = overwrite_file(fs, &path, "test contents");
+    assert!(result.is_ok());
+    assert!(fs.is_dir("/a/b/c"));
+}
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+#[test]
+fn fake_clones_share_current_dir() {
+    let fs = FakeFileSystem::new();
+    let clone = fs.clone();
+    fs.create_dir("/dir").unwrap();
+
+    clone.set_current_dir("/dir").unwrap();
+
+    assert_eq!(fs.current_dir().unwrap(), Path::new("/dir"));
 }
 
-fn read_file_returns_contents_as_bytes<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
+#[test]
+fn fake_with_independent_cwd_has_its_own_current_dir() {
+    let fs = FakeFileSystem::new();
+    fs.create_dir("/a").unwrap();
+    fs.create_dir("/b").unwrap();
+    fs.set_current_dir("/a").unwrap();
+    let other = fs.with_independent_cwd();
 
-    write_file(fs, &path, "test text").unwrap();
+    assert_eq!(other.current_dir().unwrap(), Path::new("/a"));
 
-    let result = read_file(fs, &path);
+    other.set_current_dir("/b").unwrap();
+    other.write("test.txt", "test").unwrap();
 
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), br"test text");
-}
+    assert_eq!(fs.current_dir().unwrap(), Path::new("/a"));
+    assert!(!fs.exists("test.txt"));
+    assert_eq!(fs.read_to_string("/b/test.txt").unwrap(), "test");
 
-fn read_file_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    let result = read_file(fs, &path);
+    fs.set_current_dir("/").unwrap();
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(other.current_dir().unwrap(), Path::new("/b"));
+    assert_eq!(other.clone().current_dir().unwrap(), Path::new("/b"));
 }
 
-fn read_file_to_string_returns_contents_as_string<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
+#[test]
+fn fake_dump_to_os_copies_tree() {
+    let os = OsFileSystem::new();
+    let temp_dir = os.temp_dir("test").unwrap();
+    let root = temp_dir.path().join("dump");
+    let fs = FakeFileSystem::new();
+    fs.create_dir_all("/a/b").unwrap();
+    fs.write("/a/b/c.txt", "test text").unwrap();
+    fs.write("/binary", [0, 159, 146, 150]).unwrap();
+    #[cfg(unix)]
+    {
+        set_mode(&fs, "/binary", 0o600).unwrap();
+        set_mode(&fs, "/a", 0o500).unwrap();
+        fs.symlink("a/b/c.txt", "/link").unwrap();
+    }
 
-    write_file(fs, &path, "test text").unwrap();
+    fs.dump_to_os(&root, false).unwrap();
+
+    assert!(os.is_dir(root.join("a/b")));
+    assert_eq!(os.read_to_string(root.join("a/b/c.txt")).unwrap(), "test text");
+    assert_eq!(os.read(root.join("binary")).unwrap(), [0, 159, 146, 150]);
+    #[cfg(unix)]
+    {
+        assert_eq!(mode(&os, root.join("binary")).unwrap() & 0o7777, 0o600);
+        assert_eq!(mode(&os, root.join("a")).unwrap() & 0o7777, 0o500);
+        assert_eq!(mode(&os, root.join("a/b")).unwrap() & 0o7777, 0o755);
+        assert_eq!(os.read_link(root.join("link")).unwrap(), Path::new("a/b/c.txt"));
+        set_mode(&os, root.join("a"), 0o755).unwrap();
+    }
+}
 
-    let result = read_file_to_string(fs, &path);
+#[test]
+fn fake_dump_to_os_fails_if_root_is_not_empty() {
+    let os = OsFileSystem::new();
+    let temp_dir = os.temp_dir("test").unwrap();
+    let root = temp_dir.path();
+    os.write(root.join("test.txt"), "old text").unwrap();
+    let fs = FakeFileSystem::new();
+    fs.write("/test.txt", "new text").unwrap();
 
-    assert!(result.is_ok());
-    assert_eq!(&result.unwrap(), "test text");
+    let result = fs.dump_to_os(root, false);
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert_eq!(os.read_to_string(root.join("test.txt")).unwrap(), "old text");
 }
 
-fn read_file_to_string_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    let result = read_file_to_string(fs, &path);
+#[test]
+fn fake_dump_to_os_with_overwrite_replaces_nodes_in_the_way() {
+    let os = OsFileSystem::new();
+    let temp_dir = os.temp_dir("test").unwrap();
+    let root = temp_dir.path();
+    os.create_dir(root.join("dir")).unwrap();
+    os.write(root.join("dir/kept.txt"), "kept text").unwrap();
+    os.write(root.join("test.txt"), "old text").unwrap();
+    let fs = FakeFileSystem::new();
+    fs.create_dir("/dir").unwrap();
+    fs.write("/test.txt", "new text").unwrap();
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
-}
+    fs.dump_to_os(root, true).unwrap();
 
-fn read_file_to_string_fails_if_contents_are_not_utf8<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
+    assert_eq!(os.read_to_string(root.join("test.txt")).unwrap(), "new text");
+    assert_eq!(os.read_to_string(root.join("dir/kept.txt")).unwrap(), "kept text");
+}
 
-    write_file(fs, &path, &[0, 159, 146, 150]).unwrap();
+#[test]
+fn fake_dump_to_os_round_trips_through_load_from_os() {
+    let os = OsFileSystem::new();
+    let temp_dir = os.temp_dir("test").unwrap();
+    let fs = FakeFileSystem::new();
+    fs.create_dir_all("/a/b").unwrap();
+    fs.write("/a/b/c.txt", "test text").unwrap();
 
-    let result = read_file_to_string(fs, &path);
+    fs.dump_to_os(temp_dir.path(), false).unwrap();
+    let loaded = FakeFileSystem::load_from_os(temp_dir.path()).unwrap();
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(loaded.read_to_string("/a/b/c.txt").unwrap(), "test text");
 }
 
-fn read_file_into_writes_bytes_to_buffer<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    let text = "test text";
+#[test]
+fn fake_try_clone_has_its_own_cursor() {
+    let fs = FakeFileSystem::new();
+    let path = Path::new("/test.txt");
+    fs.write(path, "test text").unwrap();
+    let mut reader = fs.open(path).unwrap();
+    let mut clone = reader.try_clone().unwrap();
 
-    write_file(fs, &path, text).unwrap();
-    let mut buf = Vec::new();
+    let mut buf = [0; 5];
+    reader.read_exact(&mut buf).unwrap();
 
-    let result = read_file_into(fs, &path, &mut buf);
+    assert_eq!(reader.stream_position().unwrap(), 5);
+    assert_eq!(clone.stream_position().unwrap(), 0);
+}
 
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), text.as_bytes().len());
-    assert_eq!(buf, br"test text");
+#[cfg(unix)]
+#[test]
+fn os_metadata_kind_of_device_is_char_device() {
+    let fs = OsFileSystem::new();
+
+    let kind = fs.metadata("/dev/null").unwrap().kind();
+
+    assert_eq!(kind, NodeKind::CharDevice);
 }
 
-fn read_file_into_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
+#[test]
+fn os_exclusive_write_is_unsupported() {
+    let fs = OsFileSystem::new();
+    let temp_dir = fs.temp_dir("test").unwrap();
+    let path = temp_dir.path().join("test.txt");
+    fs.write(&path, "test text").unwrap();
 
-    let result = read_file_into(fs, &path, &mut Vec::new());
+    let options = OpenOptions::new().write(true).exclusive_write(true);
+    let result = fs.open_with_options(&path, &options);
 
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::Unsupported);
 }
 
-fn open_object_writes_bytes_to_buffer<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    let text = "test text";
+#[cfg(target_os = "linux")]
+#[test]
+fn os_access_mode_errors_are_ebadf() {
+    let fs = OsFileSystem::new();
+    let temp_dir = fs.temp_dir("test").unwrap();
+    let path = temp_dir.path().join("test.txt");
 
-    write_file(fs, &path, text).unwrap();
-    let mut buf = Vec::new();
+    let read = fs.create(&path).unwrap().read(&mut [0; 4]);
+    let write = fs.open(&path).unwrap().write(b"test");
 
-    let mut reader = fs.open(&path).unwrap();
-    let result = reader.read_to_end(&mut buf);
+    assert_eq!(read.unwrap_err().raw_os_error(), Some(libc::EBADF));
+    assert_eq!(write.unwrap_err().raw_os_error(), Some(libc::EBADF));
+}
 
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), text.as_bytes().len());
-    assert_eq!(buf, br"test text");
-}
-
-fn open_object_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
+#[test]
+fn os_has_changed_since_open_is_unsupported_for_bare_file() {
+    let fs = OsFileSystem::new();
+    let temp_dir = fs.temp_dir("test").unwrap();
+    let path = temp_dir.path().join("test.txt");
+    fs.write(&path, "test text").unwrap();
 
-    let result = fs.open(&path);
+    let result = fs.open(&path).unwrap().into_inner().has_changed_since_open();
 
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::Unsupported);
 }
 
-fn create_file_writes_to_new_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
-    let result = create_file(fs, &path, "new contents");
-
-    assert!(result.is_ok());
-
-    let contents = String::from_utf8(read_file(fs, path).unwrap()).unwrap();
+// Used to be part of the public API.
+// Keep around for the tests.
+fn read_file_into<T, P, B>(fs: &T, path: P, mut buf: B) -> io::Result<usize>
+        where
+            T: FileSystem,
+            P: AsRef<Path>,
+            B: AsMut<Vec<u8>> {
 
-    assert_eq!(&contents, "new contents");
+    let mut reader = fs.open(path)?;
+    reader.read_to_end(buf.as_mut())
 }
 
-fn create_file_fails_if_file_already_exists<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
-
-    create_file(fs, &path, "contents").unwrap();
+// Used to be part of the public API.
+// Keep around for the tests.
+fn create_file<T, P, B>(fs: &T, path: P, buf: B) -> io::Result<()>
+where
+    T: FileSystem,
+    P: AsRef<Path>,
+    B: AsRef<[u8]>,
+{
+    let opts = OpenOptions::new().write(true).create_new(true);
+    let mut writer = fs.open_with_options(path, &opts)?;
+    writer.write_all(buf.as_ref())
+}
 
-    let result = create_file(fs, &path, "new contents");
+// Used to be part of the public API.
+// Keep around for the tests.
+fn overwrite_file<T, P, B>(fs: &T, path: P, buf: B) -> io::Result<()>
+where
+    T: FileSystem,
+    P: AsRef<Path>,
+    B: AsRef<[u8]>
+{
+    let opts = OpenOptions::new().write(true).truncate(true);
+    let mut writer = fs.open_with_options(path, &opts)?;
+    writer.write_all(buf.as_ref())
+}
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+// Used to be part of the public API.
+// Keep around for the tests.
+fn set_readonly<T: FileSystem, P: AsRef<Path>>(fs: &T, path: P, readonly: bool) -> io::Result<()>
+{
+    let mut p = fs.metadata(&path)?.permissions();
+    p.set_readonly(readonly);
+    fs.set_permissions(&path, p)
 }
 
-fn remove_file_removes_a_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
+// Used to be part of the public API.
+// Keep around for the tests.
+fn readonly<P: AsRef<Path>, T: FileSystem>(fs: &T, path: P) -> io::Result<bool>
+{
+    Ok(fs.metadata(&path)?.permissions().readonly())
+}
 
-    create_file(fs, &path, "").unwrap();
+// Used to be part of the public API.
+// Keep around for the tests.
+#[cfg(unix)]
+fn set_mode<P: AsRef<Path>, T: FileSystem>(fs: &T, path: P, mode: u32) -> io::Result<()> {
+    let mut perms = fs.metadata(&path)?.permissions();
+    perms.set_mode(mode);
+    fs.set_permissions(&path, perms)
+}
 
-    let result = fs.remove_file(&path);
+// Used to be part of the public API.
+// Keep around for the tests.
+#[cfg(unix)]
+fn mode<P: AsRef<Path>, T: FileSystem>(fs: &T, path: P) -> io::Result<u32> {
+    Ok(fs.metadata(&path)?.permissions().mode())
+}
 
-    assert!(result.is_ok());
+fn set_current_dir_fails_if_node_does_not_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("does_not_exist");
 
-    let result = read_file(fs, &path);
+    let result = fs.set_current_dir(path);
 
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn remove_file_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let result = fs.remove_file(parent.join("does_not_exist"));
+fn set_current_dir_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
+
+    create_file(fs, &path, "").unwrap();
+
+    let result = fs.set_current_dir(path);
 
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
 }
 
-fn remove_file_fails_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_dir");
+fn is_dir_returns_true_if_node_is_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_dir");
 
     fs.create_dir(&path).unwrap();
 
-    let result = fs.remove_file(&path);
+    assert!(fs.is_dir(&path));
+}
 
-    assert!(result.is_err());
+fn is_dir_returns_false_if_node_is_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_dir");
 
-    let expected_error = if cfg!(target_os = "macos") {
-        ErrorKind::PermissionDenied
-    } else {
-        ErrorKind::Other
-    };
+    create_file(fs, &path, "").unwrap();
 
-    assert_eq!(result.unwrap_err().kind(), expected_error);
+    assert!(!fs.is_dir(&path));
 }
 
-fn copy_file_copies_a_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
-
-    create_file(fs, &from, "test").unwrap();
-
-    let result = fs.copy_file(&from, &to);
+fn is_dir_returns_false_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    assert!(!fs.is_dir(parent.join("does_not_exist")));
+}
 
-    assert!(result.is_ok());
+fn is_file_returns_true_if_node_is_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_file");
 
-    let result = read_file(fs, &to);
+    create_file(fs, &path, "").unwrap();
 
-    assert!(result.is_ok());
-    assert_eq!(&result.unwrap(), b"test");
+    assert!(fs.is_file(&path));
 }
 
-fn copy_file_overwrites_destination_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
+fn is_file_returns_false_if_node_is_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_dir");
 
-    create_file(fs, &from, "expected").unwrap();
-    create_file(fs, &to, "should be overwritten").unwrap();
+    fs.create_dir(&path).unwrap();
 
-    let result = fs.copy_file(&from, &to);
+    assert!(!fs.is_file(&path));
+}
 
-    assert!(result.is_ok());
+fn is_file_returns_false_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    assert!(!fs.is_file(parent.join("does_not_exist")));
+}
 
-    let result = read_file(fs, &to);
+#[cfg(unix)]
+fn is_hidden_returns_true_if_name_starts_with_dot<T: FileSystem>(fs: &T, parent: &Path) {
+    let hidden = parent.join(".hidden");
+    let visible = parent.join("visible");
+    fs.create(&hidden).unwrap();
+    fs.create(&visible).unwrap();
 
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), b"expected");
+    assert!(fs.is_hidden(&hidden).unwrap());
+    assert!(!fs.is_hidden(&visible).unwrap());
 }
 
-fn copy_file_fails_if_original_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
-
-    let result = fs.copy_file(&from, &to);
+fn is_hidden_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.is_hidden(parent.join("does_not_exist"));
 
-    assert!(result.is_err());
     assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
-    assert!(!fs.is_file(&to));
 }
 
-fn copy_file_fails_if_destination_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
+#[cfg(unix)]
+fn is_readable_and_is_writable_follow_mode<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    create_file(fs, &path, "").unwrap();
 
-    create_file(fs, &from, "test").unwrap();
-    create_file(fs, &to, "").unwrap();
-    set_readonly(fs, &to, true).unwrap();
+    set_mode(fs, &path, 0o000).unwrap();
 
-    let result = fs.copy_file(&from, &to);
+    assert!(!fs.is_readable(&path).unwrap());
+    assert!(!fs.is_writable(&path).unwrap());
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
-}
+    set_mode(fs, &path, 0o400).unwrap();
 
-fn copy_file_fails_if_original_node_is_directory<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
+    assert!(fs.is_readable(&path).unwrap());
+    assert!(!fs.is_writable(&path).unwrap());
 
-    fs.create_dir(&from).unwrap();
+    set_mode(fs, &path, 0o600).unwrap();
 
-    let result = fs.copy_file(&from, &to);
+    assert!(fs.is_readable(&path).unwrap());
+    assert!(fs.is_writable(&path).unwrap());
+}
 
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+#[cfg(unix)]
+fn writable_follows_mode_of_file_or_parent_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    let file = dir.join("test.txt");
+    let missing = dir.join("missing.txt");
+    fs.create_dir(&dir).unwrap();
+    create_file(fs, &file, "").unwrap();
+
+    set_mode(fs, &file, 0o400).unwrap();
+    assert!(!fs.writable(&file).unwrap());
+    set_mode(fs, &file, 0o600).unwrap();
+    assert!(fs.writable(&file).unwrap());
+
+    set_mode(fs, &dir, 0o500).unwrap();
+    assert!(!fs.writable(&missing).unwrap());
+    set_mode(fs, &dir, 0o700).unwrap();
+    assert!(fs.writable(&missing).unwrap());
 }
 
-fn copy_file_fails_if_destination_node_is_directory<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
+fn writable_is_false_for_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
 
-    create_file(fs, &from, "").unwrap();
-    fs.create_dir(&to).unwrap();
+    assert!(!fs.writable(&dir).unwrap());
+    assert!(fs.writable(dir.join("new.txt")).unwrap());
+}
 
-    let result = fs.copy_file(&from, &to);
+fn writable_fails_if_parent_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.writable(parent.join("missing/test.txt"));
 
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn rename_renames_a_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
+fn is_readable_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.is_readable(parent.join("does_not_exist"));
 
-    create_file(fs, &from, "contents").unwrap();
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 
-    let result = fs.rename(&from, &to);
+    let result = fs.is_writable(parent.join("does_not_exist"));
 
-    assert!(result.is_ok());
-    assert!(!fs.is_file(&from));
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn create_dir_creates_new_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_dir");
 
-    let result = read_file_to_string(fs, &to);
+    let result = fs.create_dir(&path);
 
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), "contents");
+    assert!(fs.is_dir(path));
 }
 
-fn rename_renames_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
-    let child = from.join("child");
-
-    fs.create_dir(&from).unwrap();
-    create_file(fs, &child, "child").unwrap();
-
-    let result = fs.rename(&from, &to);
+fn create_dir_fails_if_dir_already_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_dir");
 
-    assert!(result.is_ok());
-    assert!(!fs.is_dir(&from));
+    fs.create_dir(&path).unwrap();
 
-    let result = read_file_to_string(fs, to.join("child"));
+    let result = fs.create_dir(&path);
 
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), "child");
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
 }
 
-fn rename_overwrites_destination_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
-
-    create_file(fs, &from, "from").unwrap();
-    create_file(fs, &to, "to").unwrap();
+fn create_dir_fails_if_parent_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("parent/new_dir");
 
-    let result = fs.rename(&from, &to);
+    let result = fs.create_dir(&path);
 
-    assert!(result.is_ok());
-    assert!(!fs.is_file(&from));
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
 
-    let result = read_file_to_string(fs, &to);
+fn create_dir_all_creates_dirs_in_path<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.create_dir_all(parent.join("a/b/c"));
 
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), "from");
+    assert!(fs.is_dir(parent.join("a")));
+    assert!(fs.is_dir(parent.join("a/b")));
+    assert!(fs.is_dir(parent.join("a/b/c")));
 }
 
-fn rename_overwrites_empty_destination_directory<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
-    let child = from.join("child");
+fn create_dir_all_still_succeeds_if_any_dir_already_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    fs.create_dir_all(parent.join("a/b")).unwrap();
 
-    fs.create_dir(&from).unwrap();
-    fs.create_dir(&to).unwrap();
-    create_file(fs, &child, "child").unwrap();
+    let result = fs.create_dir_all(parent.join("a/b/c"));
 
-    let result = fs.rename(&from, &to);
+    assert!(result.is_ok());
+    assert!(fs.is_dir(parent.join("a")));
+    assert!(fs.is_dir(parent.join("a/b")));
+    assert!(fs.is_dir(parent.join("a/b/c")));
+}
 
-    assert!(result.is_ok(), "err: {:?}", result);
-    assert!(!fs.is_dir(&from));
+fn create_dir_all_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("a/b");
+    fs.create_dir(parent.join("a")).unwrap();
+    create_file(fs, &path, "").unwrap();
 
-    let result = read_file_to_string(fs, to.join("child"));
+    let result = fs.create_dir_all(&path);
 
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), "child");
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert!(fs.is_file(&path));
 }
 
-fn rename_renames_all_descendants<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
-    let child_file = from.join("child_file");
-    let child_dir = from.join("child_dir");
-    let grandchild = child_dir.join("grandchild");
+fn create_dir_all_fails_if_ancestor_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let file = parent.join("a/file");
+    fs.create_dir(parent.join("a")).unwrap();
+    create_file(fs, &file, "").unwrap();
 
-    fs.create_dir(&from).unwrap();
-    create_file(fs, &child_file, "child_file").unwrap();
-    fs.create_dir(&child_dir).unwrap();
-    create_file(fs, &grandchild, "grandchild").unwrap();
+    let result = fs.create_dir_all(file.join("b/c"));
 
-    let result = fs.rename(&from, &to);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotADirectory);
+    assert!(fs.is_file(&file));
+    assert!(!fs.is_dir(file.join("b")));
+}
+
+#[cfg(unix)]
+fn create_dir_all_follows_symlink_to_dir_in_path<T: FileSystem>(fs: &T, parent: &Path) {
+    let link = parent.join("link");
+    fs.create_dir(parent.join("dir")).unwrap();
+    fs.symlink("dir", &link).unwrap();
+
+    let result = fs.create_dir_all(link.join("a/b"));
 
     assert!(result.is_ok());
-    assert!(!fs.is_dir(&from));
+    assert!(fs.is_dir(parent.join("dir/a/b")));
+    assert_eq!(fs.file_type(&link).unwrap(), NodeKind::Symlink);
+}
+
+#[cfg(unix)]
+fn create_dir_all_succeeds_if_node_is_symlink_to_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let link = parent.join("link");
+    fs.create_dir(parent.join("dir")).unwrap();
+    fs.symlink("dir", &link).unwrap();
+
+    let result = fs.create_dir_all(&link);
 
-    let result = read_file_to_string(fs, to.join("child_file"));
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), "child_file");
+    assert_eq!(fs.file_type(&link).unwrap(), NodeKind::Symlink);
+}
+
+#[cfg(unix)]
+fn create_dir_all_fails_if_node_is_dangling_symlink<T: FileSystem>(fs: &T, parent: &Path) {
+    let link = parent.join("link");
+    fs.symlink("does_not_exist", &link).unwrap();
+
+    let result = fs.create_dir_all(&link);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert!(!fs.exists(parent.join("does_not_exist")));
+}
+
+fn create_dir_all_normalizes_redundant_separators<T: FileSystem>(fs: &T, parent: &Path) {
+    let sep = std::path::MAIN_SEPARATOR;
+    let path = format!("{}{}{}a{}{}b{}{}{}c", parent.display(), sep, sep, sep, sep, sep, sep, sep);
+
+    let result = fs.create_dir_all(&path);
+
+    assert!(result.is_ok(), "err: {:?}", result);
+    assert!(fs.is_dir(parent.join("a")));
+    assert!(fs.is_dir(parent.join("a").join("b")));
+    assert!(fs.is_dir(parent.join("a").join("b").join("c")));
+
+    fs.write(parent.join("a").join("b").join("c").join("file"), "").unwrap();
+    assert!(fs.is_file(format!("{}{}{}file", path, sep, sep)));
+}
+
+fn create_dir_all_with_redundant_separators_finds_existing_dirs<T: FileSystem>(fs: &T, parent: &Path) {
+    let sep = std::path::MAIN_SEPARATOR;
+    fs.create_dir_all(parent.join("a").join("b")).unwrap();
+
+    let path = format!("{}{}{}a{}{}b{}{}c{}", parent.display(), sep, sep, sep, sep, sep, sep, sep);
+    let result = fs.create_dir_all(&path);
+
+    assert!(result.is_ok(), "err: {:?}", result);
+    assert!(fs.is_dir(parent.join("a").join("b").join("c")));
+    let entries: Vec<_> = fs.read_dir(parent.join("a")).unwrap().collect();
+    assert_eq!(entries.len(), 1);
+}
+
+fn create_dir_all_succeeds_if_racing_other_threads<T: FileSystem>(fs: &T, parent: &Path) {
+    let paths: Vec<PathBuf> = (0..8)
+        .map(|i| parent.join("a/b/c/d").join(format!("e{}", i % 2)).join(format!("f{}", i % 4)))
+        .collect();
+
+    let results: Vec<io::Result<()>> = thread::scope(|s| {
+        let handles: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                let fs = fs.clone();
+                s.spawn(move || fs.create_dir_all(path))
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    for result in results {
+        assert!(result.is_ok());
+    }
+    for path in &paths {
+        assert!(fs.is_dir(path));
+    }
+    assert_eq!(fs.read_dir(parent.join("a/b/c/d")).unwrap().count(), 2);
+}
+
+fn remove_dir_deletes_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("dir");
+
+    fs.create_dir(&path).unwrap();
+
+    let result = fs.remove_dir(&path);
 
-    let result = read_file_to_string(fs, to.join("child_dir").join("grandchild"));
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), "grandchild");
+    assert!(!fs.is_dir(&path));
 }
 
-fn rename_fails_if_original_path_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
+fn remove_dir_does_not_affect_parent<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("parent/child");
 
-    let result = fs.rename(&from, &to);
+    fs.create_dir_all(&path).unwrap();
+
+    let result = fs.remove_dir(&path);
+
+    assert!(result.is_ok());
+    assert!(fs.is_dir(parent.join("parent")));
+    assert!(!fs.is_dir(parent.join("child")));
+}
+
+fn remove_dir_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.remove_dir(parent.join("does_not_exist"));
 
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn rename_fails_if_original_and_destination_are_different_types<T: FileSystem>(
-    fs: &T,
-    parent: &Path,
-) {
-    let file = parent.join("file");
-    let dir = parent.join("dir");
+fn remove_dir_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
 
-    create_file(fs, &file, "").unwrap();
-    fs.create_dir(&dir).unwrap();
+    create_file(fs, &path, "").unwrap();
 
-    let result = fs.rename(&file, &dir);
+    let result = fs.remove_dir(&path);
 
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+    assert!(fs.is_file(&path));
+}
 
-    let result = fs.rename(&dir, &file);
+fn remove_dir_fails_if_dir_is_not_empty<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("dir");
+    let child = path.join("file");
+
+    fs.create_dir(&path).unwrap();
+    create_file(fs, &child, "").unwrap();
+
+    let result = fs.remove_dir(&path);
 
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::DirectoryNotEmpty);
+    assert!(fs.is_dir(&path));
+    assert!(fs.is_file(&child));
 }
 
-fn rename_fails_if_destination_directory_is_not_empty<T: FileSystem>(fs: &T, parent: &Path) {
-    let from = parent.join("from");
-    let to = parent.join("to");
-    let child = to.join("child");
+fn remove_dir_and_empty_parents_removes_empty_ancestors<T: FileSystem>(fs: &T, parent: &Path) {
+    // keeps the pruning from walking up beyond the test's own dir
+    fs.write(parent.join("keep.txt"), "").unwrap();
+    let path = parent.join("a").join("b").join("c");
+    fs.create_dir_all(&path).unwrap();
 
-    fs.create_dir(&from).unwrap();
-    fs.create_dir(&to).unwrap();
-    create_file(fs, &child, "child").unwrap();
+    let result = fs.remove_dir_and_empty_parents(&path);
 
-    let result = fs.rename(&from, &to);
+    assert!(result.is_ok());
+    assert!(!fs.is_dir(parent.join("a")));
+    assert!(fs.is_dir(parent));
+}
+
+fn remove_dir_and_empty_parents_stops_at_non_empty_ancestor<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("a").join("b").join("c");
+    fs.create_dir_all(&path).unwrap();
+    fs.create_dir(parent.join("a").join("sibling")).unwrap();
+
+    let result = fs.remove_dir_and_empty_parents(&path);
+
+    assert!(result.is_ok());
+    assert!(!fs.is_dir(parent.join("a").join("b")));
+    assert!(fs.is_dir(parent.join("a").join("sibling")));
+}
+
+fn remove_dir_and_empty_parents_fails_if_dir_is_not_empty<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("a").join("b");
+    fs.create_dir_all(&path).unwrap();
+    fs.write(path.join("test.txt"), "").unwrap();
+
+    let result = fs.remove_dir_and_empty_parents(&path);
 
     assert!(result.is_err());
+    assert!(fs.is_dir(&path));
 }
 
-fn readonly_returns_write_permission<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
+fn remove_dir_all_removes_dir_and_contents<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("dir");
+    let child = path.join("file");
 
-    create_file(fs, &path, "").unwrap();
+    fs.create_dir(&path).unwrap();
+    create_file(fs, &child, "").unwrap();
 
-    let result = readonly(fs, &path);
+    let result = fs.remove_dir_all(&path);
 
     assert!(result.is_ok());
-    assert!(!result.unwrap());
+    assert!(!fs.is_dir(&path));
+    assert!(!fs.is_file(&child));
+    assert!(fs.is_dir(parent));
+}
 
-    set_readonly(fs, &path, true).unwrap();
+fn remove_dir_all_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
 
-    let result = readonly(fs, &path);
+    create_file(fs, &path, "").unwrap();
 
-    assert!(result.is_ok());
-    assert!(result.unwrap());
+    let result = fs.remove_dir_all(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+    assert!(fs.is_file(&path));
 }
 
-fn readonly_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let result = readonly(fs, parent.join("does_not_exist"));
+fn remove_dir_all_report_returns_length_of_removed_files<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir_all(dir.join("sub/empty")).unwrap();
+    fs.write(dir.join("a.txt"), "12345").unwrap();
+    fs.write(dir.join("sub/b.txt"), "1234567890").unwrap();
+    fs.write(parent.join("outside.txt"), "not removed").unwrap();
+
+    let freed = fs.remove_dir_all_report(&dir).unwrap();
+
+    assert_eq!(freed, 15);
+    assert!(!fs.exists(&dir));
+    assert!(fs.is_file(parent.join("outside.txt")));
+}
+
+#[cfg(unix)]
+fn remove_dir_all_report_counts_hard_linked_file_once<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+    fs.write(dir.join("a.txt"), "12345").unwrap();
+    fs.hard_link(dir.join("a.txt"), dir.join("b.txt")).unwrap();
+
+    assert_eq!(fs.remove_dir_all_report(&dir).unwrap(), 5);
+}
+
+fn remove_dir_all_report_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.remove_dir_all_report(parent.join("does_not_exist"));
 
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn set_readonly_toggles_write_permission_of_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_file");
+#[cfg(unix)]
+fn remove_dir_all_removes_dir_and_contents_if_descendant_not_writable<
+    T: FileSystem,
+>(
+    fs: &T,
+    parent: &Path,
+) {
+    let mode = 0o555;
 
-    create_file(fs, &path, "").unwrap();
+    let path = parent.join("dir");
+    let child = path.join("child");
 
-    let result = set_readonly(fs, &path, true);
+    fs.create_dir(&path).unwrap();
+    fs.create_dir(&child).unwrap();
+
+    set_mode(fs, &child, mode).unwrap();
+
+    let result = fs.remove_dir_all(&path);
 
     assert!(result.is_ok());
-    assert!(write_file(fs, &path, "readonly").is_err());
+    assert!(!fs.is_dir(&path));
+    assert!(!fs.is_dir(&child));
+}
 
-    let result = set_readonly(fs, &path, false);
+#[cfg(unix)]
+fn remove_dir_all_removes_dir_and_contents_if_descendant_not_executable<
+    T: FileSystem,
+>(
+    fs: &T,
+    parent: &Path,
+) {
+    let mode = 0o666;
+
+    let path = parent.join("dir");
+    let child = path.join("child");
+
+    fs.create_dir(&path).unwrap();
+    fs.create_dir(&child).unwrap();
+
+    set_mode(fs, &child, mode).unwrap();
+
+    let result = fs.remove_dir_all(&path);
 
     assert!(result.is_ok());
-    assert!(write_file(fs, &path, "no longer readonly").is_ok());
+    assert!(!fs.is_dir(&path));
+    assert!(!fs.is_dir(&child));
 }
 
-fn set_readonly_toggles_write_permission_of_dir<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test_dir");
+#[cfg(unix)]
+fn remove_dir_all_fails_if_descendant_not_readable<T: FileSystem>(
+    fs: &T,
+    parent: &Path,
+) {
+    let mode = 0o333;
+
+    let path = parent.join("dir");
+    let child = path.join("child");
 
     fs.create_dir(&path).unwrap();
+    fs.create_dir(&child).unwrap();
 
-    let result = set_readonly(fs, &path, true);
+    set_mode(fs, &child, mode).unwrap();
+
+    let result = fs.remove_dir_all(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert!(fs.is_dir(&path));
+    assert!(fs.is_dir(&child));
+}
+
+fn read_dir_returns_dir_entries<T: FileSystem>(fs: &T, parent: &Path) {
+    let file1 = parent.join("file1");
+    let file2 = parent.join("file2");
+    let dir1 = parent.join("dir1");
+    let dir2 = parent.join("dir2");
+    let file3 = dir1.join("file3");
+    let file4 = dir2.join("file4");
+
+    create_file(fs, &file1, "").unwrap();
+    create_file(fs, &file2, "").unwrap();
+    fs.create_dir(&dir1).unwrap();
+    fs.create_dir(&dir2).unwrap();
+    create_file(fs, &file3, "").unwrap();
+    create_file(fs, &file4, "").unwrap();
+
+    let result = fs.read_dir(parent);
+
+    assert!(result.is_ok());
+
+    let mut entries: Vec<PathBuf> = result.unwrap().map(|e| e.unwrap().path()).collect();
+    let expected_paths = &mut [file1, file2, dir1, dir2];
+
+    entries.sort();
+    expected_paths.sort();
+
+    assert_eq!(&entries, expected_paths);
+}
+
+fn read_dir_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("does_not_exist");
+    let result = fs.read_dir(&path);
+
+    assert!(result.is_err());
+
+    match result {
+        Ok(_) => panic!("should be an err"),
+        Err(err) => assert_eq!(err.kind(), ErrorKind::NotFound),
+    }
+}
+
+fn read_dir_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
+
+    create_file(fs, &path, "").unwrap();
+
+    let result = fs.read_dir(&path);
+
+    assert!(result.is_err());
+    match result {
+        Ok(_) => panic!("should be an err"),
+        Err(err) => assert_eq!(err.kind(), ErrorKind::Other),
+    }
+}
+
+fn create_object_writes_to_new_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_file");
+    let mut writer = fs.create(&path).unwrap();
+    let result = writer.write_all(b"new contents");
+
+    assert!(result.is_ok());
+
+    let contents = fs.read(path).unwrap();
+
+    assert_eq!(&contents, b"new contents");
+}
+
+fn create_with_capacity_creates_empty_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create_with_capacity(&path, 1024 * 1024).unwrap();
+
+    assert_eq!(fs.metadata(&path).unwrap().len(), 0);
+    writer.write_all(b"test text").unwrap();
+    assert_eq!(fs.read(&path).unwrap(), b"test text");
+}
+
+fn create_with_capacity_truncates_existing_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    fs.create_with_capacity(&path, 1024).unwrap();
+
+    assert_eq!(fs.read(&path).unwrap(), b"");
+}
+
+fn create_object_fails_if_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    create_file(fs, &path, "").unwrap();
+    set_readonly(fs, &path, true).unwrap();
+
+    let result = fs.create(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+}
+
+fn walk_dir_returns_all_descendants_depth_first<T: FileSystem>(fs: &T, parent: &Path) {
+    let root = parent.join("root");
+    fs.create_dir_all(root.join("a/b")).unwrap();
+    fs.create_dir(root.join("empty")).unwrap();
+    create_file(fs, root.join("a/b/c.txt"), "").unwrap();
+    create_file(fs, root.join("a/d.txt"), "").unwrap();
+    create_file(fs, root.join("e.txt"), "").unwrap();
+
+    let paths: Vec<PathBuf> = fs.walk_dir(&root).unwrap().map(|e| e.unwrap().path()).collect();
+
+    let mut sorted = paths.clone();
+    sorted.sort();
+    let expected: Vec<PathBuf> = ["a", "a/b", "a/b/c.txt", "a/d.txt", "e.txt", "empty"]
+        .iter()
+        .map(|p| root.join(p))
+        .collect();
+    assert_eq!(sorted, expected);
+
+    for (i, path) in paths.iter().enumerate() {
+        let parent_pos = paths.iter().position(|p| p == path.parent().unwrap());
+        assert!(parent_pos.map(|pos| pos < i).unwrap_or(path.parent() == Some(&root)));
+    }
+}
+
+#[cfg(unix)]
+fn walk_dir_does_not_follow_symlinks<T: FileSystem>(fs: &T, parent: &Path) {
+    let root = parent.join("root");
+    fs.create_dir_all(root.join("dir")).unwrap();
+    create_file(fs, root.join("dir/test.txt"), "").unwrap();
+    fs.symlink(&root, root.join("dir/link")).unwrap();
+
+    let mut paths: Vec<PathBuf> = fs.walk_dir(&root).unwrap().map(|e| e.unwrap().path()).collect();
+    paths.sort();
+
+    assert_eq!(paths, [root.join("dir"), root.join("dir/link"), root.join("dir/test.txt")]);
+}
+
+fn walk_dir_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.walk_dir(parent.join("does_not_exist"));
+
+    match result {
+        Ok(_) => panic!("should be an err"),
+        Err(err) => assert_eq!(err.kind(), ErrorKind::NotFound),
+    }
+}
+
+fn find_duplicates_groups_files_with_equal_contents<T: FileSystem>(fs: &T, parent: &Path) {
+    let root = parent.join("root");
+    fs.create_dir_all(root.join("dir")).unwrap();
+    create_file(fs, root.join("a.txt"), "same text").unwrap();
+    create_file(fs, root.join("dir/b.txt"), "same text").unwrap();
+    create_file(fs, root.join("c.txt"), "diff text").unwrap();
+
+    let groups = fs.find_duplicates(&root).unwrap();
+
+    assert_eq!(groups, vec![vec![root.join("a.txt"), root.join("dir/b.txt")]]);
+}
+
+fn exists_returns_true_for_files_and_dirs<T: FileSystem>(fs: &T, parent: &Path) {
+    let file = parent.join("file");
+    create_file(fs, &file, "").unwrap();
+
+    assert!(fs.exists(parent));
+    assert!(fs.exists(&file));
+    assert!(fs.try_exists(parent).unwrap());
+    assert!(fs.try_exists(&file).unwrap());
+}
+
+fn exists_returns_false_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    assert!(!fs.exists(parent.join("does_not_exist")));
+}
+
+fn try_exists_returns_false_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.try_exists(parent.join("does_not_exist"));
+
+    assert!(!result.unwrap());
+}
+
+#[cfg(unix)]
+fn try_exists_fails_if_parent_is_not_readable<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+    create_file(fs, dir.join("file"), "").unwrap();
+    set_mode(fs, &dir, 0o000).unwrap();
+
+    let result = fs.try_exists(dir.join("file"));
+    set_mode(fs, &dir, 0o755).unwrap();
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+}
+
+fn dir_entry_metadata_describes_entry<T: FileSystem>(fs: &T, parent: &Path) {
+    create_file(fs, parent.join("file"), "test text").unwrap();
+    fs.create_dir(parent.join("dir")).unwrap();
+
+    for entry in fs.read_dir(parent).unwrap() {
+        let entry = entry.unwrap();
+        let md = entry.metadata().unwrap();
+        if entry.file_name() == "file" {
+            assert!(md.is_file());
+            assert_eq!(md.len(), 9);
+        } else {
+            assert!(md.is_dir());
+        }
+    }
+}
+
+#[cfg(unix)]
+fn dir_entry_metadata_does_not_follow_symlink<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+    fs.symlink(parent, dir.join("link")).unwrap();
+
+    let entry = fs.read_dir(&dir).unwrap().next().unwrap().unwrap();
+
+    assert!(entry.metadata().unwrap().is_symlink());
+}
+
+fn dir_entry_metadata_fails_if_entry_was_removed<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
+    create_file(fs, &path, "").unwrap();
+
+    let entry = fs.read_dir(parent).unwrap().next().unwrap().unwrap();
+    fs.remove_file(&path).unwrap();
+    let result = entry.metadata();
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn dir_entry_file_type_reports_files_and_dirs<T: FileSystem>(fs: &T, parent: &Path) {
+    create_file(fs, parent.join("file"), "").unwrap();
+    fs.create_dir(parent.join("dir")).unwrap();
+
+    for entry in fs.read_dir(parent).unwrap() {
+        let entry = entry.unwrap();
+        let file_type = entry.file_type().unwrap();
+        assert!(!file_type.is_symlink());
+        if entry.file_name() == "file" {
+            assert!(file_type.is_file());
+            assert!(!file_type.is_dir());
+        } else {
+            assert!(file_type.is_dir());
+            assert!(!file_type.is_file());
+        }
+    }
+}
+
+#[cfg(unix)]
+fn dir_entry_file_type_reports_symlinks<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+    fs.symlink(parent, dir.join("link")).unwrap();
+
+    let entry = fs.read_dir(&dir).unwrap().next().unwrap().unwrap();
+    let file_type = entry.file_type().unwrap();
+
+    assert!(file_type.is_symlink());
+    assert!(!file_type.is_dir());
+}
+
+fn read_dir_names_returns_names_and_kinds<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    create_file(fs, parent.join("file"), "").unwrap();
+    fs.create_dir(&dir).unwrap();
+    create_file(fs, dir.join("nested"), "").unwrap();
+
+    let mut entries = fs.read_dir_names(parent).unwrap();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(
+        entries,
+        vec![("dir".into(), NodeKind::Dir), ("file".into(), NodeKind::File)]
+    );
+}
+
+#[cfg(unix)]
+fn read_dir_names_reports_symlinks_as_symlinks<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+    fs.symlink(&dir, parent.join("link")).unwrap();
+
+    let mut entries = fs.read_dir_names(parent).unwrap();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(
+        entries,
+        vec![("dir".into(), NodeKind::Dir), ("link".into(), NodeKind::Symlink)]
+    );
+}
+
+fn read_dir_names_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.read_dir_names(parent.join("does_not_exist"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn dir_is_empty_is_true_for_empty_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+
+    assert!(fs.dir_is_empty(&dir).unwrap());
+}
+
+fn dir_is_empty_is_false_for_dir_with_child<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+    create_file(fs, dir.join("file"), "").unwrap();
+
+    assert!(!fs.dir_is_empty(&dir).unwrap());
+}
+
+fn dir_is_empty_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
+    create_file(fs, &path, "").unwrap();
+
+    let result = fs.dir_is_empty(&path);
+
+    assert!(result.is_err());
+    let kind = result.unwrap_err().kind();
+    assert!(kind == ErrorKind::Other || kind == ErrorKind::NotADirectory, "{:?}", kind);
+}
+
+fn dir_is_empty_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.dir_is_empty(parent.join("does_not_exist"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn seed_glob_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    for name in ["main.rs", "lib.rs", "test_1.txt", "test_22.txt", "apple", "banana", "cherry", "date"] {
+        create_file(fs, parent.join(name), "").unwrap();
+    }
+    fs.create_dir(parent.join("sub")).unwrap();
+    create_file(fs, parent.join("sub/nested.rs"), "").unwrap();
+}
+
+fn read_dir_glob_sorted<T: FileSystem>(fs: &T, parent: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut paths = fs.read_dir_glob(parent, pattern).unwrap();
+    paths.sort();
+    paths
+}
+
+fn read_dir_glob_matches_star<T: FileSystem>(fs: &T, parent: &Path) {
+    seed_glob_dir(fs, parent);
+
+    let paths = read_dir_glob_sorted(fs, parent, "*.rs");
+
+    assert_eq!(paths, vec![parent.join("lib.rs"), parent.join("main.rs")]);
+}
+
+fn read_dir_glob_matches_question_mark<T: FileSystem>(fs: &T, parent: &Path) {
+    seed_glob_dir(fs, parent);
+
+    let paths = read_dir_glob_sorted(fs, parent, "test_?.txt");
+
+    assert_eq!(paths, vec![parent.join("test_1.txt")]);
+}
+
+fn read_dir_glob_matches_character_class<T: FileSystem>(fs: &T, parent: &Path) {
+    seed_glob_dir(fs, parent);
+
+    assert_eq!(
+        read_dir_glob_sorted(fs, parent, "[abc]*"),
+        vec![parent.join("apple"), parent.join("banana"), parent.join("cherry")]
+    );
+    assert_eq!(
+        read_dir_glob_sorted(fs, parent, "[!a-c]*e"),
+        vec![parent.join("date")]
+    );
+}
+
+fn read_dir_glob_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.read_dir_glob(parent.join("does_not_exist"), "*");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn write_file_writes_to_new_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_file");
+    let result = fs.write(&path, "new contents");
+
+    assert!(result.is_ok());
+
+    let contents = String::from_utf8(fs.read(path).unwrap()).unwrap();
+
+    assert_eq!(&contents, "new contents");
+}
+
+fn write_file_overwrites_contents_of_existing_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    fs.write(&path, "old contents").unwrap();
+
+    let result = fs.write(&path, "new contents");
+
+    assert!(result.is_ok());
+
+    let contents = String::from_utf8(fs.read(path).unwrap()).unwrap();
+
+    assert_eq!(&contents, "new contents");
+}
+
+fn write_file_fails_if_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    create_file(fs, &path, "").unwrap();
+    set_readonly(fs, &path, true).unwrap();
+
+    let result = fs.write(&path, "test contents");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+}
+
+fn write_file_fails_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_dir");
+
+    fs.create_dir(&path).unwrap();
+
+    let result = fs.write(&path, "test contents");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::IsADirectory);
+}
+
+fn append_creates_file_then_appends_to_it<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    fs.append(&path, "first line\n").unwrap();
+    fs.append(&path, "second line\n").unwrap();
+
+    assert_eq!(fs.read_to_string(&path).unwrap(), "first line\nsecond line\n");
+}
+
+fn append_fails_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.append(parent, "test text");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::IsADirectory);
+}
+
+fn create_durable_writes_contents<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    fs.create_durable(&path, "test text").unwrap();
+
+    assert_eq!(fs.read_to_string(&path).unwrap(), "test text");
+}
+
+fn sync_dir_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.sync_dir(parent.join("does_not_exist"));
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn write_file_fails_if_path_ends_in_separator<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.write(parent.join("test/"), "test text");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::IsADirectory);
+}
+
+fn overwrite_file_overwrites_contents_of_existing_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    fs.write(&path, "old contents").unwrap();
+
+    let result = overwrite_file(fs, &path, "new contents");
+
+    assert!(result.is_ok());
+
+    let contents = String::from_utf8(fs.read(path).unwrap()).unwrap();
+
+    assert_eq!(&contents, "new contents");
+}
+
+fn overwrite_file_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("new_file");
+    let result = overwrite_file(fs, &path, "new contents");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn overwrite_file_fails_if_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    create_file(fs, &path, "").unwrap();
+    set_readonly(fs, &path, true).unwrap();
+
+    let result = overwrite_file(fs, &path, "test contents");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+}
+
+fn overwrite_file_fails_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_dir");
+
+    fs.create_dir(&path).unwrap();
+
+    let result = overwrite_file(fs, &path, "test contents");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::IsADirectory);
+}
+
+fn read_file_returns_contents_as_bytes<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    fs.write(&path, "test text").unwrap();
+
+    let result = fs.read(&path);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), br"test text");
+}
+
+fn read_file_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let result = fs.read(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn read_file_to_string_returns_contents_as_string<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    fs.write(&path, "test text").unwrap();
+
+    let result = fs.read_to_string(&path);
+
+    assert!(result.is_ok());
+    assert_eq!(&result.unwrap(), "test text");
+}
+
+fn read_capped_reads_file_within_cap<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    assert_eq!(fs.read_capped(&path, 100).unwrap(), b"test text");
+    assert_eq!(fs.read_capped(&path, 9).unwrap(), b"test text");
+}
+
+fn read_capped_fails_if_file_exceeds_cap<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    let result = fs.read_capped(&path, 8);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::FileTooLarge);
+}
+
+fn read_file_to_string_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let result = fs.read_to_string(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn read_file_to_string_fails_if_contents_are_not_utf8<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    fs.write(&path, [0, 159, 146, 150]).unwrap();
+
+    let result = fs.read_to_string(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+fn read_file_into_writes_bytes_to_buffer<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let text = "test text";
+
+    fs.write(&path, text).unwrap();
+    let mut buf = Vec::new();
+
+    let result = read_file_into(fs, &path, &mut buf);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), text.len());
+    assert_eq!(buf, br"test text");
+}
+
+fn read_file_into_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    let result = read_file_into(fs, &path, &mut Vec::new());
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn open_object_writes_bytes_to_buffer<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let text = "test text";
+
+    fs.write(&path, text).unwrap();
+    let mut buf = Vec::new();
+
+    let mut reader = fs.open(&path).unwrap();
+    let result = reader.read_to_end(&mut buf);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), text.len());
+    assert_eq!(buf, br"test text");
+}
+
+fn open_object_reads_empty_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    create_file(fs, &path, "").unwrap();
+
+    let mut reader = fs.open(&path).unwrap();
+
+    let mut buf = [0; 8];
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+    let mut contents = vec![];
+    assert_eq!(reader.read_to_end(&mut contents).unwrap(), 0);
+    assert!(contents.is_empty());
+    assert_eq!(fs.read(&path).unwrap(), b"");
+}
+
+fn open_object_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    let result = fs.open(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn create_file_writes_to_new_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+    let result = create_file(fs, &path, "new contents");
+
+    assert!(result.is_ok());
+
+    let contents = String::from_utf8(fs.read(path).unwrap()).unwrap();
+
+    assert_eq!(&contents, "new contents");
+}
+
+fn create_file_fails_if_file_already_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    create_file(fs, &path, "contents").unwrap();
+
+    let result = create_file(fs, &path, "new contents");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+}
+
+fn remove_file_removes_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    create_file(fs, &path, "").unwrap();
+
+    let result = fs.remove_file(&path);
+
+    assert!(result.is_ok());
+
+    let result = fs.read(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn remove_file_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.remove_file(parent.join("does_not_exist"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn remove_file_fails_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_dir");
+
+    fs.create_dir(&path).unwrap();
+
+    let result = fs.remove_file(&path);
+
+    assert!(result.is_err());
+
+    let expected_error = if cfg!(target_os = "macos") {
+        ErrorKind::PermissionDenied
+    } else if cfg!(target_os = "linux") {
+        ErrorKind::IsADirectory
+    } else {
+        ErrorKind::Other
+    };
+
+    assert_eq!(result.unwrap_err().kind(), expected_error);
+}
+
+#[cfg(unix)]
+fn remove_file_removes_symlink_but_not_target<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+    let link = parent.join("link");
+
+    create_file(fs, &path, "test").unwrap();
+    fs.symlink(&path, &link).unwrap();
+
+    let result = fs.remove_file(&link);
+
+    assert!(result.is_ok());
+
+    let result = fs.symlink_metadata(&link);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(fs.read_to_string(&path).unwrap(), "test");
+}
+
+#[cfg(target_os = "linux")]
+fn remove_file_of_directory_fails_with_eisdir<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_dir");
+    fs.create_dir(&path).unwrap();
+
+    let result = fs.remove_file(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::EISDIR));
+}
+
+fn copy_file_copies_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    create_file(fs, &from, "test").unwrap();
+
+    let result = fs.copy_file(&from, &to);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 4);
+
+    let result = fs.read(&to);
+
+    assert!(result.is_ok());
+    assert_eq!(&result.unwrap(), b"test");
+}
+
+fn copy_file_verified_copies_and_verifies_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    let contents: Vec<u8> = (0..20000).map(|i| (i % 251) as u8).collect();
+    fs.write(&from, &contents).unwrap();
+
+    let result = fs.copy_file_verified(&from, &to);
+
+    assert_eq!(result.unwrap(), 20000);
+    assert_eq!(fs.read(&to).unwrap(), contents);
+}
+
+fn copy_file_verified_fails_if_original_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.copy_file_verified(parent.join("from"), parent.join("to"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(!fs.exists(parent.join("to")));
+}
+
+fn copy_file_overwrites_destination_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    create_file(fs, &from, "expected").unwrap();
+    create_file(fs, &to, "should be overwritten").unwrap();
+
+    let result = fs.copy_file(&from, &to);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 8);
+
+    let result = fs.read(&to);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), b"expected");
+}
+
+fn copy_file_copy_is_independent_of_original<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    create_file(fs, &from, "original").unwrap();
+    fs.copy_file(&from, &to).unwrap();
+
+    fs.open_with_options(&to, &OpenOptions::new().write(true))
+        .unwrap()
+        .write_all(b"changed")
+        .unwrap();
+
+    assert_eq!(fs.read_to_string(&from).unwrap(), "original");
+    assert_eq!(fs.read_to_string(&to).unwrap(), "changedl");
+
+    fs.write(&from, "rewritten").unwrap();
+
+    assert_eq!(fs.read_to_string(&to).unwrap(), "changedl");
+}
+
+fn copy_file_overwritten_copy_is_independent_of_original<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    create_file(fs, &from, "original").unwrap();
+    create_file(fs, &to, "should be overwritten").unwrap();
+    fs.copy_file(&from, &to).unwrap();
+
+    fs.write(&to, "changed").unwrap();
+
+    assert_eq!(fs.read_to_string(&from).unwrap(), "original");
+    assert_eq!(fs.read_to_string(&to).unwrap(), "changed");
+}
+
+fn copy_dir_all_copies_tree<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    fs.create_dir_all(from.join("a/b")).unwrap();
+    fs.create_dir(from.join("empty")).unwrap();
+    create_file(fs, from.join("top.txt"), "top").unwrap();
+    create_file(fs, from.join("a/b/deep.txt"), "deep text").unwrap();
+
+    let result = fs.copy_dir_all(&from, &to);
+
+    assert!(result.is_ok(), "err: {:?}", result);
+    assert_eq!(result.unwrap(), 12);
+    assert!(fs.is_dir(to.join("empty")));
+    assert_eq!(fs.read(to.join("top.txt")).unwrap(), b"top");
+    assert_eq!(fs.read(to.join("a/b/deep.txt")).unwrap(), b"deep text");
+    assert_eq!(fs.read(from.join("a/b/deep.txt")).unwrap(), b"deep text");
+}
+
+fn copy_dir_all_fails_if_original_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    create_file(fs, &from, "").unwrap();
+
+    let result = fs.copy_dir_all(&from, &to);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(!fs.is_dir(&to));
+}
+
+fn copy_dir_all_fails_if_original_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.copy_dir_all(parent.join("from"), parent.join("to"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn copy_dir_all_fails_if_destination_is_inside_original<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    fs.create_dir(&from).unwrap();
+    create_file(fs, from.join("file"), "test text").unwrap();
+
+    let result = fs.copy_dir_all(&from, from.join("a/b"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(!fs.exists(from.join("a")));
+}
+
+fn copy_dir_all_fails_if_destination_is_original<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    fs.create_dir(&from).unwrap();
+    create_file(fs, from.join("file"), "test text").unwrap();
+
+    let result = fs.copy_dir_all(&from, from.join("."));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(fs.read_to_string(from.join("file")).unwrap(), "test text");
+}
+
+#[cfg(unix)]
+fn copy_dir_all_copies_symlinks_as_symlinks<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    fs.create_dir(&from).unwrap();
+    fs.symlink(parent, from.join("link")).unwrap();
+
+    fs.copy_dir_all(&from, &to).unwrap();
+
+    assert!(fs.symlink_metadata(to.join("link")).unwrap().is_symlink());
+    assert_eq!(fs.read_link(to.join("link")).unwrap(), parent);
+}
+
+#[cfg(unix)]
+fn copy_dir_all_fails_if_descendant_not_readable<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    fs.create_dir_all(from.join("a")).unwrap();
+    create_file(fs, from.join("a/secret.txt"), "secret").unwrap();
+    set_mode(fs, from.join("a/secret.txt"), 0o200).unwrap();
+
+    let result = fs.copy_dir_all(&from, parent.join("to"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+}
+
+#[cfg(unix)]
+fn copy_file_preserves_mode<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    create_file(fs, &from, "secret").unwrap();
+    set_mode(fs, &from, 0o600).unwrap();
+
+    fs.copy_file(&from, &to).unwrap();
+
+    assert_eq!(mode(fs, &to).unwrap(), mode(fs, &from).unwrap());
+    assert_eq!(mode(fs, &to).unwrap() & 0o777, 0o600);
+
+    // the copy has permissions of its own
+    set_mode(fs, &from, 0o640).unwrap();
+    assert_eq!(mode(fs, &to).unwrap() & 0o777, 0o600);
+}
+
+#[cfg(unix)]
+fn copy_file_replaces_mode_of_destination_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    create_file(fs, &from, "expected").unwrap();
+    create_file(fs, &to, "should be overwritten").unwrap();
+    set_mode(fs, &from, 0o640).unwrap();
+    set_mode(fs, &to, 0o666).unwrap();
+
+    fs.copy_file(&from, &to).unwrap();
+
+    assert_eq!(mode(fs, &to).unwrap() & 0o777, 0o640);
+}
+
+fn copy_file_fails_if_original_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    let result = fs.copy_file(&from, &to);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(!fs.is_file(&to));
+}
+
+fn copy_file_fails_if_destination_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    create_file(fs, &from, "test").unwrap();
+    create_file(fs, &to, "").unwrap();
+    set_readonly(fs, &to, true).unwrap();
+
+    let result = fs.copy_file(&from, &to);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+}
+
+fn copy_file_fails_if_original_node_is_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    fs.create_dir(&from).unwrap();
+
+    let result = fs.copy_file(&from, &to);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+fn copy_file_fails_if_destination_node_is_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    create_file(fs, &from, "").unwrap();
+    fs.create_dir(&to).unwrap();
+
+    let result = fs.copy_file(&from, &to);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+}
+
+fn rename_renames_a_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    create_file(fs, &from, "contents").unwrap();
+
+    let result = fs.rename(&from, &to);
+
+    assert!(result.is_ok());
+    assert!(!fs.is_file(&from));
+
+    let result = fs.read_to_string(&to);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), "contents");
+}
+
+fn rename_renames_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    let child = from.join("child");
+
+    fs.create_dir(&from).unwrap();
+    create_file(fs, &child, "child").unwrap();
+
+    let result = fs.rename(&from, &to);
+
+    assert!(result.is_ok());
+    assert!(!fs.is_dir(&from));
+
+    let result = fs.read_to_string(to.join("child"));
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), "child");
+}
+
+fn rename_overwrites_destination_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    create_file(fs, &from, "from").unwrap();
+    create_file(fs, &to, "to").unwrap();
+
+    let result = fs.rename(&from, &to);
+
+    assert!(result.is_ok());
+    assert!(!fs.is_file(&from));
+
+    let result = fs.read_to_string(&to);
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), "from");
+}
+
+fn rename_overwrites_empty_destination_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    let child = from.join("child");
+
+    fs.create_dir(&from).unwrap();
+    fs.create_dir(&to).unwrap();
+    create_file(fs, &child, "child").unwrap();
+
+    let result = fs.rename(&from, &to);
+
+    assert!(result.is_ok(), "err: {:?}", result);
+    assert!(!fs.is_dir(&from));
+
+    let result = fs.read_to_string(to.join("child"));
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), "child");
+}
+
+fn rename_renames_all_descendants<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    let child_file = from.join("child_file");
+    let child_dir = from.join("child_dir");
+    let grandchild = child_dir.join("grandchild");
+
+    fs.create_dir(&from).unwrap();
+    create_file(fs, &child_file, "child_file").unwrap();
+    fs.create_dir(&child_dir).unwrap();
+    create_file(fs, &grandchild, "grandchild").unwrap();
+
+    let result = fs.rename(&from, &to);
+
+    assert!(result.is_ok());
+    assert!(!fs.is_dir(&from));
+
+    let result = fs.read_to_string(to.join("child_file"));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), "child_file");
+
+    let result = fs.read_to_string(to.join("child_dir").join("grandchild"));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), "grandchild");
+}
+
+fn rename_fails_if_original_path_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    let result = fs.rename(&from, &to);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn rename_to_itself_does_nothing<T: FileSystem>(fs: &T, parent: &Path) {
+    let file = parent.join("file");
+    let dir = parent.join("dir");
+    fs.write(&file, "test text").unwrap();
+    fs.create_dir(&dir).unwrap();
+    fs.write(dir.join("child"), "").unwrap();
+
+    assert!(fs.rename(&file, &file).is_ok());
+    assert!(fs.rename(&dir, &dir).is_ok());
+
+    assert_eq!(fs.read_to_string(&file).unwrap(), "test text");
+    assert!(fs.is_file(dir.join("child")));
+}
+
+fn rename_fails_if_destination_is_inside_original<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = from.join("sub").join("to");
+    fs.create_dir_all(from.join("sub")).unwrap();
+
+    let result = fs.rename(&from, &to);
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(fs.is_dir(from.join("sub")));
+}
+
+fn rename_fails_if_original_and_destination_are_different_types<T: FileSystem>(
+    fs: &T,
+    parent: &Path,
+) {
+    let file = parent.join("file");
+    let dir = parent.join("dir");
+
+    create_file(fs, &file, "").unwrap();
+    fs.create_dir(&dir).unwrap();
+
+    let result = fs.rename(&file, &dir);
+
+    assert!(result.is_err());
+    let expected_error = if cfg!(unix) { ErrorKind::IsADirectory } else { ErrorKind::Other };
+    assert_eq!(result.unwrap_err().kind(), expected_error);
+
+    let result = fs.rename(&dir, &file);
+
+    assert!(result.is_err());
+    let expected_error = if cfg!(unix) { ErrorKind::NotADirectory } else { ErrorKind::Other };
+    assert_eq!(result.unwrap_err().kind(), expected_error);
+    assert!(fs.is_file(&file));
+    assert!(fs.is_dir(&dir));
+}
+
+fn rename_fails_if_destination_directory_is_not_empty<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    let child = to.join("child");
+
+    fs.create_dir(&from).unwrap();
+    fs.create_dir(&to).unwrap();
+    create_file(fs, &child, "child").unwrap();
+
+    let result = fs.rename(&from, &to);
+
+    assert!(result.is_err());
+    let expected_error = if cfg!(unix) { ErrorKind::DirectoryNotEmpty } else { ErrorKind::Other };
+    assert_eq!(result.unwrap_err().kind(), expected_error);
+    assert!(fs.is_dir(&from));
+    assert!(fs.is_file(&child));
+}
+
+fn rename_noreplace_renames_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    create_file(fs, &from, "contents").unwrap();
+
+    fs.rename_noreplace(&from, &to).unwrap();
+
+    assert!(!fs.exists(&from));
+    assert_eq!(fs.read(&to).unwrap(), b"contents");
+}
+
+fn rename_noreplace_fails_if_destination_file_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    create_file(fs, &from, "from").unwrap();
+    create_file(fs, &to, "to").unwrap();
+
+    let result = fs.rename_noreplace(&from, &to);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs.read(&from).unwrap(), b"from");
+    assert_eq!(fs.read(&to).unwrap(), b"to");
+}
+
+fn rename_noreplace_fails_if_destination_dir_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+    fs.create_dir(&from).unwrap();
+    fs.create_dir(&to).unwrap();
+
+    let result = fs.rename_noreplace(&from, &to);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert!(fs.is_dir(&from));
+    assert!(fs.is_dir(&to));
+}
+
+fn rename_noreplace_fails_if_original_path_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.rename_noreplace(parent.join("from"), parent.join("to"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn merge_dir_merges_trees_without_collisions<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    fs.create_dir_all(from.join("dir")).unwrap();
+    fs.create_dir_all(to.join("dir")).unwrap();
+    fs.write(from.join("a.txt"), "a").unwrap();
+    fs.write(from.join("dir/b.txt"), "b").unwrap();
+    fs.write(to.join("dir/c.txt"), "c").unwrap();
+
+    let result = fs.merge_dir(&from, &to, false);
+
+    assert!(result.is_ok());
+    assert!(!fs.exists(&from));
+    assert_eq!(fs.read_to_string(to.join("a.txt")).unwrap(), "a");
+    assert_eq!(fs.read_to_string(to.join("dir/b.txt")).unwrap(), "b");
+    assert_eq!(fs.read_to_string(to.join("dir/c.txt")).unwrap(), "c");
+}
+
+fn merge_dir_renames_if_destination_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    fs.create_dir(&from).unwrap();
+    fs.write(from.join("a.txt"), "a").unwrap();
+
+    let result = fs.merge_dir(&from, &to, false);
+
+    assert!(result.is_ok());
+    assert!(!fs.exists(&from));
+    assert_eq!(fs.read_to_string(to.join("a.txt")).unwrap(), "a");
+}
+
+fn merge_dir_fails_on_file_collision_without_overwrite<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    fs.create_dir_all(from.join("dir")).unwrap();
+    fs.create_dir_all(to.join("dir")).unwrap();
+    fs.write(from.join("dir/a.txt"), "new").unwrap();
+    fs.write(to.join("dir/a.txt"), "old").unwrap();
+
+    let result = fs.merge_dir(&from, &to, false);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs.read_to_string(from.join("dir/a.txt")).unwrap(), "new");
+    assert_eq!(fs.read_to_string(to.join("dir/a.txt")).unwrap(), "old");
+}
+
+fn merge_dir_overwrites_file_collision<T: FileSystem>(fs: &T, parent: &Path) {
+    let from = parent.join("from");
+    let to = parent.join("to");
+
+    fs.create_dir_all(from.join("dir")).unwrap();
+    fs.create_dir_all(to.join("dir")).unwrap();
+    fs.write(from.join("dir/a.txt"), "new").unwrap();
+    fs.write(to.join("dir/a.txt"), "old").unwrap();
+
+    let result = fs.merge_dir(&from, &to, true);
+
+    assert!(result.is_ok());
+    assert!(!fs.exists(&from));
+    assert_eq!(fs.read_to_string(to.join("dir/a.txt")).unwrap(), "new");
+}
+
+fn hard_link_shares_contents<T: FileSystem>(fs: &T, parent: &Path) {
+    let src = parent.join("src.txt");
+    let dst = parent.join("dst.txt");
+    fs.write(&src, "test text").unwrap();
+
+    let result = fs.hard_link(&src, &dst);
+    assert!(result.is_ok());
+    assert_eq!(fs.read(&dst).unwrap(), b"test text");
+
+    overwrite_file(fs, &dst, "new text").unwrap();
+    assert_eq!(fs.read(&src).unwrap(), b"new text");
+}
+
+fn hard_link_survives_removal_of_original<T: FileSystem>(fs: &T, parent: &Path) {
+    let src = parent.join("src.txt");
+    let dst = parent.join("dst.txt");
+    fs.write(&src, "test text").unwrap();
+    fs.hard_link(&src, &dst).unwrap();
+
+    fs.remove_file(&src).unwrap();
+
+    assert!(!fs.is_file(&src));
+    assert_eq!(fs.read(&dst).unwrap(), b"test text");
+}
+
+#[cfg(unix)]
+fn hard_link_shares_permissions<T: FileSystem>(fs: &T, parent: &Path) {
+    let src = parent.join("src.txt");
+    let dst = parent.join("dst.txt");
+    fs.write(&src, "test text").unwrap();
+    fs.hard_link(&src, &dst).unwrap();
+
+    set_mode(fs, &dst, 0o600).unwrap();
+
+    assert_eq!(mode(fs, &src).unwrap() % 0o100_000, 0o600);
+}
+
+#[cfg(unix)]
+fn hard_link_shares_ino<T: FileSystem>(fs: &T, parent: &Path) {
+    let src = parent.join("src.txt");
+    let dst = parent.join("dst.txt");
+    fs.write(&src, "test text").unwrap();
+    fs.hard_link(&src, &dst).unwrap();
+
+    assert_eq!(fs.metadata(&src).unwrap().ino(), fs.metadata(&dst).unwrap().ino());
+}
+
+#[cfg(unix)]
+fn distinct_nodes_have_distinct_inos<T: FileSystem>(fs: &T, parent: &Path) {
+    let first = parent.join("first.txt");
+    let second = parent.join("second.txt");
+    let dir = parent.join("dir");
+    fs.write(&first, "test text").unwrap();
+    fs.copy_file(&first, &second).unwrap();
+    fs.create_dir(&dir).unwrap();
+
+    let first = fs.metadata(&first).unwrap().ino();
+    let second = fs.metadata(&second).unwrap().ino();
+    let dir = fs.metadata(&dir).unwrap().ino();
+    assert_ne!(first, second);
+    assert_ne!(first, dir);
+    assert_ne!(second, dir);
+}
+
+fn hard_link_fails_if_destination_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    let src = parent.join("src.txt");
+    let dst = parent.join("dst.txt");
+    fs.write(&src, "test text").unwrap();
+    fs.write(&dst, "other text").unwrap();
+
+    let result = fs.hard_link(&src, &dst);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs.read(&dst).unwrap(), b"other text");
+}
+
+fn hard_link_fails_if_source_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.hard_link(parent.join("does_not_exist"), parent.join("dst.txt"));
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn hard_link_fails_if_source_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let src = parent.join("dir");
+    fs.create_dir(&src).unwrap();
+
+    let result = fs.hard_link(&src, parent.join("dst"));
+    assert!(result.is_err());
+    assert!(!fs.is_dir(parent.join("dst")));
+}
+
+fn readonly_returns_write_permission<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    create_file(fs, &path, "").unwrap();
+
+    let result = readonly(fs, &path);
+
+    assert!(result.is_ok());
+    assert!(!result.unwrap());
+
+    set_readonly(fs, &path, true).unwrap();
+
+    let result = readonly(fs, &path);
+
+    assert!(result.is_ok());
+    assert!(result.unwrap());
+}
+
+fn readonly_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = readonly(fs, parent.join("does_not_exist"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn set_readonly_toggles_write_permission_of_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_file");
+
+    create_file(fs, &path, "").unwrap();
+
+    let result = set_readonly(fs, &path, true);
+
+    assert!(result.is_ok());
+    assert!(fs.write(&path, "readonly").is_err());
+
+    let result = set_readonly(fs, &path, false);
+
+    assert!(result.is_ok());
+    assert!(fs.write(&path, "no longer readonly").is_ok());
+}
+
+fn set_readonly_toggles_write_permission_of_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_dir");
+
+    fs.create_dir(&path).unwrap();
+
+    let result = set_readonly(fs, &path, true);
+
+    assert!(result.is_ok());
+    assert!(fs.write(path.join("file"), "").is_err());
+
+    let result = set_readonly(fs, &path, false);
+
+    assert!(result.is_ok());
+    assert!(fs.write(path.join("file"), "").is_ok());
+}
+
+fn set_readonly_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = set_readonly(fs, parent.join("does_not_exist"), true);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+
+    let result = set_readonly(fs, parent.join("does_not_exist"), true);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn len_returns_size_of_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
+    let result = create_file(fs, &path, "");
+
+    assert!(result.is_ok());
+
+    let len = fs.open(&path).unwrap().metadata().unwrap().len();
+
+    assert_eq!(len, 0);
+
+    let result = fs.write(&path, "contents");
+
+    assert!(result.is_ok());
+
+    let len = fs.open(&path).unwrap().metadata().unwrap().len();
+
+    assert_eq!(len, 8);
+}
+
+fn open_objects_read_independently<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+
+    let mut readers = (fs.open(&path).unwrap(), fs.open(path).unwrap());
+    let mut bufs = (vec![], vec![]);
+    readers.0.read_to_end(&mut bufs.0).unwrap();
+    readers.1.read_to_end(&mut bufs.1).unwrap();
+    assert_eq!(bufs.0, b"test text");
+    assert_eq!(bufs.1, b"test text");
+}
+
+fn open_object_cannot_open_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("test");
+    let reader = fs.open(&dir);
+    assert!(reader.is_err());
+    assert_eq!(reader.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn open_object_read_returns_length<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let mut buf = vec![];
+    let result = reader.read_to_end(&mut buf);
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 9);
+}
+
+fn open_object_reads_chunked<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let mut buf = vec![0; 5];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, b"test ");
+
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"text");
+}
+
+fn open_object_reads_ok_after_file_deleted<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    fs.remove_file(&path).unwrap();
+    // verify file is really gone
+    let result = fs.read(&path);
+    assert!(result.is_err());
+    // check that reader can still read it
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"test text");
+}
+
+fn open_object_reads_ok_after_file_overwritten<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    fs.remove_file(&path).unwrap();
+    fs.write(&path, b"the quick brown fox").unwrap();
+    // check that reader still sees the old contents
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"test text");
+}
+
+fn open_object_reads_ok_after_parent_dir_deleted<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("test");
+    fs.create_dir(&dir).unwrap();
+    let path = dir.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    fs.remove_dir_all(&dir).unwrap();
+    // verify file is really gone
+    let result = fs.read(&path);
+    assert!(result.is_err());
+    // check that reader can still read it
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"test text");
+}
+
+fn open_object_reads_ok_after_file_renamed<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    let renamed_path = parent.join("test.html");
+    fs.rename(&path, &renamed_path).unwrap();
+    // verify file is really renamed
+    let result = fs.read(&path);
+    assert!(result.is_err());
+    let result = fs.read(&renamed_path);
+    assert!(result.is_ok());
+    // check that reader can still read it with the reader
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"test text");
+}
+
+fn open_object_reads_ok_after_parent_dir_renamed<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("test");
+    fs.create_dir(&dir).unwrap();
+    let path = dir.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    let renamed_dir = parent.join("test2");
+    fs.rename(&dir, &renamed_dir).unwrap();
+    // verify file is really gone
+    let result = fs.read(&path);
+    assert!(result.is_err());
+    // check that reader can still read it
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"test text");
+}
+
+fn open_object_reads_ok_after_parent_dir_moved<T: FileSystem>(fs: &T, parent: &Path) {
+    // parent |-> test1 -> test.txt
+    //        |-> test2
+    // after moving test1:
+    // parent |-> test2 -> test1 -> test.txt
+    //
+    let dir1 = parent.join("test1");
+    let dir2 = parent.join("test2");
+    let path = dir1.join("test.txt");
+    fs.create_dir(&dir1).unwrap();
+    fs.create_dir(&dir2).unwrap();
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    fs.rename(&dir1, dir2.join("test1")).unwrap();
+    // verify that original file is gone
+    let result = fs.read(path);
+    assert!(result.is_err());
+    // check that reader can still read the file
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"test text");
+}
+
+fn open_object_reads_ok_beyond_eof<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"the quick brown fox").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+
+    let result = reader.read_to_end(&mut buf);
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 0);
+}
+
+fn open_object_reads_ok_after_file_updated<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    let mut buf = vec![0; 5];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, b"test ");
+
+    fs.write(&path, "the quick brown fox").unwrap();
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"uick brown fox");
+}
+
+fn open_object_sees_file_truncated_by_create<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let mut writer = fs.create(&path).unwrap();
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"");
+
+    writer.write_all(b"new text").unwrap();
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"new text");
+}
+
+fn open_object_sees_write_through_other_handle<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let mut writer = fs.open_with_options(&path, &OpenOptions::new().write(true)).unwrap();
+    writer.write_all(b"best").unwrap();
+
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"best text");
+}
+
+fn open_object_sees_append<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+
+    fs.append(&path, b", more").unwrap();
+
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b", more");
+}
+
+fn open_object_sees_copy_onto_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let other = parent.join("other.txt");
+    fs.write(&path, b"test text").unwrap();
+    fs.write(&other, b"other text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    fs.copy_file(&other, &path).unwrap();
+
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"other text");
+}
+
+fn open_object_keeps_contents_after_rename_onto_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let other = parent.join("other.txt");
+    fs.write(&path, b"test text").unwrap();
+    fs.write(&other, b"other text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    fs.rename(&other, &path).unwrap();
+
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"test text");
+    assert_eq!(fs.read(&path).unwrap(), b"other text");
+}
+
+fn open_object_reads_ok_after_file_shrunk<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"the quick brown fox").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    let mut buf = vec![0; 10];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, b"the quick ");
+
+    fs.write(&path, "test").unwrap();
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"");
+}
+
+fn open_object_reads_ok_after_file_truncated_by_set_len<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"the quick brown fox!").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+    let mut buf = vec![0; 10];
+    reader.read_exact(&mut buf).unwrap();
+
+    let writer = fs.open_with_options(&path, &OpenOptions::new().write(true)).unwrap();
+    writer.set_len(5).unwrap();
+
+    let mut buf = vec![];
+    assert_eq!(reader.read_to_end(&mut buf).unwrap(), 0);
+
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"the q");
+}
+
+fn open_object_can_seek_from_start_then_read<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"the quick brown fox").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let result = reader.seek(SeekFrom::Start(5));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 5);
+
+    let result = reader.seek(SeekFrom::Start(5));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 5);
+
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"uick brown fox");
+}
+
+fn open_object_can_seek_from_current_then_read<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"the quick brown fox").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let result = reader.seek(SeekFrom::Current(5));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 5);
+
+    let result = reader.seek(SeekFrom::Current(5));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 10);
+
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"brown fox");
+}
+
+fn open_object_can_seek_from_end_then_read<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let msg = b"the quick brown fox";
+    fs.write(&path, msg).unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let result = reader.seek(SeekFrom::End(-5));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap() as usize, msg.len() - 5);
+
+    let result = reader.seek(SeekFrom::End(-5));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap() as usize, msg.len() - 5);
+
+    let mut buf = vec![];
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"n fox");
+}
+
+fn open_object_seeks_from_end_of_file_extended_by_other_handle<T: FileSystem>(
+    fs: &T,
+    parent: &Path,
+) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let mut writer = fs.open_with_options(&path, &OpenOptions::new().append(true)).unwrap();
+    writer.write_all(b" extended!").unwrap();
+    writer.flush().unwrap();
+
+    let result = reader.seek(SeekFrom::End(0));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 19);
+}
+
+fn open_object_fails_if_seeks_before_byte_0<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"the quick brown fox").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    reader.seek(SeekFrom::Start(5)).unwrap();
+
+    let result = reader.seek(SeekFrom::Current(-55));
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    // verify that the error did not change the position
+    let current_pos = reader.stream_position().unwrap();
+    assert_eq!(current_pos, 5);
+}
+
+fn open_object_can_seek_and_read_beyond_eof<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"the quick brown fox").unwrap();
+    let mut reader = fs.open(&path).unwrap();
+
+    let result = reader.seek(SeekFrom::Current(55));
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 55);
+
+    let mut buf = vec![];
+    let result = reader.read_to_end(&mut buf);
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), 0);
+}
+
+fn create_objects_write_independently<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    let mut writers = (fs.create(&path).unwrap(), fs.create(&path).unwrap());
+    let buf = b"the quick brown fox";
+    writers.0.write_all(buf).unwrap();
+    let read_buf1 = fs.read(&path).unwrap();
+    writers.1.write_all(buf).unwrap();
+    let read_buf2 = fs.read(&path).unwrap();
+    assert_eq!(read_buf1, read_buf2);
+}
+
+fn create_object_cannot_overwrite_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("test");
+    fs.create_dir(&dir).unwrap();
+    let writer = fs.create(&dir);
+    assert!(writer.is_err());
+    assert_eq!(writer.unwrap_err().kind(), ErrorKind::IsADirectory);
+}
+
+fn create_object_fails_if_path_ends_in_separator<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test/");
+
+    let result = fs.create(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::IsADirectory);
+    assert!(!fs.exists(parent.join("test")));
+}
+
+fn create_object_fails_if_file_path_ends_in_separator<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test");
+    create_file(fs, &path, "test text").unwrap();
+
+    let result = fs.create(parent.join("test/"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::IsADirectory);
+    assert_eq!(fs.read_to_string(&path).unwrap(), "test text");
+}
+
+fn open_object_fails_if_file_path_ends_in_separator<T: FileSystem>(fs: &T, parent: &Path) {
+    create_file(fs, parent.join("test"), "test text").unwrap();
+
+    let result = fs.open(parent.join("test/"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotADirectory);
+}
+
+fn create_object_writes_chunked<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test").unwrap();
+    writer.write_all(b" text").unwrap();
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"test text");
+}
+
+fn create_object_writes_ok_beyond_eof<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.write(&path, b"").unwrap();
+    writer.write_all(b"test text").unwrap();
+    let buf = fs.read(&path).unwrap();
+    assert_eq!(buf, b"\0\0\0\0\0\0\0\0\0test text");
+}
+
+fn create_object_writes_ok_after_file_deleted<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.remove_file(&path).unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+}
+
+fn create_object_metadata_ok_after_file_deleted<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+    #[cfg(unix)]
+    set_mode(fs, &path, 0o640).unwrap();
+
+    fs.remove_file(&path).unwrap();
+
+    assert_eq!(fs.metadata(&path).unwrap_err().kind(), ErrorKind::NotFound);
+    let metadata = writer.metadata().unwrap();
+    assert!(metadata.is_file());
+    assert_eq!(metadata.len(), 9);
+    #[cfg(unix)]
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+
+    writer.write_all(b" more").unwrap();
+
+    assert_eq!(writer.metadata().unwrap().len(), 14);
+}
+
+fn create_object_writes_ok_after_file_overwritten<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.write(&path, b"the quick brown fox").unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+}
+
+fn create_object_writes_ok_after_parent_dir_deleted<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("test");
+    let path = dir.join("test.txt");
+    fs.create_dir(&dir).unwrap();
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.remove_dir_all(&dir).unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+}
+
+fn create_object_writes_ok_after_file_renamed<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let renamed_path = parent.join("test.html");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.rename(&path, &renamed_path).unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+
+    let contents = fs.read(&renamed_path).unwrap();
+    assert_eq!(contents, b"test texttest text");
+}
+
+fn create_object_writes_ok_after_parent_dir_renamed<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("test");
+    let renamed_dir = parent.join("test2");
+    fs.create_dir(&dir).unwrap();
+    let path = dir.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.rename(&dir, &renamed_dir).unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+
+    let contents = fs.read(renamed_dir.join("test.txt")).unwrap();
+    assert_eq!(contents, b"test texttest text");
+}
+
+fn create_object_writes_ok_after_parent_dir_moved<T: FileSystem>(fs: &T, parent: &Path) {
+    // parent |-> test1 -> test.txt
+    //        |-> test2
+    // after moving test1:
+    // parent |-> test2 -> test1 -> test.txt
+    //
+    let dir1 = parent.join("test1");
+    let dir2 = parent.join("test2");
+    let path = dir1.join("test.txt");
+    fs.create_dir(&dir1).unwrap();
+    fs.create_dir(&dir2).unwrap();
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    let new_root = dir2.join("test1");
+    fs.rename(&dir1, &new_root).unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+
+    let contents = fs.read(new_root.join("test.txt")).unwrap();
+    assert_eq!(contents, b"test texttest text");
+}
+
+fn create_object_writes_ok_after_file_updated_long<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.write(&path, b"the quick brown fox").unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"the quicktest textx");
+}
+
+fn create_object_writes_ok_after_file_updated_short<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.write(&path, b"the quick brown").unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"the quicktest text");
+}
+
+fn create_object_writes_ok_after_file_shrunk<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    fs.write(&path, b"hello").unwrap();
+    let result = writer.write_all(b"test text");
+    assert!(result.is_ok());
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"hello\0\0\0\0test text");
+}
+
+fn create_object_can_seek_then_overwrite<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"the quick brown fox").unwrap();
+
+    writer.seek(SeekFrom::Start(5)).unwrap();
+    let cur = writer.stream_position().unwrap();
+    assert_eq!(cur, 5);
+
+    let result = writer.write_all(b"hello");
+    assert!(result.is_ok());
+
+    let buf = fs.read(&path).unwrap();
+    assert_eq!(buf, b"the qhellobrown fox");
+}
+
+fn create_object_can_seek_then_overwrite_and_extend<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    writer.seek(SeekFrom::Start(5)).unwrap();
+    let cur = writer.stream_position().unwrap();
+    assert_eq!(cur, 5);
+
+    let result = writer.write_all(b"the quick brown fox");
+    assert!(result.is_ok());
+
+    let buf = fs.read(&path).unwrap();
+    assert_eq!(buf, b"test the quick brown fox");
+}
+
+fn create_object_can_seek_then_extend<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    writer.seek(SeekFrom::Start(12)).unwrap();
+    let cur = writer.stream_position().unwrap();
+    assert_eq!(cur, 12);
 
+    let result = writer.write_all(b"test");
     assert!(result.is_ok());
-    assert!(write_file(fs, &path.join("file"), "").is_err());
-
-    let result = set_readonly(fs, &path, false);
 
-    assert!(result.is_ok());
-    assert!(write_file(fs, &path.join("file"), "").is_ok());
+    let buf = fs.read(&path).unwrap();
+    assert_eq!(buf, b"test text\0\0\0test");
 }
 
-fn set_readonly_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
-    let result = set_readonly(fs, parent.join("does_not_exist"), true);
+fn open_object_cannot_write<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    create_file(fs, &path, vec![]).unwrap();
 
+    let mut reader = fs.open(&path).unwrap();
+    let result = reader.write(b"the quick brown fox");
+    // the kind of error depends on the backend, see the *_access_mode_errors_are_* tests
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
 
-    let result = set_readonly(fs, parent.join("does_not_exist"), true);
+fn create_object_cannot_read<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
 
+    let mut writer = fs.create(&path).unwrap();
+    let mut buf = vec![];
+    let result = writer.read_to_end(&mut buf);
+    // the kind of error depends on the backend, see the *_access_mode_errors_are_* tests
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn len_returns_size_of_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("file");
-    let result = create_file(fs, &path, "");
-
-    assert!(result.is_ok());
-
-    let len = fs.open(&path).unwrap().metadata().unwrap().len();
-
-    assert_eq!(len, 0);
+fn file_operations_are_allowed_by_access_mode<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    // (options, can read, can write)
+    let all_options = [
+        (OpenOptions::new().read(true), true, false),
+        (OpenOptions::new().write(true), false, true),
+        (OpenOptions::new().append(true), false, true),
+        (OpenOptions::new().read(true).write(true), true, true),
+        (OpenOptions::new().read(true).append(true), true, true),
+    ];
+    for (options, can_read, can_write) in all_options.iter() {
+        fs.write(&path, "test text").unwrap();
+        let mut file = fs.open_with_options(&path, options).unwrap();
+
+        let mut buf = [0; 4];
+        assert_eq!(file.read(&mut buf).is_ok(), *can_read, "read {:?}", options);
+        assert_eq!(file.write(b"more").is_ok(), *can_write, "write {:?}", options);
+        assert_eq!(file.set_len(2).is_ok(), *can_write, "set_len {:?}", options);
+        assert!(file.seek(SeekFrom::Start(0)).is_ok(), "seek {:?}", options);
+    }
+}
 
-    let result = write_file(fs, &path, "contents");
+fn set_len_on_create_object_truncates_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let writer = fs.create(&path).unwrap();
+    fs.write(&path, b"test text").unwrap();
 
+    let result = writer.set_len(4);
     assert!(result.is_ok());
 
-    let len = fs.open(&path).unwrap().metadata().unwrap().len();
-
-    assert_eq!(len, 8);
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"test");
 }
 
-fn open_objects_read_independently<T: FileSystem>(fs: &T, parent: &Path) {
+fn set_len_on_create_object_extends_file<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    let writer = fs.create(&path).unwrap();
+    fs.write(&path, b"test").unwrap();
 
-    let mut readers = (fs.open(&path).unwrap(), fs.open(path).unwrap());
-    let mut bufs = (vec![], vec![]);
-    readers.0.read_to_end(&mut bufs.0).unwrap();
-    readers.1.read_to_end(&mut bufs.1).unwrap();
-    assert_eq!(bufs.0, b"test text");
-    assert_eq!(bufs.1, b"test text");
-}
+    let result = writer.set_len(9);
+    assert!(result.is_ok());
 
-fn open_object_cannot_open_dir<T: FileSystem>(fs: &T, parent: &Path) {
-    let dir = parent.join("test");
-    let reader = fs.open(&dir);
-    assert!(reader.is_err());
-    assert_eq!(reader.unwrap_err().kind(), ErrorKind::NotFound);
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"test\0\0\0\0\0");
 }
 
-fn open_object_read_returns_length<T: FileSystem>(fs: &T, parent: &Path) {
+fn set_len_on_create_object_doesnt_change_cursor<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
+    let mut writer = fs.create(&path).unwrap();
+    fs.write(&path, b"test").unwrap();
 
-    let mut buf = vec![];
-    let result = reader.read_to_end(&mut buf);
+    let result = writer.set_len(9);
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), 9);
+
+    let pos = writer.stream_position().unwrap();
+    assert_eq!(pos, 0);
 }
 
-fn open_object_reads_chunked<T: FileSystem>(fs: &T, parent: &Path) {
+fn set_len_shrink_leaves_nothing_to_read_past_new_end<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
+    fs.write(&path, b"test text").unwrap();
 
-    let mut buf = vec![0; 5];
-    reader.read_exact(&mut buf).unwrap();
-    assert_eq!(buf, b"test ");
+    let options = OpenOptions::new().read(true).write(true);
+    let mut file = fs.open_with_options(&path, &options).unwrap();
+    file.seek(SeekFrom::Start(6)).unwrap();
+    file.set_len(4).unwrap();
 
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"text");
+    let mut buf = [0; 8];
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+    assert_eq!(file.stream_position().unwrap(), 6);
 }
 
-fn open_object_reads_ok_after_file_deleted<T: FileSystem>(fs: &T, parent: &Path) {
+fn set_len_extend_reads_gap_as_zeros<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-    fs.remove_file(&path).unwrap();
-    // verify file is really gone
-    let result = read_file(fs, &path);
-    assert!(result.is_err());
-    // check that reader can still read it
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"test text");
+    fs.write(&path, b"test").unwrap();
+
+    let options = OpenOptions::new().read(true).write(true);
+    let mut file = fs.open_with_options(&path, &options).unwrap();
+    file.set_len(9).unwrap();
+
+    let mut contents = vec![];
+    file.read_to_end(&mut contents).unwrap();
+    assert_eq!(contents, b"test\0\0\0\0\0");
 }
 
-fn open_object_reads_ok_after_file_overwritten<T: FileSystem>(fs: &T, parent: &Path) {
+fn has_changed_since_open_after_write_through_path<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-    fs.remove_file(&path).unwrap();
-    write_file(fs, &path, b"the quick brown fox").unwrap();
-    // check that reader still sees the old contents
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"test text");
-}
+    let untouched = parent.join("untouched.txt");
+    fs.write(&path, "test text").unwrap();
+    fs.write(&untouched, "test text").unwrap();
 
-fn open_object_reads_ok_after_parent_dir_deleted<T: FileSystem>(fs: &T, parent: &Path) {
-    let dir = parent.join("test");
-    fs.create_dir(&dir).unwrap();
-    let path = dir.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-    fs.remove_dir_all(&dir).unwrap();
-    // verify file is really gone
-    let result = read_file(fs, &path);
-    assert!(result.is_err());
-    // check that reader can still read it
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"test text");
+    let reader = fs.open(&path).unwrap();
+    let untouched_reader = fs.open(&untouched).unwrap();
+    assert!(!reader.has_changed_since_open().unwrap());
+
+    fs.write(&path, "new text").unwrap();
+
+    assert!(reader.has_changed_since_open().unwrap());
+    assert!(reader.try_clone().unwrap().has_changed_since_open().unwrap());
+    assert!(!untouched_reader.has_changed_since_open().unwrap());
 }
 
-fn open_object_reads_ok_after_file_renamed<T: FileSystem>(fs: &T, parent: &Path) {
+fn has_changed_since_open_after_write_through_other_handle<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-    let renamed_path = parent.join("test.html");
-    fs.rename(&path, &renamed_path).unwrap();
-    // verify file is really renamed
-    let result = read_file(fs, &path);
-    assert!(result.is_err());
-    let result = read_file(fs, &renamed_path);
-    assert!(result.is_ok());
-    // check that reader can still read it with the reader
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"test text");
-}
+    fs.write(&path, "test text").unwrap();
 
-fn open_object_reads_ok_after_parent_dir_renamed<T: FileSystem>(fs: &T, parent: &Path) {
-    let dir = parent.join("test");
-    fs.create_dir(&dir).unwrap();
-    let path = dir.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-    let renamed_dir = parent.join("test2");
-    fs.rename(&dir, &renamed_dir).unwrap();
-    // verify file is really gone
-    let result = read_file(fs, &path);
-    assert!(result.is_err());
-    // check that reader can still read it
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"test text");
+    let reader = fs.open(&path).unwrap();
+    let writer = fs.open_with_options(&path, &OpenOptions::new().write(true)).unwrap();
+    writer.set_len(4).unwrap();
+
+    assert!(reader.has_changed_since_open().unwrap());
 }
 
-fn open_object_reads_ok_after_parent_dir_moved<T: FileSystem>(fs: &T, parent: &Path) {
-    // parent |-> test1 -> test.txt
-    //        |-> test2
-    // after moving test1:
-    // parent |-> test2 -> test1 -> test.txt
-    //
-    let dir1 = parent.join("test1");
-    let dir2 = parent.join("test2");
-    let path = dir1.join("test.txt");
-    fs.create_dir(&dir1).unwrap();
-    fs.create_dir(&dir2).unwrap();
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
+fn try_clone_shares_contents<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let mut writer = fs.create(&path).unwrap();
+    let clone = writer.try_clone().unwrap();
 
-    fs.rename(&dir1, dir2.join("test1")).unwrap();
-    // verify that original file is gone
-    let result = read_file(fs, path);
-    assert!(result.is_err());
-    // check that reader can still read the file
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"test text");
+    writer.write_all(b"test text").unwrap();
+
+    assert_eq!(clone.metadata().unwrap().len(), 9);
+    assert_eq!(fs.read_to_string(&path).unwrap(), "test text");
 }
 
-fn open_object_reads_ok_beyond_eof<T: FileSystem>(fs: &T, parent: &Path) {
+#[cfg(unix)]
+fn read_at_reads_at_offset_without_moving_cursor<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"the quick brown fox").unwrap();
+    fs.write(&path, "test text").unwrap();
     let mut reader = fs.open(&path).unwrap();
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
 
-    let result = reader.read_to_end(&mut buf);
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), 0);
+    let mut buf = [0; 4];
+    let result = reader.read_at(&mut buf, 5);
+
+    assert_eq!(result.unwrap(), 4);
+    assert_eq!(&buf, b"text");
+    assert_eq!(reader.stream_position().unwrap(), 0);
 }
 
-fn open_object_reads_ok_after_file_updated<T: FileSystem>(fs: &T, parent: &Path) {
+#[cfg(unix)]
+fn write_at_writes_at_offset_without_moving_cursor<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-    let mut buf = vec![0; 5];
-    reader.read_exact(&mut buf).unwrap();
-    assert_eq!(buf, b"test ");
+    fs.write(&path, "test text").unwrap();
+    let mut writer = fs.open_with_options(&path, &OpenOptions::new().write(true)).unwrap();
 
-    write_file(fs, &path, "the quick brown fox").unwrap();
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"uick brown fox");
+    let result = writer.write_at(b"next", 5);
+
+    assert_eq!(result.unwrap(), 4);
+    assert_eq!(writer.stream_position().unwrap(), 0);
+    assert_eq!(fs.read_to_string(&path).unwrap(), "test next");
 }
 
-fn open_object_reads_ok_after_file_shrunk<T: FileSystem>(fs: &T, parent: &Path) {
+#[cfg(unix)]
+fn write_at_beyond_eof_fills_gap_with_zeros<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"the quick brown fox").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-    let mut buf = vec![0; 10];
-    reader.read_exact(&mut buf).unwrap();
-    assert_eq!(buf, b"the quick ");
+    let writer = fs.create(&path).unwrap();
 
-    write_file(fs, &path, "test").unwrap();
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"");
+    writer.write_at(b"text", 3).unwrap();
+
+    assert_eq!(fs.read(&path).unwrap(), b"\0\0\0text");
 }
 
-fn open_object_can_seek_from_start_then_read<T: FileSystem>(fs: &T, parent: &Path) {
+#[cfg(unix)]
+fn read_at_fails_if_not_opened_for_reading<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"the quick brown fox").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-
-    let result = reader.seek(SeekFrom::Start(5));
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), 5);
+    let writer = fs.create(&path).unwrap();
 
-    let result = reader.seek(SeekFrom::Start(5));
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), 5);
+    let mut buf = [0; 4];
+    let result = writer.read_at(&mut buf, 0);
 
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"uick brown fox");
+    assert!(result.is_err());
 }
 
-fn open_object_can_seek_from_current_then_read<T: FileSystem>(fs: &T, parent: &Path) {
+fn set_len_on_create_object_succeeds_after_file_made_readonly<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"the quick brown fox").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-
-    let result = reader.seek(SeekFrom::Current(5));
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), 5);
+    let writer = fs.create(&path).unwrap();
+    fs.write(&path, b"test text").unwrap();
+    set_readonly(fs, &path, true).unwrap();
 
-    let result = reader.seek(SeekFrom::Current(5));
+    let result = writer.set_len(4);
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), 10);
 
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"brown fox");
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"test");
 }
 
-fn open_object_can_seek_from_end_then_read<T: FileSystem>(fs: &T, parent: &Path) {
+fn fs_set_len_truncates_file<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let msg = b"the quick brown fox";
-    write_file(fs, &path, msg).unwrap();
-    let mut reader = fs.open(&path).unwrap();
+    fs.write(&path, b"test text").unwrap();
 
-    let result = reader.seek(SeekFrom::End(-5));
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap() as usize, msg.len() - 5);
-
-    let result = reader.seek(SeekFrom::End(-5));
+    let result = fs.set_len(&path, 4);
     assert!(result.is_ok());
-    assert_eq!(result.unwrap() as usize, msg.len() - 5);
 
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf).unwrap();
-    assert_eq!(buf, b"n fox");
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"test");
 }
 
-fn open_object_fails_if_seeks_before_byte_0<T: FileSystem>(fs: &T, parent: &Path) {
+fn fs_set_len_fails_if_file_is_readonly<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"the quick brown fox").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-
-    reader.seek(SeekFrom::Start(5)).unwrap();
+    fs.write(&path, b"test text").unwrap();
+    set_readonly(fs, &path, true).unwrap();
 
-    let result = reader.seek(SeekFrom::Current(-55));
+    let result = fs.set_len(&path, 4);
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
 
-    // verify that the error did not change the position
-    let current_pos = reader.seek(SeekFrom::Current(0)).unwrap();
-    assert_eq!(current_pos, 5);
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"test text");
 }
 
-fn open_object_can_seek_and_read_beyond_eof<T: FileSystem>(fs: &T, parent: &Path) {
+fn fs_set_len_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"the quick brown fox").unwrap();
-    let mut reader = fs.open(&path).unwrap();
-
-    let result = reader.seek(SeekFrom::Current(55));
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), 55);
-
-    let mut buf = vec![];
-    let result = reader.read_to_end(&mut buf);
-    assert!(result.is_ok());
-    assert_eq!(result.unwrap(), 0);
+
+    let result = fs.set_len(&path, 4);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn create_objects_write_independently<T: FileSystem>(fs: &T, parent: &Path) {
+fn change_token_changes_after_write<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+    let token = fs.change_token(&path).unwrap();
 
-    let mut writers = (fs.create(&path).unwrap(), fs.create(&path).unwrap());
-    let buf = b"the quick brown fox";
-    writers.0.write_all(buf).unwrap();
-    let read_buf1 = read_file(fs, &path).unwrap();
-    writers.1.write_all(buf).unwrap();
-    let read_buf2 = read_file(fs, &path).unwrap();
-    assert_eq!(read_buf1, read_buf2);
-}
+    let mut writer = fs.open_with_options(&path, &OpenOptions::new().append(true)).unwrap();
+    writer.write_all(b" appended").unwrap();
 
-fn create_object_cannot_overwrite_dir<T: FileSystem>(fs: &T, parent: &Path) {
-    let dir = parent.join("test");
-    fs.create_dir(&dir).unwrap();
-    let writer = fs.create(&dir);
-    assert!(writer.is_err());
-    assert_eq!(writer.unwrap_err().kind(), ErrorKind::Other);
+    assert_ne!(fs.change_token(&path).unwrap(), token);
 }
 
-fn create_object_writes_chunked<T: FileSystem>(fs: &T, parent: &Path) {
+fn change_token_is_stable_across_reads<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test").unwrap();
-    writer.write_all(b" text").unwrap();
-    let contents = read_file(fs, &path).unwrap();
-    assert_eq!(contents, b"test text");
+    fs.write(&path, "test text").unwrap();
+    let token = fs.change_token(&path).unwrap();
+
+    fs.read(&path).unwrap();
+    fs.metadata(&path).unwrap();
+
+    assert_eq!(fs.change_token(&path).unwrap(), token);
 }
 
-fn create_object_writes_ok_beyond_eof<T: FileSystem>(fs: &T, parent: &Path) {
+fn change_token_changes_after_file_replaced<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+    let other = parent.join("other.txt");
+    fs.write(&path, "test text").unwrap();
+    let token = fs.change_token(&path).unwrap();
+    fs.write(&other, "other text").unwrap();
 
-    write_file(fs, &path, b"").unwrap();
-    writer.write_all(b"test text").unwrap();
-    let buf = read_file(fs, &path).unwrap();
-    assert_eq!(buf, b"\0\0\0\0\0\0\0\0\0test text");
+    fs.rename(&other, &path).unwrap();
+
+    assert_ne!(fs.change_token(&path).unwrap(), token);
 }
 
-fn create_object_writes_ok_after_file_deleted<T: FileSystem>(fs: &T, parent: &Path) {
+fn change_token_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.change_token(parent.join("does_not_exist"));
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn write_if_unchanged_writes_if_token_matches<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+    fs.write(&path, "test text").unwrap();
+    let token = fs.change_token(&path).unwrap();
+
+    let result = fs.write_if_unchanged(&path, token, "new text");
 
-    fs.remove_file(&path).unwrap();
-    let result = writer.write_all(b"test text");
     assert!(result.is_ok());
+    assert!(result.unwrap());
+    assert_eq!(fs.read(&path).unwrap(), b"new text");
 }
 
-fn create_object_writes_ok_after_file_overwritten<T: FileSystem>(fs: &T, parent: &Path) {
+fn write_if_unchanged_does_not_write_if_file_changed<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+    fs.write(&path, "test text").unwrap();
+    let token = fs.change_token(&path).unwrap();
+    fs.write(&path, "other writer's text").unwrap();
+
+    let result = fs.write_if_unchanged(&path, token, "new text");
 
-    write_file(fs, &path, b"the quick brown fox").unwrap();
-    let result = writer.write_all(b"test text");
     assert!(result.is_ok());
+    assert!(!result.unwrap());
+    assert_eq!(fs.read(&path).unwrap(), b"other writer's text");
 }
 
-fn create_object_writes_ok_after_parent_dir_deleted<T: FileSystem>(fs: &T, parent: &Path) {
-    let dir = parent.join("test");
-    let path = dir.join("test.txt");
-    fs.create_dir(&dir).unwrap();
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+fn write_if_unchanged_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("does_not_exist");
 
-    fs.remove_dir_all(&dir).unwrap();
-    let result = writer.write_all(b"test text");
-    assert!(result.is_ok());
+    let result = fs.write_if_unchanged(&path, 0, "new text");
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(!fs.is_file(&path));
 }
 
-fn create_object_writes_ok_after_file_renamed<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    let renamed_path = parent.join("test.html");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+fn write_batch_writes_all_files<T: FileSystem>(fs: &T, parent: &Path) {
+    let existing = parent.join("existing.txt");
+    let new = parent.join("new.txt");
+    fs.write(&existing, "old text").unwrap();
 
-    fs.rename(&path, &renamed_path).unwrap();
-    let result = writer.write_all(b"test text");
-    assert!(result.is_ok());
+    let result = fs.write_batch(&[
+        (existing.clone(), b"new text".to_vec()),
+        (new.clone(), b"more text".to_vec()),
+    ]);
 
-    let contents = read_file(fs, &renamed_path).unwrap();
-    assert_eq!(contents, b"test texttest text");
+    assert!(result.is_ok());
+    assert_eq!(fs.read_to_string(&existing).unwrap(), "new text");
+    assert_eq!(fs.read_to_string(&new).unwrap(), "more text");
 }
 
-fn create_object_writes_ok_after_parent_dir_renamed<T: FileSystem>(fs: &T, parent: &Path) {
-    let dir = parent.join("test");
-    let renamed_dir = parent.join("test2");
-    fs.create_dir(&dir).unwrap();
-    let path = dir.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+// long enough for timestamps to tick even on file systems which
+// only record them in steps of 2 s, such as FAT
+const TIMESTAMP_TICK: Duration = Duration::from_secs(2);
 
-    fs.rename(&dir, &renamed_dir).unwrap();
-    let result = writer.write_all(b"test text");
-    assert!(result.is_ok());
+fn created_is_unchanged_by_write<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+    let created = fs.metadata(&path).unwrap().created();
 
-    let contents = read_file(fs, renamed_dir.join("test.txt")).unwrap();
-    assert_eq!(contents, b"test texttest text");
+    thread::sleep(TIMESTAMP_TICK);
+    fs.write(&path, "new text").unwrap();
+
+    if let Ok(created) = created {
+        assert_eq!(fs.metadata(&path).unwrap().created().unwrap(), created);
+    }
 }
 
-fn create_object_writes_ok_after_parent_dir_moved<T: FileSystem>(fs: &T, parent: &Path) {
-    // parent |-> test1 -> test.txt
-    //        |-> test2
-    // after moving test1:
-    // parent |-> test2 -> test1 -> test.txt
-    //
-    let dir1 = parent.join("test1");
-    let dir2 = parent.join("test2");
-    let path = dir1.join("test.txt");
-    fs.create_dir(&dir1).unwrap();
-    fs.create_dir(&dir2).unwrap();
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+fn modified_is_updated_by_write<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+    let past = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    let mut writer = fs.open_with_options(&path, &OpenOptions::new().write(true)).unwrap();
+    writer.set_times(FileTimes::new().set_modified(past)).unwrap();
 
-    let new_root = dir2.join("test1");
-    fs.rename(&dir1, &new_root).unwrap();
-    let result = writer.write_all(b"test text");
-    assert!(result.is_ok());
+    writer.write_all(b"new").unwrap();
 
-    let contents = read_file(fs, new_root.join("test.txt")).unwrap();
-    assert_eq!(contents, b"test texttest text");
+    assert!(fs.metadata(&path).unwrap().modified().unwrap() > past);
 }
 
-fn create_object_writes_ok_after_file_updated_long<T: FileSystem>(fs: &T, parent: &Path) {
+fn set_times_sets_modified_in_the_past<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+    fs.write(&path, "test text").unwrap();
+    let past = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
 
-    write_file(fs, &path, b"the quick brown fox").unwrap();
-    let result = writer.write_all(b"test text");
-    assert!(result.is_ok());
+    let file = fs.open(&path).unwrap();
+    file.set_times(FileTimes::new().set_accessed(past).set_modified(past)).unwrap();
 
-    let contents = read_file(fs, &path).unwrap();
-    assert_eq!(contents, b"the quicktest textx");
+    let md = fs.metadata(&path).unwrap();
+    assert_eq!(md.modified().unwrap(), past);
+    assert_eq!(md.accessed().unwrap(), past);
 }
 
-fn create_object_writes_ok_after_file_updated_short<T: FileSystem>(fs: &T, parent: &Path) {
+fn set_times_leaves_unset_times_unchanged<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+    fs.write(&path, "test text").unwrap();
+    let past = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
 
-    write_file(fs, &path, b"the quick brown").unwrap();
-    let result = writer.write_all(b"test text");
-    assert!(result.is_ok());
+    let file = fs.open(&path).unwrap();
+    let accessed = file.metadata().unwrap().accessed().unwrap();
+    file.set_times(FileTimes::new().set_modified(past)).unwrap();
 
-    let contents = read_file(fs, &path).unwrap();
-    assert_eq!(contents, b"the quicktest text");
+    let md = fs.metadata(&path).unwrap();
+    assert_eq!(md.modified().unwrap(), past);
+    assert_eq!(md.accessed().unwrap(), accessed);
 }
 
-fn create_object_writes_ok_after_file_shrunk<T: FileSystem>(fs: &T, parent: &Path) {
+fn sig_changes_with_modified_and_len<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+    fs.write(&path, "test text").unwrap();
+    let past = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    fs.open(&path).unwrap().set_times(FileTimes::new().set_modified(past)).unwrap();
 
-    write_file(fs, &path, b"hello").unwrap();
-    let result = writer.write_all(b"test text");
-    assert!(result.is_ok());
+    let sig = fs.sig(&path).unwrap();
+    assert_eq!(sig, (9, past));
 
-    let contents = read_file(fs, &path).unwrap();
-    assert_eq!(contents, b"hello\0\0\0\0test text");
+    let earlier = past - Duration::from_secs(60);
+    fs.open(&path).unwrap().set_times(FileTimes::new().set_modified(earlier)).unwrap();
+    let touched = fs.sig(&path).unwrap();
+    assert_eq!(touched.0, sig.0);
+    assert_ne!(touched.1, sig.1);
+
+    fs.write(&path, "longer text").unwrap();
+    let changed = fs.sig(&path).unwrap();
+    assert_ne!(changed.0, touched.0);
+    assert_ne!(changed.1, touched.1);
 }
 
-fn create_object_can_seek_then_overwrite<T: FileSystem>(fs: &T, parent: &Path) {
+fn fs_dir_metadata_is_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test");
+    fs.create_dir(&path).unwrap();
+
+    let md = fs.metadata(&path).unwrap();
+    assert!(!md.is_file());
+    assert!(md.is_dir());
+}
+
+fn fs_dir_metadata_has_correct_len<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"the quick brown fox").unwrap();
+    fs.create_dir(&path).unwrap();
 
-    writer.seek(SeekFrom::Start(5)).unwrap();
-    let cur = writer.seek(SeekFrom::Current(0)).unwrap();
-    assert_eq!(cur, 5);
+    let md = fs.metadata(&path).unwrap();
+    // to keep things portable, don't test for a particular value
+    assert_ne!(md.len(), 0);
+}
 
-    let result = writer.write_all(b"hello");
-    assert!(result.is_ok());
+#[cfg(unix)]
+fn metadata_kind_matches_node<T: FileSystem>(fs: &T, parent: &Path) {
+    let file = parent.join("file.txt");
+    let dir = parent.join("dir");
+    let link = parent.join("link");
+    fs.write(&file, "test text").unwrap();
+    fs.create_dir(&dir).unwrap();
+    fs.symlink(&file, &link).unwrap();
 
-    let buf = read_file(fs, &path).unwrap();
-    assert_eq!(buf, b"the qhellobrown fox");
+    assert_eq!(fs.metadata(&file).unwrap().kind(), NodeKind::File);
+    assert_eq!(fs.metadata(&dir).unwrap().kind(), NodeKind::Dir);
+    assert_eq!(fs.symlink_metadata(&link).unwrap().kind(), NodeKind::Symlink);
+    assert_eq!(fs.metadata(&link).unwrap().kind(), NodeKind::File);
 }
 
-fn create_object_can_seek_then_overwrite_and_extend<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+#[cfg(unix)]
+fn symlink_metadata_len_is_length_of_target_path<T: FileSystem>(fs: &T, parent: &Path) {
+    let target = parent.join("target.txt");
+    fs.write(&target, "0123456789abcdef").unwrap();
+    let link = parent.join("link");
+    fs.symlink(&target, &link).unwrap();
 
-    writer.seek(SeekFrom::Start(5)).unwrap();
-    let cur = writer.seek(SeekFrom::Current(0)).unwrap();
-    assert_eq!(cur, 5);
+    let metadata = fs.symlink_metadata(&link).unwrap();
+    assert!(metadata.is_symlink());
+    assert!(!metadata.is_file());
+    assert!(!metadata.is_dir());
+    assert_eq!(metadata.len(), target.as_os_str().len() as u64);
 
-    let result = writer.write_all(b"the quick brown fox");
-    assert!(result.is_ok());
+    let metadata = fs.metadata(&link).unwrap();
+    assert!(!metadata.is_symlink());
+    assert!(metadata.is_file());
+    assert_eq!(metadata.len(), 16);
+}
 
-    let buf = read_file(fs, &path).unwrap();
-    assert_eq!(buf, b"test the quick brown fox");
+#[cfg(unix)]
+fn symlink_metadata_of_dangling_symlink_succeeds<T: FileSystem>(fs: &T, parent: &Path) {
+    let link = parent.join("link");
+    fs.symlink("/some/path", &link).unwrap();
+
+    let metadata = fs.symlink_metadata(&link).unwrap();
+    assert!(metadata.is_symlink());
+    assert_eq!(metadata.len(), "/some/path".len() as u64);
+
+    let result = fs.metadata(&link);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn create_object_can_seek_then_extend<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    writer.write_all(b"test text").unwrap();
+fn file_type_reports_files_and_dirs<T: FileSystem>(fs: &T, parent: &Path) {
+    let file = parent.join("file");
+    let dir = parent.join("dir");
+    create_file(fs, &file, "").unwrap();
+    fs.create_dir(&dir).unwrap();
 
-    writer.seek(SeekFrom::Start(12)).unwrap();
-    let cur = writer.seek(SeekFrom::Current(0)).unwrap();
-    assert_eq!(cur, 12);
+    assert_eq!(fs.file_type(&file).unwrap(), NodeKind::File);
+    assert_eq!(fs.file_type(&dir).unwrap(), NodeKind::Dir);
+}
 
-    let result = writer.write_all(b"test");
-    assert!(result.is_ok());
+#[cfg(unix)]
+fn file_type_reports_symlink_rather_than_target<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    let link = parent.join("link");
+    let dangling = parent.join("dangling");
+    fs.create_dir(&dir).unwrap();
+    fs.symlink(&dir, &link).unwrap();
+    fs.symlink("/some/path", &dangling).unwrap();
 
-    let buf = read_file(fs, &path).unwrap();
-    assert_eq!(buf, b"test text\0\0\0test");
+    let file_type = fs.file_type(&link).unwrap();
+    assert!(file_type.is_symlink());
+    assert!(!file_type.is_dir());
+    assert_eq!(fs.file_type(&dangling).unwrap(), NodeKind::Symlink);
 }
 
-fn open_object_cannot_write<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    create_file(fs, &path, vec![]).unwrap();
+fn file_type_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.file_type(parent.join("does_not_exist"));
 
-    let mut reader = fs.open(&path).unwrap();
-    let result = reader.write(b"the quick brown fox");
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
-fn create_object_cannot_read<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
+#[cfg(unix)]
+fn create_new_fails_if_node_is_dangling_symlink<T: FileSystem>(fs: &T, parent: &Path) {
+    let target = parent.join("target.txt");
+    let link = parent.join("link");
+    fs.symlink(&target, &link).unwrap();
 
-    let mut writer = fs.create(&path).unwrap();
-    let mut buf = vec![];
-    let result = writer.read_to_end(&mut buf);
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().kind(), ErrorKind::Other);
+    let result = fs.open_with_options(&link, &OpenOptions::new().write(true).create_new(true));
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert!(!fs.exists(&target));
 }
 
-fn set_len_on_create_object_truncates_file<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test.txt");
-    let writer = fs.create(&path).unwrap();
-    write_file(fs, &path, b"test text").unwrap();
+#[cfg(unix)]
+fn symlink_can_be_opened<T: FileSystem>(fs: &T, parent: &Path) {
+    let target = parent.join("target.txt");
+    fs.write(&target, "test text").unwrap();
+    let link = parent.join("link");
+    fs.symlink("target.txt", &link).unwrap();
 
-    let result = writer.set_len(4);
-    assert!(result.is_ok());
+    assert!(fs.is_file(&link));
+    assert_eq!(fs.read(&link).unwrap(), b"test text");
 
-    let contents = read_file(fs, &path).unwrap();
-    assert_eq!(contents, b"test");
+    fs.write(&link, "new text").unwrap();
+    assert_eq!(fs.read(&target).unwrap(), b"new text");
 }
 
-fn set_len_on_create_object_extends_file<T: FileSystem>(fs: &T, parent: &Path) {
+#[cfg(unix)]
+fn symlink_to_dir_is_followed_in_path<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+    let link = parent.join("link");
+    fs.symlink(&dir, &link).unwrap();
+
+    fs.write(link.join("test.txt"), "test text").unwrap();
+
+    assert!(fs.is_dir(&link));
+    assert_eq!(fs.read(dir.join("test.txt")).unwrap(), b"test text");
+}
+
+#[cfg(unix)]
+fn symlink_target_may_lead_to_parent_dir<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+    fs.write(parent.join("test.txt"), "test text").unwrap();
+    fs.symlink("../test.txt", dir.join("file_link")).unwrap();
+    fs.symlink("./../dir/..", dir.join("dir_link")).unwrap();
+
+    assert_eq!(fs.read(dir.join("file_link")).unwrap(), b"test text");
+    assert!(fs.is_dir(dir.join("dir_link")));
+    assert!(fs.is_file(dir.join("dir_link").join("test.txt")));
+}
+
+#[cfg(target_os = "linux")]
+fn symlink_loop_fails_with_eloop<T: FileSystem>(fs: &T, parent: &Path) {
+    let first = parent.join("first");
+    let second = parent.join("second");
+    fs.symlink(&second, &first).unwrap();
+    fs.symlink(&first, &second).unwrap();
+
+    let result = fs.read(&first);
+
+    assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::ELOOP));
+    assert!(!fs.exists(&first));
+    assert!(fs.try_exists(&first).is_err());
+}
+
+#[cfg(unix)]
+fn symlink_fails_if_link_already_exists<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let writer = fs.create(&path).unwrap();
-    write_file(fs, &path, b"test").unwrap();
+    fs.write(&path, "test text").unwrap();
 
-    let result = writer.set_len(9);
+    let result = fs.symlink("/some/path", &path);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+}
+
+#[cfg(unix)]
+fn read_link_returns_unresolved_target<T: FileSystem>(fs: &T, parent: &Path) {
+    fs.create_dir(parent.join("dir")).unwrap();
+    let link = parent.join("link");
+    fs.symlink("dir/../missing.txt", &link).unwrap();
+
+    let result = fs.read_link(&link);
     assert!(result.is_ok());
+    assert_eq!(result.unwrap(), PathBuf::from("dir/../missing.txt"));
+}
 
-    let contents = read_file(fs, &path).unwrap();
-    assert_eq!(contents, b"test\0\0\0\0\0");
+#[cfg(unix)]
+fn read_link_does_not_follow_chain<T: FileSystem>(fs: &T, parent: &Path) {
+    let first = parent.join("first");
+    let second = parent.join("second");
+    fs.symlink("/some/path", &second).unwrap();
+    fs.symlink(&second, &first).unwrap();
+
+    assert_eq!(fs.read_link(&first).unwrap(), second);
+    assert_eq!(fs.read_link(&second).unwrap(), PathBuf::from("/some/path"));
 }
 
-fn set_len_on_create_object_doesnt_change_cursor<T: FileSystem>(fs: &T, parent: &Path) {
+fn read_link_fails_if_node_is_a_file<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    let mut writer = fs.create(&path).unwrap();
-    write_file(fs, &path, b"test").unwrap();
+    fs.write(&path, "test text").unwrap();
 
-    let result = writer.set_len(9);
-    assert!(result.is_ok());
+    let result = fs.read_link(&path);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+}
 
-    let pos = writer.seek(SeekFrom::Current(0)).unwrap();
-    assert_eq!(pos, 0);
+fn read_link_fails_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.read_link(parent);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
 }
 
-fn fs_dir_metadata_is_dir<T: FileSystem>(fs: &T, parent: &Path) {
-    let path = parent.join("test");
-    fs.create_dir(&path).unwrap();
+fn read_link_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.read_link(parent.join("does_not_exist"));
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+#[cfg(unix)]
+fn set_len_on_create_object_makes_file_sparse<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let f = fs.create(&path).unwrap();
+    f.set_len(1024 * 1024).unwrap();
 
-    let md = fs.metadata(&path).unwrap();
-    assert!(!md.is_file());
-    assert!(md.is_dir());
+    assert!(fs.metadata(&path).unwrap().is_sparse());
+    assert!(f.metadata().unwrap().is_sparse());
 }
 
-fn fs_dir_metadata_has_correct_len<T: FileSystem>(fs: &T, parent: &Path) {
+#[cfg(unix)]
+fn fully_written_file_is_not_sparse<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    fs.create_dir(&path).unwrap();
+    let mut f = fs.create(&path).unwrap();
+    f.write_all(&[0x55; 64 * 1024]).unwrap();
+    f.sync_all().unwrap();
 
-    let md = fs.metadata(&path).unwrap();
-    // to keep things portable, don't test for a particular value
-    assert_ne!(md.len(), 0);
+    assert!(!fs.metadata(&path).unwrap().is_sparse());
+    assert!(!f.metadata().unwrap().is_sparse());
 }
 
 fn fs_file_metadata_is_file<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
 
     let md = fs.metadata(&path).unwrap();
     assert!(md.is_file());
@@ -1811,7 +4873,7 @@ fn fs_file_metadata_is_file<T: FileSystem>(fs: &T, parent: &Path) {
 
 fn fs_file_metadata_has_correct_len<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
 
     let md = fs.metadata(&path).unwrap();
     assert_eq!(md.len(), 9);
@@ -1819,12 +4881,12 @@ fn fs_file_metadata_has_correct_len<T: FileSystem>(fs: &T, parent: &Path) {
 
 fn fs_file_metadata_len_is_immutable<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let md = fs.metadata(&path).unwrap();
 
     assert_eq!(md.len(), 9);
 
-    write_file(fs, &path, b"hi").unwrap();
+    fs.write(&path, b"hi").unwrap();
     assert_eq!(md.len(), 9);
 }
 
@@ -1838,7 +4900,7 @@ fn fs_file_metadata_fails_if_file_doesn_exist<T: FileSystem>(fs: &T, parent: &Pa
 
 fn open_object_metadata_is_file<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let reader = fs.open(&path).unwrap();
 
     let md = reader.metadata().unwrap();
@@ -1848,7 +4910,7 @@ fn open_object_metadata_is_file<T: FileSystem>(fs: &T, parent: &Path) {
 
 fn open_object_metadata_has_correct_len<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let reader = fs.open(&path).unwrap();
 
     let md = reader.metadata().unwrap();
@@ -1857,16 +4919,30 @@ fn open_object_metadata_has_correct_len<T: FileSystem>(fs: &T, parent: &Path) {
 
 fn open_object_metadata_len_is_immutable<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let reader = fs.open(&path).unwrap();
     let md = reader.metadata().unwrap();
 
     assert_eq!(md.len(), 9);
 
-    write_file(fs, &path, b"hi").unwrap();
+    fs.write(&path, b"hi").unwrap();
     assert_eq!(md.len(), 9);
 }
 
+fn open_object_metadata_again_has_current_len<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let reader = fs.open(&path).unwrap();
+
+    assert_eq!(reader.metadata().unwrap().len(), 9);
+
+    fs.append(&path, b" appended").unwrap();
+    assert_eq!(reader.metadata().unwrap().len(), 18);
+
+    fs.write(&path, b"hi").unwrap();
+    assert_eq!(reader.metadata().unwrap().len(), 2);
+}
+
 fn create_object_metadata_is_file<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
     let writer = fs.create(&path).unwrap();
@@ -1911,73 +4987,458 @@ fn writable_object_does_not_create_file<T: FileSystem>(fs: &T, parent: &Path) {
 
 fn writable_object_sets_cursor_to_beginning<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let mut writer = open_writable(fs, &path).unwrap();
-    let pos = writer.seek(SeekFrom::Current(0)).unwrap();
+    let pos = writer.stream_position().unwrap();
     assert_eq!(pos, 0);
 }
 
 fn writable_object_allows_append<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let mut writer = open_writable(fs, &path).unwrap();
     writer.seek(SeekFrom::End(0)).unwrap();
 
     writer.write_all(b"hello").unwrap();
 
-    let contents = read_file(fs, &path).unwrap();
+    let contents = fs.read(&path).unwrap();
     assert_eq!(contents, b"test texthello");
 }
 
 fn writable_object_truncates<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let mut writer = open_writable(fs, &path).unwrap();
     writer.seek(SeekFrom::End(-4)).unwrap();
 
     writer.write_all(b"hello").unwrap();
 
-    let contents = read_file(fs, &path).unwrap();
+    let contents = fs.read(&path).unwrap();
     assert_eq!(String::from_utf8(contents).unwrap(), "test hello");
 }
 
 fn writable_object_allows_write_short<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let mut writer = open_writable(fs, &path).unwrap();
 
     writer.write_all(b"hello").unwrap();
 
-    let contents = read_file(fs, &path).unwrap();
+    let contents = fs.read(&path).unwrap();
     assert_eq!(contents, b"hellotext");
 }
 
 fn writable_object_allows_write_long<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let mut writer = open_writable(fs, &path).unwrap();
 
     writer.write_all(b"the quick brown fox").unwrap();
 
-    let contents = read_file(fs, &path).unwrap();
+    let contents = fs.read(&path).unwrap();
     assert_eq!(contents, b"the quick brown fox");
 }
 
 fn writable_object_extends_file<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, b"test text").unwrap();
+    fs.write(&path, b"test text").unwrap();
     let mut writer = open_writable(fs, &path).unwrap();
 
     writer.seek(SeekFrom::Start(12)).unwrap();
     writer.write_all(b"hi").unwrap();
 
-    let contents = read_file(fs, &path).unwrap();
+    let contents = fs.read(&path).unwrap();
     assert_eq!(contents, b"test text\0\0\0hi");
 }
 
+fn writable_object_seeks_without_read_access<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, b"test text").unwrap();
+    let mut writer = open_writable(fs, &path).unwrap();
+
+    assert_eq!(writer.seek(SeekFrom::End(-4)).unwrap(), 5);
+    writer.write_all(b"T").unwrap();
+    assert_eq!(writer.seek(SeekFrom::Current(-6)).unwrap(), 0);
+    writer.write_all(b"T").unwrap();
+    assert_eq!(writer.seek(SeekFrom::Start(9)).unwrap(), 9);
+    writer.write_all(b"!").unwrap();
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"Test Text!");
+}
+
+fn open_with_options_create_without_truncate_patches_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "abcdefghij").unwrap();
+
+    let options = OpenOptions::new().write(true).create(true);
+    let mut writer = fs.open_with_options(&path, &options).unwrap();
+    writer.write_all(b"XYZ").unwrap();
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"XYZdefghij");
+}
+
+fn open_with_options_write_truncate_empties_existing_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "abcdefghij").unwrap();
+
+    let options = OpenOptions::new().write(true).truncate(true);
+    let mut writer = fs.open_with_options(&path, &options).unwrap();
+    writer.write_all(b"XYZ").unwrap();
+
+    assert_eq!(fs.read(&path).unwrap(), b"XYZ");
+}
+
+fn open_with_options_write_truncate_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    let options = OpenOptions::new().write(true).truncate(true);
+    let result = fs.open_with_options(&path, &options);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(!fs.exists(&path));
+
+    let options = options.create(true);
+    assert!(fs.open_with_options(&path, &options).is_ok());
+    assert!(fs.is_file(&path));
+}
+
+#[cfg(unix)]
+fn open_with_options_create_without_truncate_preserves_mode<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "abcdefghij").unwrap();
+    set_mode(fs, &path, 0o600).unwrap();
+    let past = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    fs.open(&path).unwrap().set_times(FileTimes::new().set_modified(past)).unwrap();
+
+    let options = OpenOptions::new().write(true).create(true).mode(0o644);
+    let mut writer = fs.open_with_options(&path, &options).unwrap();
+    writer.write_all(b"XYZ").unwrap();
+
+    assert_eq!(mode(fs, &path).unwrap() & 0o777, 0o600);
+    assert!(fs.metadata(&path).unwrap().modified().unwrap() > past);
+    assert_eq!(fs.read(&path).unwrap(), b"XYZdefghij");
+}
+
+fn open_with_options_fails_for_every_write_mode_if_node_is_a_directory<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_dir");
+    fs.create_dir(&path).unwrap();
+
+    let all_options = [
+        OpenOptions::new().write(true),
+        OpenOptions::new().append(true),
+        OpenOptions::new().create(true).write(true),
+        OpenOptions::new().create(true).append(true),
+        OpenOptions::new().read(true).write(true),
+        OpenOptions::new().write(true).truncate(true),
+    ];
+    for options in all_options.iter() {
+        let result = fs.open_with_options(&path, options);
+        assert!(result.is_err(), "{:?}", options);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::IsADirectory, "{:?}", options);
+    }
+    assert!(fs.is_dir(&path));
+}
+
+fn open_with_options_create_append_creates_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let options = OpenOptions::new().create(true).append(true);
+    let mut writer = fs.open_with_options(&path, &options).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"test text");
+}
+
+fn open_with_options_create_parents_creates_missing_dirs<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("a/b/test.txt");
+    let options = OpenOptions::new().write(true).create(true).create_parents(true);
+    let mut writer = fs.open_with_options(&path, &options).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    assert!(fs.is_dir(parent.join("a/b")));
+    assert_eq!(fs.read(&path).unwrap(), b"test text");
+}
+
+fn open_with_options_accepts_cloexec<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let options = OpenOptions::new().write(true).create(true).cloexec(true);
+    let mut writer = fs.open_with_options(&path, &options).unwrap();
+    writer.write_all(b"test text").unwrap();
+
+    let options = OpenOptions::new().read(true).cloexec(true);
+    let mut contents = String::new();
+    fs.open_with_options(&path, &options).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "test text");
+}
+
+fn open_with_options_fails_without_cloexec<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    let options = OpenOptions::new().write(true).create(true).cloexec(false);
+
+    let result = fs.open_with_options(&path, &options);
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::Unsupported);
+    assert!(!fs.exists(&path));
+}
+
+fn open_with_options_without_create_parents_fails_if_parent_missing<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("a/b/test.txt");
+    let options = OpenOptions::new().write(true).create(true);
+    let result = fs.open_with_options(&path, &options);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(!fs.exists(parent.join("a")));
+}
+
+fn open_with_options_create_append_appends_to_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    let options = OpenOptions::new().create(true).append(true);
+    let mut writer = fs.open_with_options(&path, &options).unwrap();
+    writer.seek(SeekFrom::Start(0)).unwrap();
+    writer.write_all(b" appended").unwrap();
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"test text appended");
+}
+
+fn open_with_options_append_writers_keep_each_others_writes<T: FileSystem>(
+    fs: &T,
+    parent: &Path,
+) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "start").unwrap();
+
+    let options = OpenOptions::new().append(true);
+    let mut writer1 = fs.open_with_options(&path, &options).unwrap();
+    let mut writer2 = fs.open_with_options(&path, &options).unwrap();
+    writer1.write_all(b" one").unwrap();
+    writer2.write_all(b" two").unwrap();
+    writer1.write_all(b" three").unwrap();
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"start one two three");
+}
+
+fn open_with_options_append_writers_racing_keep_all_writes<T: FileSystem>(
+    fs: &T,
+    parent: &Path,
+) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "").unwrap();
+
+    thread::scope(|s| {
+        for chunk in [b"aaaa", b"bbbb"] {
+            let (fs, path) = (fs.clone(), &path);
+            s.spawn(move || {
+                let options = OpenOptions::new().append(true);
+                let mut writer = fs.open_with_options(path, &options).unwrap();
+                for _ in 0..100 {
+                    writer.write_all(chunk).unwrap();
+                }
+            });
+        }
+    });
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents.len(), 800);
+    assert_eq!(contents.iter().filter(|&&b| b == b'a').count(), 400);
+}
+
+fn open_with_options_read_write_can_read_and_write<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    let options = OpenOptions::new().read(true).write(true);
+    let mut file = fs.open_with_options(&path, &options).unwrap();
+    let mut buf = [0; 4];
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"test");
+    file.write_all(b"TEXT").unwrap();
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"testTEXTt");
+}
+
+fn open_with_options_create_new_read_write_starts_empty<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    let options = OpenOptions::new().read(true).write(true).create_new(true);
+    let mut file = fs.open_with_options(&path, &options).unwrap();
+    assert_eq!(file.stream_position().unwrap(), 0);
+    let mut buf = vec![];
+    assert_eq!(file.read_to_end(&mut buf).unwrap(), 0);
+
+    file.write_all(b"test text").unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"test text");
+}
+
+fn open_with_options_read_append_moves_cursor_to_end<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "0123456789").unwrap();
+
+    let options = OpenOptions::new().read(true).append(true);
+    let mut file = fs.open_with_options(&path, &options).unwrap();
+    let mut buf = [0; 3];
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"012");
+    assert_eq!(file.stream_position().unwrap(), 3);
+
+    file.write_all(b"abcde").unwrap();
+    assert_eq!(file.stream_position().unwrap(), 15);
+
+    let mut rest = vec![];
+    assert_eq!(file.read_to_end(&mut rest).unwrap(), 0);
+
+    file.seek(SeekFrom::Start(3)).unwrap();
+    file.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"3456789abcde");
+}
+
+fn open_with_options_read_write_append_reads_anywhere_and_appends<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("records.db");
+    let options = OpenOptions::new().read(true).write(true).append(true).create(true);
+    let mut file = fs.open_with_options(&path, &options).unwrap();
+
+    file.write_all(b"rec1;").unwrap();
+    file.write_all(b"rec2;").unwrap();
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut record = [0; 5];
+    file.read_exact(&mut record).unwrap();
+    assert_eq!(&record, b"rec1;");
+
+    file.write_all(b"rec3;").unwrap();
+    assert_eq!(file.stream_position().unwrap(), 15);
+
+    file.seek(SeekFrom::Start(5)).unwrap();
+    file.read_exact(&mut record).unwrap();
+    assert_eq!(&record, b"rec2;");
+
+    drop(file);
+    let mut file = fs.open_with_options(&path, &options).unwrap();
+    file.write_all(b"rec4;").unwrap();
+
+    let contents = fs.read(&path).unwrap();
+    assert_eq!(contents, b"rec1;rec2;rec3;rec4;");
+}
+
+fn open_with_options_read_write_append_fails_if_file_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("records.db");
+    let options = OpenOptions::new().read(true).write(true).append(true);
+
+    let result = fs.open_with_options(&path, &options);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(!fs.is_file(&path));
+}
+
+fn open_with_options_fails_without_access_mode<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    let result = fs.open_with_options(&path, &OpenOptions::new());
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+fn open_with_options_fails_if_truncate_without_write<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    let options = OpenOptions::new().read(true).truncate(true);
+    let result = fs.open_with_options(&path, &options);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(fs.read(&path).unwrap(), b"test text");
+}
+
+fn open_with_options_fails_if_create_new_without_write<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    let options = OpenOptions::new().read(true).create_new(true);
+    let result = fs.open_with_options(&path, &options);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(!fs.is_file(&path));
+}
+
+fn open_with_options_create_new_fails_if_file_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    let options = OpenOptions::new().write(true).create_new(true);
+    let result = fs.open_with_options(&path, &options);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs.read(&path).unwrap(), b"test text");
+}
+
+fn open_with_options_create_new_fails_if_dir_exists<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test_dir");
+    fs.create_dir(&path).unwrap();
+
+    let options = OpenOptions::new().write(true).create_new(true);
+    let result = fs.open_with_options(&path, &options);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert!(fs.is_dir(&path));
+}
+
+fn open_with_options_fails_if_append_and_truncate<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    let options = OpenOptions::new().append(true).truncate(true);
+    let result = fs.open_with_options(&path, &options);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(unix)]
+fn open_with_options_creates_file_with_mode<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    let options = OpenOptions::new().write(true).create_new(true).mode(0o600);
+    fs.open_with_options(&path, &options).unwrap();
+
+    assert_eq!(mode(fs, &path).unwrap() % 0o100_000, 0o600);
+}
+
+#[cfg(unix)]
+fn open_with_options_can_write_to_new_readonly_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+
+    let options = OpenOptions::new().write(true).create(true).mode(0o400);
+    let mut writer = fs.open_with_options(&path, &options).unwrap();
+    writer.write_all(b"secret").unwrap();
+
+    assert_eq!(mode(fs, &path).unwrap() % 0o100_000, 0o400);
+    assert_eq!(fs.read(&path).unwrap(), b"secret");
+}
+
+#[cfg(unix)]
+fn open_with_options_mode_does_not_affect_existing_file<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    create_file(fs, &path, "test text").unwrap();
+    set_mode(fs, &path, 0o644).unwrap();
+
+    let options = OpenOptions::new().write(true).create(true).mode(0o600);
+    fs.open_with_options(&path, &options).unwrap();
+
+    assert_eq!(mode(fs, &path).unwrap() % 0o100_000, 0o644);
+}
+
 fn canonicalize_ok_if_file_exists<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("test.txt");
-    write_file(fs, &path, "test.txt").unwrap();
+    fs.write(&path, "test.txt").unwrap();
     let result = fs.canonicalize(&path);
     assert!(result.is_ok());
     assert_eq!(result.unwrap(), path);
@@ -2007,8 +5468,8 @@ fn canonicalize_dot_is_current_dir<T: FileSystem>(fs: &T, _parent: &Path) {
 fn canonicalize_ok_if_relative_path<T: FileSystem>(fs: &T, parent: &Path) {
     let save_current_dir = fs.current_dir().unwrap();
 
-    fs.set_current_dir(&parent).unwrap();
-    let result = fs.canonicalize(&PathBuf::from("."));
+    fs.set_current_dir(parent).unwrap();
+    let result = fs.canonicalize(PathBuf::from("."));
     assert!(result.is_ok());
     assert_eq!(result.unwrap(), parent);
 
@@ -2035,7 +5496,7 @@ fn canonicalize_ok_with_dotdot_if_paths_exist<T: FileSystem>(fs: &T, parent: &Pa
     let dir = parent.join("test");
     fs.create_dir(&dir).unwrap();
     let path = dir.join("test.txt");
-    write_file(fs, &path, "test text").unwrap();
+    fs.write(&path, "test text").unwrap();
 
     let dotdot = dir.join("..").join("test").join("test.txt");
     let result = fs.canonicalize(&dotdot);
@@ -2043,11 +5504,23 @@ fn canonicalize_ok_with_dotdot_if_paths_exist<T: FileSystem>(fs: &T, parent: &Pa
     assert_eq!(result.unwrap(), path);
 }
 
+fn canonicalize_ok_with_dot_if_paths_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("test");
+    fs.create_dir(&dir).unwrap();
+    let path = dir.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+
+    let dot = dir.join(".").join("test.txt");
+    let result = fs.canonicalize(&dot);
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), fs.canonicalize(&path).unwrap());
+}
+
 fn canonicalize_fails_with_dotdot_if_path_doesnt_exist<T: FileSystem>(fs: &T, parent: &Path) {
     let dir = parent.join("test");
     fs.create_dir(&dir).unwrap();
     let path = dir.join("test.txt");
-    write_file(fs, &path, "test text").unwrap();
+    fs.write(&path, "test text").unwrap();
 
     let dotdot = dir.join("does_not_exist").join("..").join("test.txt");
     let result = fs.canonicalize(&dotdot);
@@ -2056,20 +5529,74 @@ fn canonicalize_fails_with_dotdot_if_path_doesnt_exist<T: FileSystem>(fs: &T, pa
 
 fn canonicalize_cant_go_lower_than_root<T: FileSystem>(fs: &T, parent: &Path) {
     let num_dirs = parent.iter().count();
-    let dotdot_root: PathBuf = std::iter::repeat("..").take(num_dirs * 2)
+    let dotdot_root: PathBuf = std::iter::repeat_n("..", num_dirs * 2)
                         .collect();
-    let root = parent.iter().nth(0).unwrap();
+    let root = parent.iter().next().unwrap();
     let result = fs.canonicalize(&dotdot_root);
     assert!(result.is_ok());
     assert_eq!(result.unwrap(), root);
 }
 
-#[cfg(not(target_os = "macos"))]
+fn canonicalize_fails_after_file_removed<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "test text").unwrap();
+    assert_eq!(fs.canonicalize(&path).unwrap(), path);
+
+    fs.remove_file(&path).unwrap();
+    let result = fs.canonicalize(&path);
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+fn canonicalize_follows_renamed_ancestor<T: FileSystem>(fs: &T, parent: &Path) {
+    let old = parent.join("old");
+    let new = parent.join("new");
+    fs.create_dir_all(old.join("a")).unwrap();
+    fs.write(old.join("a/test.txt"), "test text").unwrap();
+    assert!(fs.canonicalize(old.join("a/test.txt")).is_ok());
+
+    fs.rename(&old, &new).unwrap();
+
+    assert!(fs.canonicalize(old.join("a/test.txt")).is_err());
+    assert!(fs.canonicalize(old.join("a")).is_err());
+    assert_eq!(fs.canonicalize(new.join("a/test.txt")).unwrap(), new.join("a/test.txt"));
+}
+
+fn canonicalize_fails_after_dotdot_target_removed<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir_all(dir.join("sub")).unwrap();
+    let dotdot = parent.join("dir/sub/..");
+    assert_eq!(fs.canonicalize(&dotdot).unwrap(), dir);
+
+    fs.remove_dir_all(dir.join("sub")).unwrap();
+
+    assert!(fs.canonicalize(&dotdot).is_err());
+}
+
+#[cfg(unix)]
+fn canonicalize_follows_replaced_symlink<T: FileSystem>(fs: &T, parent: &Path) {
+    let first = parent.join("first");
+    let second = parent.join("second");
+    let link = parent.join("link");
+    fs.create_dir(&first).unwrap();
+    fs.create_dir(&second).unwrap();
+    fs.write(first.join("test.txt"), "first").unwrap();
+    fs.write(second.join("test.txt"), "second").unwrap();
+    fs.symlink(&first, &link).unwrap();
+    assert_eq!(fs.canonicalize(link.join("test.txt")).unwrap(), first.join("test.txt"));
+
+    fs.remove_file(&link).unwrap();
+    fs.symlink(&second, &link).unwrap();
+
+    assert_eq!(fs.canonicalize(link.join("test.txt")).unwrap(), second.join("test.txt"));
+}
+
 fn canonicalize_fails_if_subpath_is_file<T: FileSystem>(fs: &T, parent: &Path) {
     let dir = parent.join("test");
     fs.create_dir(&dir).unwrap();
     let path = dir.join("test.txt");
-    write_file(fs, &path, "test text").unwrap();
+    fs.write(&path, "test text").unwrap();
 
     let dotdot = parent.join("test/test.txt/../test.txt");
     let result = fs.canonicalize(&dotdot);
@@ -2077,21 +5604,49 @@ fn canonicalize_fails_if_subpath_is_file<T: FileSystem>(fs: &T, parent: &Path) {
 }
 
 #[cfg(target_os = "macos")]
-fn canonicalize_ok_if_subpath_is_file<T: FileSystem>(fs: &T, parent: &Path) {
+#[test]
+fn os_canonicalize_ok_if_subpath_is_file() {
+    let fs = OsFileSystem::new();
+    let temp_dir = fs.temp_dir("test").unwrap();
+    let parent = fs.canonicalize(temp_dir.path()).unwrap();
     let dir = parent.join("test");
     fs.create_dir(&dir).unwrap();
     let path = dir.join("test.txt");
-    write_file(fs, &path, "content 3").unwrap();
+    fs.write(&path, "content 3").unwrap();
 
     let dotdot = parent.join("test/test.txt/../test.txt");
     let result = fs.canonicalize(&dotdot);
     assert!(result.is_ok());
 
-    let content = read_file(fs, result.unwrap().as_path());
+    let content = fs.read(result.unwrap().as_path());
     assert_eq!(content.unwrap(), b"content 3");
 
 }
 
+fn canonicalize_lenient_matches_canonicalize_for_existing_path<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("test.txt");
+    fs.write(&path, "").unwrap();
+
+    let dotted = parent.join("./test.txt");
+    assert_eq!(fs.canonicalize_lenient(&dotted).unwrap(), fs.canonicalize(&path).unwrap());
+}
+
+fn canonicalize_lenient_appends_missing_final_component<T: FileSystem>(fs: &T, parent: &Path) {
+    let dir = parent.join("dir");
+    fs.create_dir(&dir).unwrap();
+
+    let result = fs.canonicalize_lenient(parent.join("dir/../dir/missing.txt"));
+
+    assert_eq!(result.unwrap(), fs.canonicalize(&dir).unwrap().join("missing.txt"));
+}
+
+fn canonicalize_lenient_fails_if_intermediate_is_missing<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.canonicalize_lenient(parent.join("missing/missing.txt"));
+
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
 #[cfg(unix)]
 fn mode_returns_permissions<T: FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("file");
@@ -2127,6 +5682,41 @@ fn mode_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
     assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
 }
 
+#[cfg(unix)]
+fn chown_keeps_owner_given_as_none<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
+    fs.create(&path).unwrap();
+    let before = fs.metadata(&path).unwrap();
+
+    // changing the owner to someone else requires privileges
+    fs.chown(&path, None, None).unwrap();
+
+    let after = fs.metadata(&path).unwrap();
+    assert_eq!(after.uid(), before.uid());
+    assert_eq!(after.gid(), before.gid());
+}
+
+#[cfg(unix)]
+fn chown_fails_if_node_does_not_exist<T: FileSystem>(fs: &T, parent: &Path) {
+    let result = fs.chown(parent.join("does_not_exist"), Some(0), Some(0));
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+}
+
+#[cfg(unix)]
+fn set_mode_leaves_modified_time_alone<T: FileSystem>(fs: &T, parent: &Path) {
+    let path = parent.join("file");
+    fs.write(&path, "test text").unwrap();
+    let past = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    fs.open(&path).unwrap().set_times(FileTimes::new().set_modified(past)).unwrap();
+
+    set_mode(fs, &path, 0o600).unwrap();
+    assert_eq!(fs.metadata(&path).unwrap().modified().unwrap(), past);
+
+    fs.write(&path, "new text").unwrap();
+    assert!(fs.metadata(&path).unwrap().modified().unwrap() > past);
+}
+
 #[cfg(unix)]
 fn set_mode_sets_permissions<T: FileSystem + FileSystem>(fs: &T, parent: &Path) {
     let path = parent.join("file");
@@ -2142,8 +5732,8 @@ fn set_mode_sets_permissions<T: FileSystem + FileSystem>(fs: &T, parent: &Path)
     assert!(readonly_result.is_ok());
     assert!(readonly_result.unwrap());
 
-    let read_result = read_file(fs, &path);
-    let write_result = write_file(fs, &path, "should not be allowed");
+    let read_result = fs.read(&path);
+    let write_result = fs.write(&path, "should not be allowed");
 
     assert!(read_result.is_err());
     assert!(write_result.is_err());
@@ -2157,8 +5747,8 @@ fn set_mode_sets_permissions<T: FileSystem + FileSystem>(fs: &T, parent: &Path)
 
     assert!(result.is_ok());
 
-    let read_result = read_file(fs, &path);
-    let write_result = write_file(fs, &path, "should be allowed");
+    let read_result = fs.read(&path);
+    let write_result = fs.write(&path, "should be allowed");
 
     assert!(read_result.is_err());
     assert!(write_result.is_ok());